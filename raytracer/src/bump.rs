@@ -0,0 +1,148 @@
+use crate::hit::HitResult;
+use crate::material::*;
+use crate::onb::ONB;
+use std::sync::Arc;
+
+// Orients `normal` to the side of the geometric normal that faces the ray.
+fn face_forward(normal: Vec3, hit_record: &HitResult) -> Vec3 {
+    if normal.clone() * hit_record.normal.clone() < 0.0 {
+        -normal
+    } else {
+        normal
+    }
+}
+
+// Tangent-space normal map: the color of `map` encodes the normal as
+// `2 * rgb - 1` in the (dpdu, dpdv, n) frame; `strength` scales the tilt.
+pub struct NormalMap {
+    pub material: Arc<dyn Material>,
+    pub map: Arc<dyn Texture>,
+    pub strength: f64,
+}
+impl Material for NormalMap {
+    fn eval(&self, hit_record: &HitResult, wo: &Vec3, wi: &Vec3) -> Vec3 {
+        self.material.eval(hit_record, wo, wi)
+    }
+    fn sample(&self, hit_record: &HitResult, wo: &Vec3, u: (f64, f64)) -> Option<ScatterRecord> {
+        self.material.sample(hit_record, wo, u)
+    }
+    fn pdf(&self, hit_record: &HitResult, wo: &Vec3, wi: &Vec3) -> f64 {
+        self.material.pdf(hit_record, wo, wi)
+    }
+    fn emitted(&self, hit_record: &HitResult, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.material.emitted(hit_record, u, v, p)
+    }
+    fn is_cutout(&self, hit_record: &HitResult) -> bool {
+        self.material.is_cutout(hit_record)
+    }
+    fn perturb_normal(&self, hit_record: &mut HitResult) {
+        let color = hit_record.texture_value(&self.map);
+        let local = Vec3::new(
+            (2.0 * color.x - 1.0) * self.strength,
+            (2.0 * color.y - 1.0) * self.strength,
+            (2.0 * color.z - 1.0).max(0.0001),
+        );
+        let frame = ONB::build_from_wu(hit_record.outward_normal(), hit_record.dpdu.clone());
+        let bitangent = if frame.v.clone() * hit_record.dpdv.clone() < 0.0 {
+            -frame.v.clone()
+        } else {
+            frame.v.clone()
+        };
+        let normal = frame.u.clone() * local.x + bitangent * local.y + frame.w.clone() * local.z;
+        hit_record.shading_normal = face_forward(normal.unit(), hit_record);
+        self.material.perturb_normal(hit_record);
+    }
+}
+
+// Bump map: treats the first channel of `height` as a displacement of
+// `scale` along the normal and shades with the normal of the displaced surface.
+pub struct BumpMap {
+    pub material: Arc<dyn Material>,
+    pub height: Arc<dyn Texture>,
+    pub scale: f64,
+}
+impl Material for BumpMap {
+    fn eval(&self, hit_record: &HitResult, wo: &Vec3, wi: &Vec3) -> Vec3 {
+        self.material.eval(hit_record, wo, wi)
+    }
+    fn sample(&self, hit_record: &HitResult, wo: &Vec3, u: (f64, f64)) -> Option<ScatterRecord> {
+        self.material.sample(hit_record, wo, u)
+    }
+    fn pdf(&self, hit_record: &HitResult, wo: &Vec3, wi: &Vec3) -> f64 {
+        self.material.pdf(hit_record, wo, wi)
+    }
+    fn emitted(&self, hit_record: &HitResult, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.material.emitted(hit_record, u, v, p)
+    }
+    fn is_cutout(&self, hit_record: &HitResult) -> bool {
+        self.material.is_cutout(hit_record)
+    }
+    fn perturb_normal(&self, hit_record: &mut HitResult) {
+        let delta = 0.0005;
+        let (u, v, p) = (hit_record.fu, hit_record.fv, hit_record.p.clone());
+        let (dpdu, dpdv) = (hit_record.dpdu.clone(), hit_record.dpdv.clone());
+        if dpdu.squared_length() == 0.0 || dpdv.squared_length() == 0.0 {
+            self.material.perturb_normal(hit_record);
+            return;
+        }
+        let n = hit_record.outward_normal();
+        let height = self.height.value(u, v, p.clone()).x;
+        let height_u = self
+            .height
+            .value(u + delta, v, p.clone() + dpdu.clone() * delta)
+            .x;
+        let height_v = self.height.value(u, v + delta, p + dpdv.clone() * delta).x;
+        let displaced_dpdu = dpdu + n.clone() * ((height_u - height) / delta * self.scale);
+        let displaced_dpdv = dpdv + n.clone() * ((height_v - height) / delta * self.scale);
+        let normal = Vec3::cross(displaced_dpdu, displaced_dpdv);
+        if normal.squared_length() > 0.0 {
+            let normal = if normal.clone() * n < 0.0 {
+                -normal
+            } else {
+                normal
+            };
+            hit_record.shading_normal = face_forward(normal.unit(), hit_record);
+        }
+        self.material.perturb_normal(hit_record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hit::white_lambertian;
+    use crate::ray::Ray;
+
+    #[test]
+    fn test_flat_maps() {
+        // A flat normal map and a constant height leave the normal alone.
+        let white = white_lambertian();
+        let flat_normal: Arc<dyn Material> = Arc::new(NormalMap {
+            material: white.clone(),
+            map: Arc::new(ConstantTexture {
+                color: Vec3::new(0.5, 0.5, 1.0),
+            }),
+            strength: 1.0,
+        });
+        let flat_height: Arc<dyn Material> = Arc::new(BumpMap {
+            material: white,
+            height: Arc::new(ConstantTexture {
+                color: Vec3::ones() * 0.7,
+            }),
+            scale: 2.0,
+        });
+        let ray = Ray {
+            origin: Vec3::new(0.2, 0.1, 1.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+        };
+        let normal = Vec3::new(0.3, 0.0, 1.0).unit();
+        for material in vec![flat_normal, flat_height] {
+            let mut hit_record =
+                HitResult::new(&ray, 1.0, normal.clone(), 0.4, 0.6, material.clone());
+            hit_record.dpdu = Vec3::new(1.0, 0.0, -0.3);
+            hit_record.dpdv = Vec3::new(0.0, 1.0, 0.0);
+            material.perturb_normal(&mut hit_record);
+            assert!((hit_record.shading_normal - normal.clone()).length() < 1e-9);
+        }
+    }
+}
@@ -1,4 +1,4 @@
-pub use crate::ray::Ray;
+pub use crate::ray::{Ray, RayDifferential};
 pub use crate::vec3::Vec3;
 
 #[derive(Clone, Debug, PartialEq)]
@@ -66,12 +66,30 @@ impl Camera {
         }
     }
     pub fn get_ray(&self, s: f64, t: f64) -> Ray {
+        self.get_ray_through(&self.lens_origin(), s, t)
+    }
+    // Ray through (s, t) with the rays offset by one pixel in each direction,
+    // all leaving from the same point on the lens.
+    pub fn get_ray_differential(&self, s: f64, t: f64) -> RayDifferential {
+        let origin = self.lens_origin();
+        RayDifferential {
+            ray: self.get_ray_through(&origin, s, t),
+            rx: self.get_ray_through(&origin, s + 1.0 / self.width as f64, t),
+            ry: self.get_ray_through(&origin, s, t + 1.0 / self.height as f64),
+        }
+    }
+    fn lens_origin(&self) -> Vec3 {
         let rd = Vec3::random_in_unit_disk() * self.aperture * 0.5;
-        let origin = self.position.clone() + self.u.clone() * rd.x + self.v.clone() * rd.y;
+        self.position.clone() + self.u.clone() * rd.x + self.v.clone() * rd.y
+    }
+    fn get_ray_through(&self, origin: &Vec3, s: f64, t: f64) -> Ray {
         let direction = self.lower_left_corner.clone()
             + self.horizontal.clone() * s
             + self.vertical.clone() * t
             - origin.clone();
-        Ray { origin, direction }
+        Ray {
+            origin: origin.clone(),
+            direction,
+        }
     }
 }
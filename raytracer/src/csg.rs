@@ -0,0 +1,197 @@
+use crate::hit::*;
+use std::sync::Arc;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum CsgOp {
+    Union,
+    Intersection,
+    // `a` with `b` cut away.
+    Difference,
+}
+
+impl CsgOp {
+    fn inside(self, in_a: bool, in_b: bool) -> bool {
+        match self {
+            CsgOp::Union => in_a || in_b,
+            CsgOp::Intersection => in_a && in_b,
+            CsgOp::Difference => in_a && !in_b,
+        }
+    }
+}
+
+// Boolean combination of two closed shapes, built on their spans. Faces keep
+// the material of the shape they come from, except that the faces `b` cuts
+// into `a` in a difference take `a`'s material. Nodes nest.
+pub struct Csg {
+    pub op: CsgOp,
+    pub a: Arc<dyn Hitable>,
+    pub b: Arc<dyn Hitable>,
+}
+
+impl Hitable for Csg {
+    fn hit(&self, ra: &Ray, t_min: f64, t_max: f64) -> Option<HitResult> {
+        let in_range = |hit_result: &HitResult| hit_result.t > t_min && hit_result.t <= t_max;
+        for span in self.spans(ra) {
+            if in_range(&span.enter) {
+                return Some(span.enter);
+            }
+            if in_range(&span.exit) {
+                return Some(span.exit);
+            }
+        }
+        None
+    }
+    fn bounding_box(&self) -> Option<AABB> {
+        let (a, b) = (self.a.bounding_box()?, self.b.bounding_box()?);
+        match self.op {
+            CsgOp::Union => Some(AABB::surrounding_box(a, b)),
+            CsgOp::Intersection => Some(AABB {
+                min: a.min.max(b.min),
+                max: a.max.min(b.max),
+            }),
+            CsgOp::Difference => Some(a),
+        }
+    }
+    // Sweeps the boundaries of both operands in order and keeps those where
+    // the combined inside changes.
+    fn spans(&self, ra: &Ray) -> Vec<Span> {
+        let mut events = Vec::new();
+        for (from_a, shape) in &[(true, &self.a), (false, &self.b)] {
+            // A span of degenerate geometry with a NaN end is dropped whole,
+            // which keeps the sweep's inside/outside parity.
+            for span in shape.spans(ra) {
+                if span.enter.t.is_nan() || span.exit.t.is_nan() {
+                    continue;
+                }
+                events.push((*from_a, true, span.enter));
+                events.push((*from_a, false, span.exit));
+            }
+        }
+        events.sort_by(|x, y| x.2.t.partial_cmp(&y.2.t).unwrap());
+
+        let mut spans = Vec::new();
+        let (mut in_a, mut in_b) = (false, false);
+        let mut a_material: Option<Arc<dyn Material>> = None;
+        let mut enter: Option<HitResult> = None;
+        for (from_a, entering, mut hit_result) in events {
+            let was_inside = self.op.inside(in_a, in_b);
+            if from_a {
+                in_a = entering;
+                if entering {
+                    a_material = Some(hit_result.mat_ptr.clone());
+                }
+            } else {
+                in_b = entering;
+                if self.op == CsgOp::Difference {
+                    if let Some(material) = &a_material {
+                        hit_result.mat_ptr = material.clone();
+                    }
+                }
+            }
+            let inside = self.op.inside(in_a, in_b);
+            if inside == was_inside {
+                continue;
+            }
+            hit_result.front_face = inside;
+            if inside {
+                enter = Some(hit_result);
+            } else if let Some(enter) = enter.take() {
+                if enter.t < hit_result.t {
+                    spans.push(Span {
+                        enter,
+                        exit: hit_result,
+                    });
+                }
+            }
+        }
+        spans
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sphere(z: f64) -> Arc<dyn Hitable> {
+        Arc::new(Sphere {
+            center: Vec3::new(0.0, 0.0, z),
+            radius: 1.0,
+            mat_ptr: white_lambertian(),
+        })
+    }
+
+    #[test]
+    fn test_csg_spans() {
+        let (a, b) = (sphere(0.0), sphere(1.0));
+        let ra = Ray {
+            origin: Vec3::new(0.0, 0.0, -3.0),
+            direction: Vec3::new(0.0, 0.0, 1.0),
+        };
+        for &(op, enter, exit) in &[
+            (CsgOp::Union, 2.0, 5.0),
+            (CsgOp::Intersection, 3.0, 4.0),
+            (CsgOp::Difference, 2.0, 3.0),
+        ] {
+            let csg = Csg {
+                op,
+                a: a.clone(),
+                b: b.clone(),
+            };
+            let spans = csg.spans(&ra);
+            assert_eq!(spans.len(), 1);
+            assert!((spans[0].enter.t - enter).abs() < 1e-9);
+            assert!((spans[0].exit.t - exit).abs() < 1e-9);
+            assert!(spans[0].enter.front_face && !spans[0].exit.front_face);
+        }
+
+        // The face cut by `b` takes `a`'s material.
+        let csg = Csg {
+            op: CsgOp::Difference,
+            a: a.clone(),
+            b,
+        };
+        let cut = csg.hit(&ra, 2.5, f64::INFINITY).unwrap();
+        let a_material = a.hit(&ra, 0.0, f64::INFINITY).unwrap().mat_ptr;
+        assert!(Arc::as_ptr(&cut.mat_ptr) as *const u8 == Arc::as_ptr(&a_material) as *const u8);
+    }
+
+    // Degenerate geometry whose single span has NaN ends.
+    struct Degenerate;
+    impl Hitable for Degenerate {
+        fn hit(&self, _ra: &Ray, _t_min: f64, _t_max: f64) -> Option<HitResult> {
+            None
+        }
+        fn bounding_box(&self) -> Option<AABB> {
+            None
+        }
+        fn spans(&self, ra: &Ray) -> Vec<Span> {
+            let hit_result = HitResult::new(
+                ra,
+                f64::NAN,
+                Vec3::new(0.0, 0.0, 1.0),
+                0.0,
+                0.0,
+                white_lambertian(),
+            );
+            vec![Span {
+                enter: hit_result.clone(),
+                exit: hit_result,
+            }]
+        }
+    }
+
+    #[test]
+    fn test_csg_nan_span() {
+        let csg = Csg {
+            op: CsgOp::Union,
+            a: sphere(0.0),
+            b: Arc::new(Degenerate),
+        };
+        let ra = Ray {
+            origin: Vec3::new(0.0, 0.0, -3.0),
+            direction: Vec3::new(0.0, 0.0, 1.0),
+        };
+        let hit = csg.hit(&ra, 0.0, f64::INFINITY).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-9);
+    }
+}
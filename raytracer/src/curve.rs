@@ -0,0 +1,409 @@
+use crate::hit::*;
+use crate::world::BVHNode;
+use std::f64::consts::SQRT_2;
+use std::sync::Arc;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CurveType {
+    // A strip that always turns to face the ray; enough for thin hair.
+    Flat,
+    // A flat strip whose shading normals bend across its width, so that it
+    // shades like a tube.
+    Cylinder,
+    // A strip oriented by the normals at its two ends, for grass and leaves.
+    Ribbon,
+}
+
+// A cubic Bezier curve whose width changes linearly from one end to the
+// other. Curves are intersected as segments, see `Curve::into_segments`.
+pub struct Curve {
+    pub control_points: [Vec3; 4],
+    pub width: [f64; 2],
+    pub kind: CurveType,
+    // Normals at the two ends of a ribbon; ignored by the other kinds.
+    pub normals: [Vec3; 2],
+    pub mat_ptr: Arc<dyn Material>,
+}
+
+fn blossom(cp: &[Vec3; 4], u0: f64, u1: f64, u2: f64) -> Vec3 {
+    let a = [
+        Vec3::lerp(cp[1].clone(), cp[0].clone(), u0),
+        Vec3::lerp(cp[2].clone(), cp[1].clone(), u0),
+        Vec3::lerp(cp[3].clone(), cp[2].clone(), u0),
+    ];
+    let b = [
+        Vec3::lerp(a[1].clone(), a[0].clone(), u1),
+        Vec3::lerp(a[2].clone(), a[1].clone(), u1),
+    ];
+    Vec3::lerp(b[1].clone(), b[0].clone(), u2)
+}
+
+// The control points of the part of the curve between `u0` and `u1`.
+fn sub_curve(cp: &[Vec3; 4], u0: f64, u1: f64) -> [Vec3; 4] {
+    [
+        blossom(cp, u0, u0, u0),
+        blossom(cp, u0, u0, u1),
+        blossom(cp, u0, u1, u1),
+        blossom(cp, u1, u1, u1),
+    ]
+}
+
+// The two halves of the curve (de Casteljau).
+fn split(cp: &[Vec3; 4]) -> [[Vec3; 4]; 2] {
+    let a = [
+        Vec3::lerp(cp[1].clone(), cp[0].clone(), 0.5),
+        Vec3::lerp(cp[2].clone(), cp[1].clone(), 0.5),
+        Vec3::lerp(cp[3].clone(), cp[2].clone(), 0.5),
+    ];
+    let b = [
+        Vec3::lerp(a[1].clone(), a[0].clone(), 0.5),
+        Vec3::lerp(a[2].clone(), a[1].clone(), 0.5),
+    ];
+    let c = Vec3::lerp(b[1].clone(), b[0].clone(), 0.5);
+    [
+        [cp[0].clone(), a[0].clone(), b[0].clone(), c.clone()],
+        [c, b[1].clone(), a[2].clone(), cp[3].clone()],
+    ]
+}
+
+// Point and derivative at `u`.
+fn evaluate(cp: &[Vec3; 4], u: f64) -> (Vec3, Vec3) {
+    let a = [
+        Vec3::lerp(cp[1].clone(), cp[0].clone(), u),
+        Vec3::lerp(cp[2].clone(), cp[1].clone(), u),
+        Vec3::lerp(cp[3].clone(), cp[2].clone(), u),
+    ];
+    let b = [
+        Vec3::lerp(a[1].clone(), a[0].clone(), u),
+        Vec3::lerp(a[2].clone(), a[1].clone(), u),
+    ];
+    let derivative = b[1].clone() - b[0].clone();
+    // The derivative vanishes at an end whose control points coincide.
+    let derivative = if derivative.squared_length() > 0.0 {
+        derivative * 3.0
+    } else {
+        cp[3].clone() - cp[0].clone()
+    };
+    (Vec3::lerp(b[1].clone(), b[0].clone(), u), derivative)
+}
+
+impl Curve {
+    // Splits the curve into `count` segments. Each is bounded by the control
+    // points of its own part of the curve, which hug it far tighter than the
+    // whole curve's, so a BVH over the segments of many curves culls well.
+    pub fn into_segments(self, count: usize) -> Vec<Arc<dyn Hitable>> {
+        let count = count.max(1);
+        let curve = Arc::new(self);
+        (0..count)
+            .map(|i| -> Arc<dyn Hitable> {
+                Arc::new(CurveSegment {
+                    curve: curve.clone(),
+                    u0: i as f64 / count as f64,
+                    u1: (i + 1) as f64 / count as f64,
+                })
+            })
+            .collect()
+    }
+    pub fn into_hitable(self, count: usize) -> Arc<dyn Hitable> {
+        curves_into_hitable(vec![self], count)
+    }
+    // A smooth strand through `points` (Catmull-Rom), one curve per span,
+    // with the width going from `width[0]` at the root to `width[1]` at the
+    // tip. Ribbons need end normals, so strands are flat or cylinders.
+    pub fn strand(
+        points: &[Vec3],
+        width: [f64; 2],
+        kind: CurveType,
+        mat_ptr: Arc<dyn Material>,
+    ) -> Vec<Curve> {
+        assert!(kind != CurveType::Ribbon, "strands cannot be ribbons");
+        assert!(points.len() >= 2, "a strand needs two points");
+        let spans = points.len() - 1;
+        let width_at = |i: usize| width[0] + (width[1] - width[0]) * i as f64 / spans as f64;
+        (0..spans)
+            .map(|i| {
+                let p1 = &points[i];
+                let p2 = &points[i + 1];
+                let p0 = if i > 0 { &points[i - 1] } else { p1 };
+                let p3 = points.get(i + 2).unwrap_or(p2);
+                Curve {
+                    control_points: [
+                        p1.clone(),
+                        p1.clone() + (p2.clone() - p0.clone()) / 6.0,
+                        p2.clone() - (p3.clone() - p1.clone()) / 6.0,
+                        p2.clone(),
+                    ],
+                    width: [width_at(i), width_at(i + 1)],
+                    kind,
+                    normals: [Vec3::zero(), Vec3::zero()],
+                    mat_ptr: mat_ptr.clone(),
+                }
+            })
+            .collect()
+    }
+    fn width_at(&self, u: f64) -> f64 {
+        self.width[0] * (1.0 - u) + self.width[1] * u
+    }
+    // The ribbon normal at `u`, turning at a constant rate between the ends.
+    fn ribbon_normal(&self, u: f64) -> Vec3 {
+        let n0 = self.normals[0].unit();
+        let n1 = self.normals[1].unit();
+        let angle = (n0.clone() * n1.clone()).max(-1.0).min(1.0).acos();
+        if angle < 1e-6 {
+            return n0;
+        }
+        (n0 * ((1.0 - u) * angle).sin() + n1 * (u * angle).sin()) / angle.sin()
+    }
+}
+
+// One BVH over the segments of all the curves, e.g. a head of hair.
+pub fn curves_into_hitable(curves: Vec<Curve>, segments: usize) -> Arc<dyn Hitable> {
+    let mut list: Vec<Arc<dyn Hitable>> = curves
+        .into_iter()
+        .flat_map(|curve| curve.into_segments(segments))
+        .collect();
+    assert!(!list.is_empty(), "no curves");
+    let length = list.len();
+    Arc::new(BVHNode::new(&mut list, 0, length))
+}
+
+pub struct CurveSegment {
+    pub curve: Arc<Curve>,
+    pub u0: f64,
+    pub u1: f64,
+}
+
+// The closest hit so far, in ray space.
+struct CurveHit {
+    z: f64,
+    u: f64,
+    // From the curve to the hit point, across the width.
+    offset: Vec3,
+    width: f64,
+}
+
+impl CurveSegment {
+    // Whether the box around `cp`, widened by half of `width`, can hold a hit
+    // with depth in `z_range`. The ray runs along z through the origin.
+    fn overlaps(cp: &[Vec3; 4], width: f64, z_range: (f64, f64)) -> bool {
+        let min = cp[0]
+            .min(cp[1].clone())
+            .min(cp[2].clone())
+            .min(cp[3].clone());
+        let max = cp[0]
+            .max(cp[1].clone())
+            .max(cp[2].clone())
+            .max(cp[3].clone());
+        let half = 0.5 * width;
+        min.x - half <= 0.0
+            && max.x + half >= 0.0
+            && min.y - half <= 0.0
+            && max.y + half >= 0.0
+            && max.z + half >= z_range.0
+            && min.z - half <= z_range.1
+    }
+    // Splits `cp` until it is nearly straight, then intersects the pieces
+    // as flat strips facing the ray.
+    #[allow(clippy::too_many_arguments)]
+    fn intersect(
+        &self,
+        ra: &Ray,
+        cp: &[Vec3; 4],
+        u0: f64,
+        u1: f64,
+        depth: u32,
+        z_range: &mut (f64, f64),
+        closest: &mut Option<CurveHit>,
+    ) {
+        let curve = &self.curve;
+        let max_width = curve.width_at(u0).max(curve.width_at(u1));
+        if !Self::overlaps(cp, max_width, *z_range) {
+            return;
+        }
+        if depth > 0 {
+            let halves = split(cp);
+            let middle = 0.5 * (u0 + u1);
+            self.intersect(ra, &halves[0], u0, middle, depth - 1, z_range, closest);
+            self.intersect(ra, &halves[1], middle, u1, depth - 1, z_range, closest);
+            return;
+        }
+
+        // The ray must pass between the perpendiculars to the curve at both
+        // ends, or the hit belongs to a neighbouring piece.
+        let edge = (cp[1].y - cp[0].y) * -cp[0].y + cp[0].x * (cp[0].x - cp[1].x);
+        if edge < 0.0 {
+            return;
+        }
+        let edge = (cp[2].y - cp[3].y) * -cp[3].y + cp[3].x * (cp[3].x - cp[2].x);
+        if edge < 0.0 {
+            return;
+        }
+        // Closest point to the ray on the chord, as a parameter along it.
+        let (dx, dy) = (cp[3].x - cp[0].x, cp[3].y - cp[0].y);
+        let denominator = dx * dx + dy * dy;
+        if denominator == 0.0 {
+            return;
+        }
+        let w = -(cp[0].x * dx + cp[0].y * dy) / denominator;
+        let u = (u0 + (u1 - u0) * w).max(u0).min(u1);
+        let mut width = curve.width_at(u);
+        if curve.kind == CurveType::Ribbon {
+            // Seen edge-on, a ribbon is narrower.
+            let n = curve.ribbon_normal(u);
+            width *= (n * ra.direction.clone()).abs() / ra.direction.length();
+        }
+        let (pc, _) = evaluate(cp, w.max(0.0).min(1.0));
+        if pc.x * pc.x + pc.y * pc.y > width * width * 0.25 {
+            return;
+        }
+        if pc.z <= z_range.0 || pc.z > z_range.1 {
+            return;
+        }
+        z_range.1 = pc.z;
+        *closest = Some(CurveHit {
+            z: pc.z,
+            u,
+            offset: Vec3::new(-pc.x, -pc.y, 0.0),
+            width,
+        });
+    }
+}
+
+impl Hitable for CurveSegment {
+    fn hit(&self, ra: &Ray, t_min: f64, t_max: f64) -> Option<HitResult> {
+        let curve = &self.curve;
+        let cp = sub_curve(&curve.control_points, self.u0, self.u1);
+        // Ray space: the ray starts at the origin and runs along z, with the
+        // segment roughly along x.
+        let frame = ONB::build_from_wu(ra.direction.clone(), cp[3].clone() - cp[0].clone());
+        let to_ray = |p: &Vec3| frame.to_local(p.clone() - ra.origin.clone());
+        let cp = [
+            to_ray(&cp[0]),
+            to_ray(&cp[1]),
+            to_ray(&cp[2]),
+            to_ray(&cp[3]),
+        ];
+
+        // Split until the control polygon strays from the curve by less than
+        // a twentieth of its width.
+        let mut bend = 0.0f64;
+        for i in 0..2 {
+            let d = (cp[i].clone() - cp[i + 1].clone() * 2.0 + cp[i + 2].clone()).abs();
+            bend = bend.max(d.x).max(d.y).max(d.z);
+        }
+        let epsilon = curve.width[0].max(curve.width[1]) * 0.05;
+        let depth = if bend > 0.0 {
+            ((SQRT_2 * 6.0 * bend / (8.0 * epsilon)).log2() / 2.0)
+                .max(0.0)
+                .min(10.0) as u32
+        } else {
+            0
+        };
+
+        let length = ra.direction.length();
+        let mut z_range = (t_min * length, t_max * length);
+        let mut closest = None;
+        self.intersect(ra, &cp, self.u0, self.u1, depth, &mut z_range, &mut closest);
+        let hit = closest?;
+
+        let (_, dpdu) = evaluate(&curve.control_points, hit.u);
+        let dpdv = if curve.kind == CurveType::Ribbon {
+            Vec3::cross(curve.ribbon_normal(hit.u), dpdu.clone())
+        } else {
+            // Across the curve as seen along the ray.
+            let tangent = frame.to_local(dpdu.clone());
+            let across = Vec3::new(-tangent.y, tangent.x, 0.0);
+            if across.squared_length() > 0.0 {
+                frame.localvec(across)
+            } else {
+                frame.u.clone()
+            }
+        };
+        let dpdv = dpdv.unit() * hit.width;
+        let offset = frame.localvec(hit.offset);
+        // Across the width from 0 to 1, increasing along `dpdv`.
+        let v = (0.5 + offset.clone() * dpdv.clone() / (hit.width * hit.width))
+            .max(0.0)
+            .min(1.0);
+        let normal = Vec3::cross(dpdu.clone(), dpdv.clone()).unit();
+        let mut hit_result =
+            HitResult::new(ra, hit.z / length, normal, hit.u, v, curve.mat_ptr.clone());
+        if curve.kind == CurveType::Cylinder {
+            // The normal of a tube of the same width.
+            let h = (2.0 * offset.length() / hit.width).min(1.0);
+            let side = if offset.squared_length() > 0.0 {
+                offset.unit()
+            } else {
+                Vec3::zero()
+            };
+            hit_result.shading_normal =
+                (hit_result.normal.clone() * (1.0 - h * h).sqrt() + side * h).unit();
+        }
+        hit_result.dpdu = dpdu;
+        hit_result.dpdv = dpdv;
+        hit_result.p_error = Vec3::ones() * (2.0 * hit.width);
+        Some(hit_result)
+    }
+    fn bounding_box(&self) -> Option<AABB> {
+        let cp = sub_curve(&self.curve.control_points, self.u0, self.u1);
+        let half = 0.5
+            * self
+                .curve
+                .width_at(self.u0)
+                .max(self.curve.width_at(self.u1));
+        let min = cp[0]
+            .min(cp[1].clone())
+            .min(cp[2].clone())
+            .min(cp[3].clone());
+        let max = cp[0]
+            .max(cp[1].clone())
+            .max(cp[2].clone())
+            .max(cp[3].clone());
+        Some(AABB {
+            min: min - Vec3::ones() * half,
+            max: max + Vec3::ones() * half,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_curve_intersection() {
+        let mat_ptr = white_lambertian();
+        // An arch in the xy plane, 0.2 wide at the root and 0.1 at the tip.
+        let curve = Curve {
+            control_points: [
+                Vec3::new(-1.0, 0.0, 0.0),
+                Vec3::new(-1.0, 1.0, 0.0),
+                Vec3::new(1.0, 1.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+            ],
+            width: [0.2, 0.1],
+            kind: CurveType::Cylinder,
+            normals: [Vec3::zero(), Vec3::zero()],
+            mat_ptr,
+        };
+        let hitable = curve.into_hitable(4);
+        let ray = |x: f64, y: f64| Ray {
+            origin: Vec3::new(x, y, 5.0),
+            direction: Vec3::new(0.0, 0.0, -2.0),
+        };
+
+        // The apex is at (0, 0.75), where the width is 0.15.
+        let hit = hitable.hit(&ray(0.0, 0.75), 0.0, f64::INFINITY).unwrap();
+        assert!((hit.t - 2.5).abs() < 1e-9);
+        assert!((hit.fu - 0.5).abs() < 1e-3 && (hit.fv - 0.5).abs() < 1e-3);
+        assert!((hit.normal.clone() - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-6);
+        assert!(hitable.hit(&ray(0.0, 0.84), 0.0, f64::INFINITY).is_none());
+        assert!(hitable.hit(&ray(0.0, 0.75), 0.0, 2.0).is_none());
+
+        // Off the axis, a cylinder's shading normal leans to the side.
+        let hit = hitable.hit(&ray(0.0, 0.8), 0.0, f64::INFINITY).unwrap();
+        assert!(hit.shading_normal.y > 0.5);
+        assert!(hitable.hit(&ray(-1.0, 0.05), 0.0, f64::INFINITY).is_some());
+        assert!(hitable.hit(&ray(-0.5, 0.2), 0.0, f64::INFINITY).is_none());
+    }
+}
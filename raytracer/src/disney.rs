@@ -0,0 +1,375 @@
+use crate::hit::HitResult;
+use crate::material::*;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+fn schlick_weight(cosine: f64) -> f64 {
+    let m = (1.0 - cosine).max(0.0).min(1.0);
+    let m2 = m * m;
+    m2 * m2 * m
+}
+
+fn luminance(color: &Vec3) -> f64 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+// Berry's GTR1 distribution, used by the clearcoat lobe.
+fn gtr1(cos_h: f64, alpha: f64) -> f64 {
+    if alpha >= 1.0 {
+        return 1.0 / PI;
+    }
+    let a2 = alpha * alpha;
+    let t = 1.0 + (a2 - 1.0) * cos_h * cos_h;
+    (a2 - 1.0) / (PI * a2.ln() * t)
+}
+
+fn sample_gtr1(alpha: f64, u1: f64, u2: f64) -> Vec3 {
+    let a2 = alpha * alpha;
+    let cos_theta = if alpha >= 1.0 {
+        (1.0 - u1).sqrt()
+    } else {
+        ((1.0 - a2.powf(1.0 - u1)) / (1.0 - a2)).max(0.0).sqrt()
+    };
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+    Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+// Principled material after Burley 2012/2015. Every knob is a texture; scalar
+// knobs read the first channel.
+pub struct DisneyMaterial {
+    pub base_color: Arc<dyn Texture>,
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    pub specular: Arc<dyn Texture>,
+    pub specular_tint: Arc<dyn Texture>,
+    pub sheen: Arc<dyn Texture>,
+    pub sheen_tint: Arc<dyn Texture>,
+    pub clearcoat: Arc<dyn Texture>,
+    pub clearcoat_gloss: Arc<dyn Texture>,
+    pub transmission: Arc<dyn Texture>,
+    pub anisotropic: Arc<dyn Texture>,
+    pub ior: f64,
+}
+
+impl DisneyMaterial {
+    pub fn new(base_color: Arc<dyn Texture>) -> Self {
+        let constant = |value: f64| -> Arc<dyn Texture> {
+            Arc::new(ConstantTexture {
+                color: Vec3::ones() * value,
+            })
+        };
+        Self {
+            base_color,
+            metallic: constant(0.0),
+            roughness: constant(0.5),
+            specular: constant(0.5),
+            specular_tint: constant(0.0),
+            sheen: constant(0.0),
+            sheen_tint: constant(0.5),
+            clearcoat: constant(0.0),
+            clearcoat_gloss: constant(1.0),
+            transmission: constant(0.0),
+            anisotropic: constant(0.0),
+            ior: 1.5,
+        }
+    }
+    pub fn bsdf(&self, hit_record: &HitResult, wo: &Vec3) -> DisneyBsdf {
+        let scalar = |texture: &Arc<dyn Texture>| hit_record.texture_value(texture).x;
+        let roughness = scalar(&self.roughness).max(0.01);
+        DisneyBsdf {
+            base_color: hit_record.texture_value(&self.base_color),
+            metallic: scalar(&self.metallic),
+            roughness,
+            specular: scalar(&self.specular),
+            specular_tint: scalar(&self.specular_tint),
+            sheen: scalar(&self.sheen),
+            sheen_tint: scalar(&self.sheen_tint),
+            clearcoat: scalar(&self.clearcoat),
+            clearcoat_gloss: scalar(&self.clearcoat_gloss),
+            transmission: scalar(&self.transmission),
+            eta: if hit_record.front_face {
+                self.ior
+            } else {
+                1.0 / self.ior
+            },
+            ggx: Ggx::anisotropic(roughness, scalar(&self.anisotropic)),
+            wo: wo.clone(),
+        }
+    }
+}
+
+// The principled BSDF with its textures resolved at one shading point for the
+// outgoing direction `wo`; `eta` is inside over outside.
+pub struct DisneyBsdf {
+    pub base_color: Vec3,
+    pub metallic: f64,
+    pub roughness: f64,
+    pub specular: f64,
+    pub specular_tint: f64,
+    pub sheen: f64,
+    pub sheen_tint: f64,
+    pub clearcoat: f64,
+    pub clearcoat_gloss: f64,
+    pub transmission: f64,
+    pub eta: f64,
+    pub ggx: Ggx,
+    pub wo: Vec3,
+}
+
+impl DisneyBsdf {
+    fn tint(&self) -> Vec3 {
+        let lum = luminance(&self.base_color);
+        if lum > 0.0 {
+            self.base_color.clone() / lum
+        } else {
+            Vec3::ones()
+        }
+    }
+    fn diffuse_weight(&self) -> f64 {
+        (1.0 - self.metallic) * (1.0 - self.transmission)
+    }
+    fn transmission_weight(&self) -> f64 {
+        (1.0 - self.metallic) * self.transmission
+    }
+    fn clearcoat_alpha(&self) -> f64 {
+        0.1 + (0.001 - 0.1) * self.clearcoat_gloss
+    }
+    // Selection probabilities of the diffuse, specular, clearcoat and
+    // transmission lobes.
+    fn lobe_probabilities(&self) -> [f64; 4] {
+        let diffuse = self.diffuse_weight() * luminance(&self.base_color).max(0.05);
+        let specular = 1.0;
+        let clearcoat = 0.25 * self.clearcoat;
+        let transmission = self.transmission_weight();
+        let total = diffuse + specular + clearcoat + transmission;
+        [
+            diffuse / total,
+            specular / total,
+            clearcoat / total,
+            transmission / total,
+        ]
+    }
+    pub fn eval(&self, wi: &Vec3) -> Vec3 {
+        let wo = &self.wo;
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return Vec3::zero();
+        }
+        if wi.z < 0.0 {
+            return self.eval_transmission(wi) * self.transmission_weight();
+        }
+        let h = (wo.clone() + wi.clone()).unit();
+        let cos_d = wi.clone() * h.clone();
+        let fl = schlick_weight(wi.z);
+        let fv = schlick_weight(wo.z);
+
+        // Burley's retro-reflection renormalized as in Frostbite (Lagarde
+        // and de Rousiers 2014), so that the diffuse albedo stays below one.
+        let energy_bias = 0.5 * self.roughness;
+        let energy_factor = 1.0 + (1.0 / 1.51 - 1.0) * self.roughness;
+        let fd90 = energy_bias + 2.0 * cos_d * cos_d * self.roughness;
+        let fd = (1.0 + (fd90 - 1.0) * fl) * (1.0 + (fd90 - 1.0) * fv) * energy_factor;
+        let fh = schlick_weight(cos_d);
+        let sheen_color = Vec3::ones() * (1.0 - self.sheen_tint) + self.tint() * self.sheen_tint;
+        let diffuse = self.base_color.clone() * (fd / PI) + sheen_color * (fh * self.sheen);
+
+        let spec_color =
+            Vec3::ones() * (1.0 - self.specular_tint) + self.tint() * self.specular_tint;
+        let f0 = Vec3::lerp(
+            self.base_color.clone(),
+            spec_color * (self.specular * 0.08),
+            self.metallic,
+        );
+        let fresnel = f0.clone() + (Vec3::ones() - f0) * fh;
+        let specular = fresnel * (self.ggx.d(&h) * self.ggx.g(wo, wi) / (4.0 * wo.z * wi.z));
+
+        let fc = 0.04 + 0.96 * fh;
+        let coat_ggx = Ggx {
+            alpha_x: 0.25,
+            alpha_y: 0.25,
+        };
+        let clearcoat =
+            0.25 * self.clearcoat * gtr1(h.z, self.clearcoat_alpha()) * fc * coat_ggx.g(wo, wi)
+                / (4.0 * wo.z * wi.z);
+
+        diffuse * self.diffuse_weight() + specular + Vec3::ones() * clearcoat
+    }
+    fn transmission_half_vector(&self, wi: &Vec3) -> Option<Vec3> {
+        let h = -(self.wo.clone() + wi.clone() * self.eta);
+        if h.squared_length() == 0.0 {
+            return None;
+        }
+        let h = h.unit();
+        Some(if h.z < 0.0 { -h } else { h })
+    }
+    fn eval_transmission(&self, wi: &Vec3) -> Vec3 {
+        let wo = &self.wo;
+        let h = match self.transmission_half_vector(wi) {
+            Some(h) => h,
+            None => return Vec3::zero(),
+        };
+        let cos_o = wo.clone() * h.clone();
+        let cos_i = wi.clone() * h.clone();
+        if cos_o <= 0.0 || cos_i >= 0.0 {
+            return Vec3::zero();
+        }
+        let denom = cos_o + self.eta * cos_i;
+        let fresnel = fresnel_dielectric(cos_o, 1.0 / self.eta);
+        let value = (cos_o * cos_i).abs() * (1.0 - fresnel) * self.ggx.d(&h) * self.ggx.g(wo, wi)
+            / ((wo.z * wi.z).abs() * denom * denom);
+        self.base_color.sqrt() * value
+    }
+    pub fn pdf(&self, wi: &Vec3) -> f64 {
+        let wo = &self.wo;
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        let probs = self.lobe_probabilities();
+        if wi.z < 0.0 {
+            let h = match self.transmission_half_vector(wi) {
+                Some(h) => h,
+                None => return 0.0,
+            };
+            let cos_o = wo.clone() * h.clone();
+            let cos_i = wi.clone() * h.clone();
+            if cos_o <= 0.0 || cos_i >= 0.0 {
+                return 0.0;
+            }
+            let denom = cos_o + self.eta * cos_i;
+            return probs[3] * self.ggx.pdf_m(&h) * self.eta * self.eta * cos_i.abs()
+                / (denom * denom);
+        }
+        let h = (wo.clone() + wi.clone()).unit();
+        let cos_oh = (wo.clone() * h.clone()).max(1e-8);
+        probs[0] * wi.z / PI
+            + probs[1] * self.ggx.pdf_m(&h) / (4.0 * cos_oh)
+            + probs[2] * gtr1(h.z, self.clearcoat_alpha()) * h.z / (4.0 * cos_oh)
+    }
+    pub fn sample(&self, u: (f64, f64)) -> Option<Vec3> {
+        let wo = &self.wo;
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let probs = self.lobe_probabilities();
+        // Pick a lobe, then reuse what is left of u.0 within it.
+        let mut remainder = u.0;
+        let mut lobe = 0;
+        while lobe < 3 && remainder >= probs[lobe] {
+            remainder -= probs[lobe];
+            lobe += 1;
+        }
+        let u1 = (remainder / probs[lobe]).max(0.0).min(1.0 - 1e-9);
+        let u2 = u.1;
+        if lobe == 0 {
+            return Some(Vec3::cosine_direction(u1, u2));
+        }
+        let m = if lobe == 2 {
+            sample_gtr1(self.clearcoat_alpha(), u1, u2)
+        } else {
+            self.ggx.sample_m(u1, u2)
+        };
+        // Microfacets facing away from `wo` are not seen, and a lobe may not
+        // send light to the other side than it is meant for.
+        if wo.clone() * m.clone() <= 0.0 {
+            return None;
+        }
+        if lobe < 3 {
+            Some(reflect_about(wo, &m)).filter(|wi| wi.z > 0.0)
+        } else {
+            refract_about(wo, &m, 1.0 / self.eta).filter(|wi| wi.z < 0.0)
+        }
+    }
+}
+
+impl Material for DisneyMaterial {
+    fn eval(&self, hit_record: &HitResult, wo: &Vec3, wi: &Vec3) -> Vec3 {
+        self.bsdf(hit_record, wo).eval(wi) * wi.z.abs()
+    }
+    fn sample(&self, hit_record: &HitResult, wo: &Vec3, u: (f64, f64)) -> Option<ScatterRecord> {
+        let bsdf = self.bsdf(hit_record, wo);
+        let wi = bsdf.sample(u)?;
+        let pdf = bsdf.pdf(&wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        let flags = if wi.z < 0.0 {
+            Lobe::TRANSMISSION | Lobe::GLOSSY
+        } else {
+            Lobe::REFLECTION | Lobe::DIFFUSE | Lobe::GLOSSY
+        };
+        Some(ScatterRecord {
+            f: bsdf.eval(&wi) * wi.z.abs(),
+            wi,
+            pdf,
+            flags,
+        })
+    }
+    fn pdf(&self, hit_record: &HitResult, wo: &Vec3, wi: &Vec3) -> f64 {
+        self.bsdf(hit_record, wo).pdf(wi)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hit::white_texture;
+    use crate::ray::Ray;
+
+    #[test]
+    fn test_disney_sampling() {
+        // Sampling agrees with `eval` and `pdf`, and a white material
+        // reflects no more than it receives.
+        let constant = |value: f64| -> Arc<dyn Texture> {
+            Arc::new(ConstantTexture {
+                color: Vec3::ones() * value,
+            })
+        };
+        let variants: Vec<fn(&mut DisneyMaterial, &dyn Fn(f64) -> Arc<dyn Texture>)> = vec![
+            |_, _| {},
+            |material, constant| material.metallic = constant(1.0),
+            |material, constant| material.transmission = constant(1.0),
+            |material, constant| material.sheen = constant(1.0),
+            |material, constant| material.roughness = constant(1.0),
+            |material, constant| {
+                material.clearcoat = constant(1.0);
+                material.anisotropic = constant(0.8);
+            },
+        ];
+        let ray = Ray {
+            origin: Vec3::new(0.0, 0.0, 1.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+        };
+        for variant in variants {
+            let mut material = DisneyMaterial::new(white_texture());
+            variant(&mut material, &constant);
+            let material = Arc::new(material);
+            let hit_record = HitResult::new(
+                &ray,
+                1.0,
+                Vec3::new(0.0, 0.0, 1.0),
+                0.0,
+                0.0,
+                material.clone(),
+            );
+            for &cosine in &[1.0, 0.5, 0.1] {
+                let wo = Vec3::new((1.0 - cosine * cosine as f64).sqrt(), 0.0, cosine);
+                let samples = 20000;
+                let mut sum = 0.0;
+                for i in 0..samples {
+                    let u = ((i as f64 + 0.5) / samples as f64, random_double());
+                    let scatter = match material.sample(&hit_record, &wo, u) {
+                        Some(scatter) => scatter,
+                        None => continue,
+                    };
+                    let f = material.eval(&hit_record, &wo, &scatter.wi);
+                    let pdf = material.pdf(&hit_record, &wo, &scatter.wi);
+                    assert!((f - scatter.f.clone()).length() <= 1e-9 * scatter.f.length());
+                    assert!((pdf - scatter.pdf).abs() <= 1e-9 * scatter.pdf);
+                    sum += luminance(&scatter.f) / scatter.pdf;
+                }
+                let albedo = sum / samples as f64;
+                assert!(albedo < 1.01, "albedo {} at cosine {}", albedo, cosine);
+            }
+        }
+    }
+}
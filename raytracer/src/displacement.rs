@@ -0,0 +1,208 @@
+use crate::mesh::TriangleMesh;
+use crate::subdivision::position_key;
+use crate::texture::Texture;
+use crate::vec3::Vec3;
+use std::collections::HashMap;
+
+impl TriangleMesh {
+    // Splits every triangle into four `levels` times, without smoothing, then
+    // moves each vertex along its normal by `scale` times the first channel
+    // of `height`, looked up at the vertex's uv and undisplaced position.
+    // Unlike a bump map this changes silhouettes and shadows. The result is
+    // an ordinary mesh, bounded triangle by triangle after displacement, so
+    // the BVH over it is exact. Vertices at the same position, split for
+    // hard edges or uv seams or never shared as in STL, move together by
+    // their averaged normal and height, so no cracks open between them.
+    pub fn displace(&self, height: &dyn Texture, scale: f64, levels: usize) -> TriangleMesh {
+        let mut mesh = self.clone();
+        for _ in 0..levels {
+            mesh = mesh.split_triangles();
+        }
+        let mut welded = HashMap::new();
+        let group: Vec<usize> = mesh
+            .positions
+            .iter()
+            .map(|p| {
+                let next = welded.len();
+                *welded.entry(position_key(p)).or_insert(next)
+            })
+            .collect();
+        // Without authored normals, those of the welded faces.
+        let mut normals = vec![Vec3::zero(); welded.len()];
+        if mesh.normals.len() == mesh.positions.len() {
+            for (i, normal) in mesh.normals.iter().enumerate() {
+                normals[group[i]] += normal.clone();
+            }
+        } else {
+            for face in &mesh.indices {
+                let p = [
+                    &mesh.positions[face[0]],
+                    &mesh.positions[face[1]],
+                    &mesh.positions[face[2]],
+                ];
+                let normal = Vec3::cross(p[1].clone() - p[0].clone(), p[2].clone() - p[0].clone());
+                for &v in face {
+                    normals[group[v]] += normal.clone();
+                }
+            }
+        }
+        let mut heights = vec![(0.0, 0); welded.len()];
+        for (i, position) in mesh.positions.iter().enumerate() {
+            let (u, v) = mesh.uvs.get(i).cloned().unwrap_or((0.0, 0.0));
+            let sum = &mut heights[group[i]];
+            sum.0 += height.value(u, v, position.clone()).x;
+            sum.1 += 1;
+        }
+        for (i, position) in mesh.positions.iter_mut().enumerate() {
+            let normal = &normals[group[i]];
+            if normal.squared_length() > 0.0 {
+                let (sum, count) = heights[group[i]];
+                *position += normal.unit() * (scale * sum / count as f64);
+            }
+        }
+        mesh.compute_normals();
+        mesh
+    }
+    // Each triangle into four, through the midpoints of its edges. Triangles
+    // sharing an edge share its midpoint, so no cracks open.
+    fn split_triangles(&self) -> TriangleMesh {
+        let mut mesh = self.clone();
+        let mut midpoints = HashMap::new();
+        let mut midpoint = |mesh: &mut TriangleMesh, a: usize, b: usize| -> usize {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                let half = |values: &[Vec3]| (values[a].clone() + values[b].clone()) * 0.5;
+                let position = half(&mesh.positions);
+                mesh.positions.push(position);
+                if !mesh.normals.is_empty() {
+                    let normal = half(&mesh.normals);
+                    let normal = if normal.squared_length() > 0.0 {
+                        normal.unit()
+                    } else {
+                        normal
+                    };
+                    mesh.normals.push(normal);
+                }
+                if !mesh.uvs.is_empty() {
+                    let (ua, va) = mesh.uvs[a];
+                    let (ub, vb) = mesh.uvs[b];
+                    mesh.uvs.push((0.5 * (ua + ub), 0.5 * (va + vb)));
+                }
+                if !mesh.colors.is_empty() {
+                    let color = half(&mesh.colors);
+                    mesh.colors.push(color);
+                }
+                mesh.positions.len() - 1
+            })
+        };
+        mesh.indices = Vec::with_capacity(4 * self.indices.len());
+        for &[a, b, c] in &self.indices {
+            let ab = midpoint(&mut mesh, a, b);
+            let bc = midpoint(&mut mesh, b, c);
+            let ca = midpoint(&mut mesh, c, a);
+            mesh.indices.push([a, ab, ca]);
+            mesh.indices.push([ab, b, bc]);
+            mesh.indices.push([ca, bc, c]);
+            mesh.indices.push([ab, bc, ca]);
+        }
+        mesh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::ConstantTexture;
+
+    #[test]
+    fn test_displace_mesh() {
+        // A unit square in the xy plane, lifted by a constant height.
+        let square = TriangleMesh {
+            positions: vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(1.0, 1.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            ],
+            uvs: vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)],
+            indices: vec![[0, 1, 2], [0, 2, 3]],
+            ..TriangleMesh::default()
+        };
+        let height = ConstantTexture {
+            color: Vec3::new(0.5, 0.0, 0.0),
+        };
+        let displaced = square.displace(&height, 0.2, 2);
+        // Shared edges are split once, leaving a 5 by 5 grid of vertices.
+        assert_eq!(displaced.positions.len(), 25);
+        assert_eq!(displaced.indices.len(), 32);
+        assert!(displaced
+            .positions
+            .iter()
+            .all(|p| (p.z - 0.1).abs() < 1e-12));
+        assert!(displaced
+            .uvs
+            .iter()
+            .all(|&(u, v)| (0.0..=1.0).contains(&u) && (0.0..=1.0).contains(&v)));
+        assert!(displaced.normals.iter().all(|n| (n.z - 1.0).abs() < 1e-12));
+    }
+
+    #[test]
+    fn test_displace_unshared_cube() {
+        // A unit cube with no shared vertices, as loaded from STL.
+        let mut cube = TriangleMesh::default();
+        for axis in 0..3 {
+            for &side in &[-0.5, 0.5] {
+                let corner = |a: f64, b: f64| {
+                    let mut p = [0.0; 3];
+                    p[axis] = side;
+                    p[(axis + 1) % 3] = a;
+                    p[(axis + 2) % 3] = b;
+                    Vec3::new(p[0], p[1], p[2])
+                };
+                let quad = [
+                    corner(-0.5, -0.5),
+                    corner(0.5, -0.5),
+                    corner(0.5, 0.5),
+                    corner(-0.5, 0.5),
+                ];
+                // Wound to face outwards.
+                let triangles = if side > 0.0 {
+                    [[0, 1, 2], [0, 2, 3]]
+                } else {
+                    [[0, 2, 1], [0, 3, 2]]
+                };
+                for &[a, b, c] in &triangles {
+                    let first = cube.positions.len();
+                    for &k in &[a, b, c] {
+                        cube.positions.push(quad[k].clone());
+                    }
+                    cube.indices.push([first, first + 1, first + 2]);
+                }
+            }
+        }
+        let height = ConstantTexture {
+            color: Vec3::new(0.5, 0.0, 0.0),
+        };
+        let displaced = cube.displace(&height, 0.2, 2);
+        // Watertight: every edge, matched by position, borders two triangles.
+        let mut edges = HashMap::new();
+        for face in &displaced.indices {
+            for k in 0..3 {
+                let (a, b) = (
+                    position_key(&displaced.positions[face[k]]),
+                    position_key(&displaced.positions[face[(k + 1) % 3]]),
+                );
+                *edges.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+            }
+        }
+        assert!(edges.values().all(|&count| count == 2));
+        // Corners move out along the diagonal, face centres along the face.
+        let moved = |p: Vec3| {
+            displaced
+                .positions
+                .iter()
+                .any(|q| (q.clone() - p.clone()).length() < 1e-12)
+        };
+        assert!(moved(Vec3::ones() * (0.5 + 0.1 / 3.0f64.sqrt())));
+        assert!(moved(Vec3::new(0.0, 0.0, -0.6)));
+    }
+}
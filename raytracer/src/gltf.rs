@@ -0,0 +1,960 @@
+use crate::bump::NormalMap;
+use crate::camera::Camera;
+use crate::disney::DisneyMaterial;
+use crate::hit::*;
+use crate::json::Json;
+use crate::mapping::Transform3;
+use crate::mesh::{invalid, TriangleMesh, VertexColorTexture};
+use crate::nodes::*;
+use crate::world::{BVHNode, World};
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::fs;
+use std::io::Result;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// Radius of the spheres that stand in for point and spot lights, which this
+// renderer cannot sample as true points.
+pub const PUNCTUAL_LIGHT_RADIUS: f64 = 0.05;
+
+// What a glTF scene holds that this renderer can draw: one BVH per mesh
+// primitive with its node transform baked in, the emissive triangles and
+// punctual lights to sample, and the perspective cameras in node order.
+pub struct GltfScene {
+    pub objects: Vec<Arc<dyn Hitable>>,
+    pub lights: Vec<Arc<dyn Hitable>>,
+    pub cameras: Vec<Camera>,
+}
+
+impl GltfScene {
+    // Loads a .gltf (with external or data URI buffers and images) or a .glb
+    // file. Cameras render `width` pixels wide. Materials map the
+    // metallic-roughness model with its base color, metallic-roughness,
+    // normal and emissive textures, plus KHR_materials_transmission,
+    // KHR_materials_ior and KHR_materials_emissive_strength, onto
+    // `DisneyMaterial`; alpha, occlusion and texture coordinate sets other
+    // than the first are ignored. Point and spot lights of
+    // KHR_lights_punctual become small glowing spheres, spot lights glowing
+    // only within their cone; directional lights, orthographic cameras,
+    // skins and morph targets are not supported, and directional lights
+    // fail the load rather than silently darken the scene.
+    pub fn from_path<P: AsRef<Path>>(path: P, width: u32) -> Result<Self> {
+        Self::from_path_subdivided(path, width, 0)
+    }
+    // Like `from_path`, with every mesh given `levels` rounds of Loop
+    // subdivision.
+    pub fn from_path_subdivided<P: AsRef<Path>>(
+        path: P,
+        width: u32,
+        levels: usize,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        Self::parse(&fs::read(path)?, base, width, levels)
+    }
+    fn parse(bytes: &[u8], base: &Path, width: u32, subdivision: usize) -> Result<Self> {
+        let document = Document::parse(bytes, base)?;
+        let mut loader = Loader {
+            document: &document,
+            width,
+            subdivision,
+            textures: HashMap::new(),
+            materials: HashMap::new(),
+            scene: GltfScene {
+                objects: Vec::new(),
+                lights: Vec::new(),
+                cameras: Vec::new(),
+            },
+        };
+        let json = &document.json;
+        let scene = &json["scenes"][json["scene"].as_usize().unwrap_or(0)];
+        let roots: Vec<usize> = if scene.is_null() {
+            // Without scenes, draw every node that is no other node's child.
+            let children: Vec<usize> = json["nodes"]
+                .members()
+                .iter()
+                .flat_map(|node| node["children"].members())
+                .filter_map(Json::as_usize)
+                .collect();
+            (0..json["nodes"].members().len())
+                .filter(|node| !children.contains(node))
+                .collect()
+        } else {
+            scene["nodes"]
+                .members()
+                .iter()
+                .filter_map(Json::as_usize)
+                .collect()
+        };
+        for root in roots {
+            loader.node(root, &Transform3::identity(), 0)?;
+        }
+        Ok(loader.scene)
+    }
+    // A world with a BVH over all objects.
+    pub fn into_world(mut self) -> World {
+        assert!(!self.objects.is_empty(), "scene has no objects");
+        let length = self.objects.len();
+        World {
+            root: Arc::new(BVHNode::new(&mut self.objects, 0, length)),
+            lights: self.lights,
+            media: Vec::new(),
+        }
+    }
+}
+
+fn decode_base64(text: &str) -> Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut bits, mut count) = (0u32, 0);
+    for c in text
+        .bytes()
+        .filter(|&c| c != b'=' && !c.is_ascii_whitespace())
+    {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return Err(invalid("gltf", "bad base64 data")),
+        };
+        bits = bits << 6 | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+            bits &= (1 << count) - 1;
+        }
+    }
+    Ok(bytes)
+}
+
+// Undoes the %XX escapes of a relative URI.
+fn decode_percent(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Result<u32> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| invalid("gltf", "unexpected end of file"))
+}
+
+fn read_component(bytes: &[u8], component_type: usize, normalized: bool) -> f64 {
+    let (value, max) = match component_type {
+        5120 => (bytes[0] as i8 as f64, 127.0),
+        5121 => (bytes[0] as f64, 255.0),
+        5122 => (i16::from_le_bytes([bytes[0], bytes[1]]) as f64, 32767.0),
+        5123 => (u16::from_le_bytes([bytes[0], bytes[1]]) as f64, 65535.0),
+        5125 => (
+            u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+            1.0,
+        ),
+        _ => (
+            f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+            1.0,
+        ),
+    };
+    if normalized {
+        (value / max).max(-1.0)
+    } else {
+        value
+    }
+}
+
+fn component_size(component_type: usize) -> Result<usize> {
+    match component_type {
+        5120 | 5121 => Ok(1),
+        5122 | 5123 => Ok(2),
+        5125 | 5126 => Ok(4),
+        _ => Err(invalid("gltf", "unknown component type")),
+    }
+}
+
+// The JSON of a file with its buffers loaded.
+struct Document {
+    json: Json,
+    buffers: Vec<Vec<u8>>,
+    base: PathBuf,
+}
+
+impl Document {
+    fn parse(bytes: &[u8], base: &Path) -> Result<Self> {
+        let (text, binary) = if bytes.starts_with(b"glTF") {
+            if u32_at(bytes, 4)? != 2 {
+                return Err(invalid("gltf", "only glb version 2 is supported"));
+            }
+            let length = (u32_at(bytes, 8)? as usize).min(bytes.len());
+            let (mut text, mut binary) = (None, None);
+            let mut offset = 12;
+            while offset + 8 <= length {
+                let size = u32_at(bytes, offset)? as usize;
+                let kind = u32_at(bytes, offset + 4)?;
+                let chunk = bytes
+                    .get(offset + 8..offset + 8 + size)
+                    .ok_or_else(|| invalid("gltf", "chunk out of range"))?;
+                match kind {
+                    0x4e4f_534a => text = Some(chunk),
+                    0x004e_4942 => binary = Some(chunk.to_vec()),
+                    _ => {}
+                }
+                offset += 8 + size;
+            }
+            (
+                text.ok_or_else(|| invalid("gltf", "glb without json"))?,
+                binary,
+            )
+        } else {
+            (bytes, None)
+        };
+        let text = std::str::from_utf8(text).map_err(|_| invalid("gltf", "json is not utf-8"))?;
+        let mut document = Self {
+            json: Json::parse(text)?,
+            buffers: Vec::new(),
+            base: base.to_path_buf(),
+        };
+        let mut binary = binary;
+        for buffer in document.json["buffers"].members() {
+            let data = match buffer["uri"].as_str() {
+                Some(uri) => document.read_uri(uri)?,
+                // Only the first buffer of a glb may lack a URI.
+                None => binary
+                    .take()
+                    .ok_or_else(|| invalid("gltf", "buffer without data"))?,
+            };
+            document.buffers.push(data);
+        }
+        Ok(document)
+    }
+    fn read_uri(&self, uri: &str) -> Result<Vec<u8>> {
+        if uri.starts_with("data:") {
+            let start = uri
+                .find(";base64,")
+                .ok_or_else(|| invalid("gltf", "data uri is not base64"))?;
+            decode_base64(&uri[start + 8..])
+        } else {
+            fs::read(self.base.join(decode_percent(uri)))
+        }
+    }
+    // The bytes of a buffer view and its stride, if interleaved.
+    fn buffer_view(&self, index: usize) -> Result<(&[u8], Option<usize>)> {
+        let view = &self.json["bufferViews"][index];
+        let buffer = view["buffer"]
+            .as_usize()
+            .and_then(|buffer| self.buffers.get(buffer))
+            .ok_or_else(|| invalid("gltf", "buffer view without a buffer"))?;
+        let offset = view["byteOffset"].as_usize().unwrap_or(0);
+        let length = view["byteLength"]
+            .as_usize()
+            .ok_or_else(|| invalid("gltf", "buffer view without a length"))?;
+        let data = offset
+            .checked_add(length)
+            .and_then(|end| buffer.get(offset..end))
+            .ok_or_else(|| invalid("gltf", "buffer view out of range"))?;
+        Ok((data, view["byteStride"].as_usize()))
+    }
+    // Reads `count` elements of `components` values each, `stride` bytes
+    // apart, starting at `offset` of a buffer view.
+    #[allow(clippy::too_many_arguments)]
+    fn read_elements(
+        &self,
+        view: usize,
+        offset: usize,
+        stride: Option<usize>,
+        count: usize,
+        components: usize,
+        component_type: usize,
+        normalized: bool,
+    ) -> Result<Vec<f64>> {
+        let (data, view_stride) = self.buffer_view(view)?;
+        let size = component_size(component_type)?;
+        let stride = stride.or(view_stride).unwrap_or(size * components);
+        // The sizes come from the file, so the last element is checked to lie
+        // within the view before anything is allocated.
+        let end = count
+            .checked_sub(1)
+            .and_then(|last| last.checked_mul(stride))
+            .and_then(|start| start.checked_add(offset))
+            .and_then(|start| start.checked_add(components.checked_mul(size)?));
+        match end {
+            Some(end) if end <= data.len() => {}
+            None if count == 0 => return Ok(Vec::new()),
+            _ => return Err(invalid("gltf", "accessor out of range")),
+        }
+        let mut values = Vec::with_capacity(count * components);
+        for i in 0..count {
+            for c in 0..components {
+                let start = offset + i * stride + c * size;
+                values.push(read_component(
+                    &data[start..start + size],
+                    component_type,
+                    normalized,
+                ));
+            }
+        }
+        Ok(values)
+    }
+    // The values of an accessor, flattened, and the number per element.
+    fn accessor(&self, index: usize) -> Result<(Vec<f64>, usize)> {
+        let accessor = &self.json["accessors"][index];
+        let components = match accessor["type"].as_str() {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") => 4,
+            _ => return Err(invalid("gltf", "unsupported accessor type")),
+        };
+        let component_type = accessor["componentType"]
+            .as_usize()
+            .ok_or_else(|| invalid("gltf", "accessor without a component type"))?;
+        let count = accessor["count"]
+            .as_usize()
+            .ok_or_else(|| invalid("gltf", "accessor without a count"))?;
+        let normalized = accessor["normalized"].as_bool().unwrap_or(false);
+        let offset = accessor["byteOffset"].as_usize().unwrap_or(0);
+        let mut values = match accessor["bufferView"].as_usize() {
+            Some(view) => self.read_elements(
+                view,
+                offset,
+                None,
+                count,
+                components,
+                component_type,
+                normalized,
+            )?,
+            None => vec![0.0; count * components],
+        };
+        let sparse = &accessor["sparse"];
+        if let Some(sparse_count) = sparse["count"].as_usize() {
+            let indices = &sparse["indices"];
+            let replaced = self.read_elements(
+                indices["bufferView"].as_usize().unwrap_or(0),
+                indices["byteOffset"].as_usize().unwrap_or(0),
+                None,
+                sparse_count,
+                1,
+                indices["componentType"].as_usize().unwrap_or(5125),
+                false,
+            )?;
+            let replacements = self.read_elements(
+                sparse["values"]["bufferView"].as_usize().unwrap_or(0),
+                sparse["values"]["byteOffset"].as_usize().unwrap_or(0),
+                Some(component_size(component_type)? * components),
+                sparse_count,
+                components,
+                component_type,
+                normalized,
+            )?;
+            for (k, &element) in replaced.iter().enumerate() {
+                let element = element as usize;
+                if element >= count {
+                    return Err(invalid("gltf", "sparse index out of range"));
+                }
+                values[element * components..(element + 1) * components]
+                    .copy_from_slice(&replacements[k * components..(k + 1) * components]);
+            }
+        }
+        Ok((values, components))
+    }
+}
+
+fn node_transform(node: &Json) -> Transform3 {
+    if let Some(matrix) = node["matrix"].as_f64_vec().filter(|m| m.len() == 16) {
+        // Column major.
+        return Transform3 {
+            rows: [
+                Vec3::new(matrix[0], matrix[4], matrix[8]),
+                Vec3::new(matrix[1], matrix[5], matrix[9]),
+                Vec3::new(matrix[2], matrix[6], matrix[10]),
+            ],
+            offset: Vec3::new(matrix[12], matrix[13], matrix[14]),
+        };
+    }
+    let vector = |name: &str, default: Vec3| {
+        node[name]
+            .as_f64_vec()
+            .filter(|values| values.len() == 3)
+            .map_or(default, |values| Vec3::new(values[0], values[1], values[2]))
+    };
+    let quaternion = node["rotation"]
+        .as_f64_vec()
+        .filter(|quaternion| quaternion.len() == 4)
+        .unwrap_or_else(|| vec![0.0, 0.0, 0.0, 1.0]);
+    let length = quaternion
+        .iter()
+        .map(|c| c * c)
+        .sum::<f64>()
+        .sqrt()
+        .max(1e-12);
+    let (x, y, z, w) = (
+        quaternion[0] / length,
+        quaternion[1] / length,
+        quaternion[2] / length,
+        quaternion[3] / length,
+    );
+    let rotation = Transform3 {
+        rows: [
+            Vec3::new(
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - z * w),
+                2.0 * (x * z + y * w),
+            ),
+            Vec3::new(
+                2.0 * (x * y + z * w),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - x * w),
+            ),
+            Vec3::new(
+                2.0 * (x * z - y * w),
+                2.0 * (y * z + x * w),
+                1.0 - 2.0 * (x * x + y * y),
+            ),
+        ],
+        offset: Vec3::zero(),
+    };
+    Transform3::scale(vector("scale", Vec3::ones()))
+        .then(&rotation)
+        .then(&Transform3::translate(vector("translation", Vec3::zero())))
+}
+
+fn constant(color: Vec3) -> Arc<dyn Texture> {
+    Arc::new(ConstantTexture { color })
+}
+
+// `texture` times `factor`, or just the factor without a texture.
+fn scaled(texture: Option<Arc<dyn Texture>>, factor: Vec3) -> Arc<dyn Texture> {
+    match texture {
+        None => constant(factor),
+        Some(texture) if factor == Vec3::ones() => texture,
+        Some(texture) => Arc::new(ArithmeticTexture {
+            op: Arithmetic::Multiply,
+            a: texture,
+            b: constant(factor),
+        }),
+    }
+}
+
+fn factor3(json: &Json, default: Vec3) -> Vec3 {
+    json.as_f64_vec()
+        .filter(|v| v.len() >= 3)
+        .map_or(default, |v| Vec3::new(v[0], v[1], v[2]))
+}
+
+struct Loader<'a> {
+    document: &'a Document,
+    width: u32,
+    subdivision: usize,
+    textures: HashMap<(usize, bool), Arc<dyn Texture>>,
+    // Each material and whether it glows.
+    materials: HashMap<Option<usize>, (Arc<dyn Material>, bool)>,
+    scene: GltfScene,
+}
+
+impl<'a> Loader<'a> {
+    fn node(&mut self, index: usize, parent: &Transform3, depth: usize) -> Result<()> {
+        if depth > 256 {
+            return Err(invalid("gltf", "node hierarchy is cyclic or too deep"));
+        }
+        let document = self.document;
+        let json = &document.json;
+        let node = &json["nodes"][index];
+        if node.is_null() {
+            return Err(invalid("gltf", "node out of range"));
+        }
+        let transform = node_transform(node).then(parent);
+        if let Some(mesh) = node["mesh"].as_usize() {
+            for primitive in json["meshes"][mesh]["primitives"].members() {
+                self.primitive(primitive, &transform)?;
+            }
+        }
+        if let Some(camera) = node["camera"].as_usize() {
+            self.camera(&json["cameras"][camera], &transform);
+        }
+        if let Some(light) = node["extensions"]["KHR_lights_punctual"]["light"].as_usize() {
+            let light = &json["extensions"]["KHR_lights_punctual"]["lights"][light];
+            self.light(light, &transform)?;
+        }
+        for child in node["children"].members() {
+            let child = child
+                .as_usize()
+                .ok_or_else(|| invalid("gltf", "bad child index"))?;
+            self.node(child, &transform, depth + 1)?;
+        }
+        Ok(())
+    }
+    fn primitive(&mut self, primitive: &Json, transform: &Transform3) -> Result<()> {
+        let mode = primitive["mode"].as_usize().unwrap_or(4);
+        if mode < 4 {
+            // Points and lines have no surface.
+            return Ok(());
+        }
+        let document = self.document;
+        let attributes = &primitive["attributes"];
+        let attribute = |name: &str| -> Result<Option<(Vec<f64>, usize)>> {
+            match attributes[name].as_usize() {
+                Some(accessor) => document.accessor(accessor).map(Some),
+                None => Ok(None),
+            }
+        };
+        let (positions, _) =
+            attribute("POSITION")?.ok_or_else(|| invalid("gltf", "primitive without positions"))?;
+        let mut mesh = TriangleMesh {
+            positions: positions
+                .chunks_exact(3)
+                .map(|p| transform.apply(Vec3::new(p[0], p[1], p[2])))
+                .collect(),
+            ..TriangleMesh::default()
+        };
+        let vertices = mesh.positions.len();
+        // Normals transform by the inverse transpose.
+        let inverse = transform.inverse();
+        if let Some((normals, _)) = attribute("NORMAL")? {
+            mesh.normals = normals
+                .chunks_exact(3)
+                .map(|n| {
+                    let n = inverse.rows[0].clone() * n[0]
+                        + inverse.rows[1].clone() * n[1]
+                        + inverse.rows[2].clone() * n[2];
+                    if n.squared_length() > 0.0 {
+                        n.unit()
+                    } else {
+                        n
+                    }
+                })
+                .collect();
+        }
+        if let Some((uvs, _)) = attribute("TEXCOORD_0")? {
+            // glTF puts v = 0 at the top of images.
+            mesh.uvs = uvs.chunks_exact(2).map(|uv| (uv[0], 1.0 - uv[1])).collect();
+        }
+        if let Some((colors, components)) = attribute("COLOR_0")? {
+            mesh.colors = colors
+                .chunks_exact(components)
+                .map(|c| Vec3::new(c[0], c[1], c[2]))
+                .collect();
+        }
+        if [mesh.normals.len(), mesh.uvs.len(), mesh.colors.len()]
+            .iter()
+            .any(|&n| n != 0 && n != vertices)
+        {
+            return Err(invalid("gltf", "attributes differ in count"));
+        }
+
+        let indices: Vec<usize> = match primitive["indices"].as_usize() {
+            Some(accessor) => document
+                .accessor(accessor)?
+                .0
+                .into_iter()
+                .map(|i| i as usize)
+                .collect(),
+            None => (0..vertices).collect(),
+        };
+        if indices.iter().any(|&i| i >= vertices) {
+            return Err(invalid("gltf", "index out of range"));
+        }
+        let corners = indices.len();
+        mesh.indices = match mode {
+            4 => indices
+                .chunks_exact(3)
+                .map(|f| [f[0], f[1], f[2]])
+                .collect(),
+            // Strips alternate their winding.
+            5 => (0..corners.saturating_sub(2))
+                .map(|k| {
+                    if k % 2 == 0 {
+                        [indices[k], indices[k + 1], indices[k + 2]]
+                    } else {
+                        [indices[k + 1], indices[k], indices[k + 2]]
+                    }
+                })
+                .collect(),
+            6 => (1..corners.saturating_sub(1))
+                .map(|k| [indices[0], indices[k], indices[k + 1]])
+                .collect(),
+            _ => return Err(invalid("gltf", "unknown primitive mode")),
+        };
+        // Mirroring transforms turn the winding around.
+        let [a, b, c] = &transform.rows;
+        if a.clone() * Vec3::cross(b.clone(), c.clone()) < 0.0 {
+            for face in &mut mesh.indices {
+                face.swap(1, 2);
+            }
+        }
+        if mesh.indices.is_empty() {
+            return Ok(());
+        }
+        if self.subdivision > 0 {
+            mesh = mesh.subdivide(self.subdivision);
+        }
+
+        let (material, emissive) = self.material(primitive["material"].as_usize())?;
+        let mut triangles = mesh.into_triangles(material);
+        if emissive {
+            self.scene.lights.extend(triangles.iter().cloned());
+        }
+        let length = triangles.len();
+        self.scene
+            .objects
+            .push(Arc::new(BVHNode::new(&mut triangles, 0, length)));
+        Ok(())
+    }
+    fn texture(&mut self, info: &Json, srgb: bool) -> Result<Option<Arc<dyn Texture>>> {
+        let index = match info["index"].as_usize() {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+        if let Some(texture) = self.textures.get(&(index, srgb)) {
+            return Ok(Some(texture.clone()));
+        }
+        let document = self.document;
+        let json = &document.json;
+        let texture = &json["textures"][index];
+        let source = texture["source"]
+            .as_usize()
+            .ok_or_else(|| invalid("gltf", "texture without an image"))?;
+        let image = &json["images"][source];
+        let bytes = match (image["uri"].as_str(), image["bufferView"].as_usize()) {
+            (Some(uri), _) => self.document.read_uri(uri)?,
+            (None, Some(view)) => self.document.buffer_view(view)?.0.to_vec(),
+            _ => return Err(invalid("gltf", "image without data")),
+        };
+        let decoded = image::load_from_memory(&bytes)
+            .map_err(|error| invalid("gltf", &format!("image {}: {}", source, error)))?;
+        let sampler = &json["samplers"][texture["sampler"].as_usize().unwrap_or(usize::MAX)];
+        let wrap = |name: &str| match sampler[name].as_usize() {
+            Some(33071) => WrapMode::Clamp,
+            Some(33648) => WrapMode::Mirror,
+            _ => WrapMode::Repeat,
+        };
+        let wrap = (wrap("wrapS"), wrap("wrapT"));
+        let mut image_texture = ImageTexture::from_image(&decoded, srgb, wrap);
+        if sampler["magFilter"].as_usize() == Some(9728) {
+            image_texture.filter = Filter::Nearest;
+        }
+        let image_texture: Arc<dyn Texture> = Arc::new(image_texture);
+        self.textures.insert((index, srgb), image_texture.clone());
+        Ok(Some(image_texture))
+    }
+    fn material(&mut self, index: Option<usize>) -> Result<(Arc<dyn Material>, bool)> {
+        if let Some(material) = self.materials.get(&index) {
+            return Ok(material.clone());
+        }
+        // A missing material reads as all defaults.
+        let document = self.document;
+        let json = &document.json;
+        let material = &json["materials"][index.unwrap_or(usize::MAX)];
+        let pbr = &material["pbrMetallicRoughness"];
+        let extensions = &material["extensions"];
+
+        let base_color = scaled(
+            self.texture(&pbr["baseColorTexture"], true)?,
+            factor3(&pbr["baseColorFactor"], Vec3::ones()),
+        );
+        let mut disney = DisneyMaterial::new(Arc::new(ArithmeticTexture {
+            op: Arithmetic::Multiply,
+            a: base_color,
+            b: Arc::new(VertexColorTexture {
+                fallback: constant(Vec3::ones()),
+            }),
+        }));
+        // Roughness is in the green and metalness in the blue channel.
+        let metallic_roughness = self.texture(&pbr["metallicRoughnessTexture"], false)?;
+        let channel = |channel: Channel| -> Option<Arc<dyn Texture>> {
+            metallic_roughness
+                .clone()
+                .map(|texture| -> Arc<dyn Texture> {
+                    Arc::new(ChannelTexture { texture, channel })
+                })
+        };
+        disney.metallic = scaled(
+            channel(Channel::Blue),
+            Vec3::ones() * pbr["metallicFactor"].as_f64().unwrap_or(1.0),
+        );
+        disney.roughness = scaled(
+            channel(Channel::Green),
+            Vec3::ones() * pbr["roughnessFactor"].as_f64().unwrap_or(1.0),
+        );
+        let transmission = &extensions["KHR_materials_transmission"];
+        if !transmission.is_null() {
+            disney.transmission = scaled(
+                self.texture(&transmission["transmissionTexture"], false)?
+                    .map(|texture| -> Arc<dyn Texture> {
+                        Arc::new(ChannelTexture {
+                            texture,
+                            channel: Channel::Red,
+                        })
+                    }),
+                Vec3::ones() * transmission["transmissionFactor"].as_f64().unwrap_or(0.0),
+            );
+        }
+        if let Some(ior) = extensions["KHR_materials_ior"]["ior"].as_f64() {
+            disney.ior = ior;
+        }
+
+        let mut result: Arc<dyn Material> = Arc::new(disney);
+        let normal = &material["normalTexture"];
+        if let Some(map) = self.texture(normal, false)? {
+            result = Arc::new(NormalMap {
+                material: result,
+                map,
+                strength: normal["scale"].as_f64().unwrap_or(1.0),
+            });
+        }
+        let strength = extensions["KHR_materials_emissive_strength"]["emissiveStrength"]
+            .as_f64()
+            .unwrap_or(1.0);
+        let emissive = factor3(&material["emissiveFactor"], Vec3::zero()) * strength;
+        let glows = emissive != Vec3::zero();
+        if glows {
+            result = Arc::new(Emissive {
+                material: result,
+                emit: scaled(self.texture(&material["emissiveTexture"], true)?, emissive),
+            });
+        }
+        self.materials.insert(index, (result.clone(), glows));
+        Ok((result, glows))
+    }
+    fn camera(&mut self, camera: &Json, transform: &Transform3) {
+        let perspective = &camera["perspective"];
+        let yfov = match perspective["yfov"].as_f64() {
+            Some(yfov) => yfov,
+            None => return,
+        };
+        // Cameras look down their -z axis with +y up.
+        let position = transform.apply(Vec3::zero());
+        let forward = transform.apply(Vec3::new(0.0, 0.0, -1.0)) - position.clone();
+        let up = transform.apply(Vec3::new(0.0, 1.0, 0.0)) - position.clone();
+        self.scene.cameras.push(Camera::new(
+            yfov,
+            perspective["aspectRatio"].as_f64().unwrap_or(1.0),
+            self.width,
+            position.clone(),
+            position + forward.unit(),
+            up.unit(),
+            0.0,
+        ));
+    }
+    fn light(&mut self, light: &Json, transform: &Transform3) -> Result<()> {
+        let kind = light["type"].as_str().unwrap_or("");
+        if kind == "directional" {
+            return Err(invalid("gltf", "directional lights are not supported"));
+        }
+        if kind != "point" && kind != "spot" {
+            return Err(invalid("gltf", &format!("unknown light type {:?}", kind)));
+        }
+        // A sphere of radiance L has an intensity of L * pi * r^2 in every
+        // direction; intensities are in candela.
+        let radius = PUNCTUAL_LIGHT_RADIUS;
+        let intensity = light["intensity"].as_f64().unwrap_or(1.0);
+        let radiance =
+            factor3(&light["color"], Vec3::ones()) * (intensity / (PI * radius * radius));
+        let center = transform.apply(Vec3::zero());
+        let sphere = Sphere {
+            center: center.clone(),
+            radius,
+            mat_ptr: Arc::new(DiffuseLight {
+                emit: constant(radiance.clone()),
+            }),
+        };
+        let emitter: Arc<dyn Hitable> = if kind == "spot" {
+            // Spot lights shine down their node's -z axis.
+            let spot = &light["spot"];
+            Arc::new(SpotLight {
+                sphere,
+                radiance,
+                direction: (transform.apply(Vec3::new(0.0, 0.0, -1.0)) - center).unit(),
+                cos_inner: spot["innerConeAngle"].as_f64().unwrap_or(0.0).cos(),
+                cos_outer: spot["outerConeAngle"].as_f64().unwrap_or(PI / 4.0).cos(),
+            })
+        } else {
+            Arc::new(sphere)
+        };
+        self.scene.objects.push(emitter.clone());
+        self.scene.lights.push(emitter);
+        Ok(())
+    }
+}
+
+// The sphere standing in for a spot light, glowing with `radiance` towards
+// directions within the cone around `direction` and fading out between
+// the inner and outer cone angles as KHR_lights_punctual recommends.
+pub struct SpotLight {
+    pub sphere: Sphere,
+    pub radiance: Vec3,
+    pub direction: Vec3,
+    pub cos_inner: f64,
+    pub cos_outer: f64,
+}
+
+impl SpotLight {
+    fn falloff(&self, towards: &Vec3) -> f64 {
+        let cosine = towards.unit() * self.direction.unit();
+        let scale = 1.0 / (self.cos_inner - self.cos_outer).max(0.001);
+        let attenuation = ((cosine - self.cos_outer) * scale).max(0.0).min(1.0);
+        attenuation * attenuation
+    }
+}
+
+impl Hitable for SpotLight {
+    // What leaves the sphere depends on where it goes, back along the ray.
+    fn hit(&self, ra: &Ray, t_min: f64, t_max: f64) -> Option<HitResult> {
+        let mut hit_result = self.sphere.hit(ra, t_min, t_max)?;
+        let falloff = self.falloff(&-ra.direction.clone());
+        hit_result.mat_ptr = Arc::new(DiffuseLight {
+            emit: constant(self.radiance.clone() * falloff),
+        });
+        Some(hit_result)
+    }
+    fn bounding_box(&self) -> Option<AABB> {
+        self.sphere.bounding_box()
+    }
+    fn pdf_value(&self, o: Vec3, v: Vec3) -> f64 {
+        self.sphere.pdf_value(o, v)
+    }
+    fn random(&self, o: Vec3) -> Vec3 {
+        self.sphere.random(o)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_gltf() {
+        assert_eq!(decode_base64("aGVsbG8=").unwrap(), b"hello");
+        assert_eq!(decode_percent("my%20mesh.bin"), "my mesh.bin");
+
+        // One triangle in the xy plane, its node moved up by the parent and
+        // spun half a turn about y, a camera and a point light.
+        let json = r#"{
+            "asset": {"version": "2.0"},
+            "scene": 0,
+            "scenes": [{"nodes": [0]}],
+            "nodes": [
+                {"translation": [0, 1, 0], "children": [1, 2, 3, 4]},
+                {"mesh": 0, "rotation": [0, 1, 0, 0]},
+                {"camera": 0, "translation": [0, 0, 5]},
+                {"extensions": {"KHR_lights_punctual": {"light": 0}}},
+                {"extensions": {"KHR_lights_punctual": {"light": 1}}}
+            ],
+            "meshes": [{"primitives": [{
+                "attributes": {"POSITION": 0}, "indices": 1, "material": 0
+            }]}],
+            "materials": [{"emissiveFactor": [1, 0.5, 0]}],
+            "cameras": [{"type": "perspective",
+                "perspective": {"yfov": 0.8, "aspectRatio": 2.0, "znear": 0.1}}],
+            "extensions": {"KHR_lights_punctual": {"lights": [
+                {"type": "point", "intensity": 2.0},
+                {"type": "spot", "spot": {"outerConeAngle": 0.5}}
+            ]}},
+            "accessors": [
+                {"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"},
+                {"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}
+            ],
+            "bufferViews": [
+                {"buffer": 0, "byteOffset": 0, "byteLength": 36},
+                {"buffer": 0, "byteOffset": 36, "byteLength": 6}
+            ],
+            "buffers": [{"byteLength": 42}]
+        }"#;
+        let mut binary = Vec::new();
+        for &v in &[0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            binary.extend_from_slice(&v.to_le_bytes());
+        }
+        for &i in &[0u16, 1, 2] {
+            binary.extend_from_slice(&i.to_le_bytes());
+        }
+        binary.extend_from_slice(&[0, 0]);
+        let glb = |json: &str| {
+            let mut text = json.as_bytes().to_vec();
+            while text.len() % 4 != 0 {
+                text.push(b' ');
+            }
+            let mut glb = b"glTF".to_vec();
+            glb.extend_from_slice(&2u32.to_le_bytes());
+            glb.extend_from_slice(&((28 + text.len() + binary.len()) as u32).to_le_bytes());
+            glb.extend_from_slice(&(text.len() as u32).to_le_bytes());
+            glb.extend_from_slice(b"JSON");
+            glb.extend_from_slice(&text);
+            glb.extend_from_slice(&(binary.len() as u32).to_le_bytes());
+            glb.extend_from_slice(b"BIN\0");
+            glb.extend_from_slice(&binary);
+            glb
+        };
+
+        let scene = GltfScene::parse(&glb(json), Path::new(""), 100, 0).unwrap();
+        assert_eq!(scene.objects.len(), 3);
+        // The emissive triangle and the light spheres.
+        assert_eq!(scene.lights.len(), 3);
+        let camera = &scene.cameras[0];
+        assert!((camera.position.clone() - Vec3::new(0.0, 1.0, 5.0)).length() < 1e-9);
+        assert_eq!(camera.height, 50);
+
+        // The turned triangle covers negative x and faces -z, so it glows
+        // only seen from behind.
+        let ra = Ray {
+            origin: Vec3::new(-0.25, 1.25, -3.0),
+            direction: Vec3::new(0.0, 0.0, 1.0),
+        };
+        let hit = scene.objects[0].hit(&ra, 0.0, f64::INFINITY).unwrap();
+        assert!((hit.t - 3.0).abs() < 1e-6);
+        assert!(hit.front_face);
+        let glow = hit.mat_ptr.emitted(&hit, hit.fu, hit.fv, hit.p.clone());
+        assert!((glow - Vec3::new(1.0, 0.5, 0.0)).length() < 1e-9);
+
+        // The spot light shines down -z, so it is dark seen from above it.
+        let radius = PUNCTUAL_LIGHT_RADIUS;
+        let full = Vec3::ones() / (PI * radius * radius);
+        let spot_glow = |origin: Vec3, direction: Vec3| {
+            let ra = Ray { origin, direction };
+            let hit = scene.lights[2].hit(&ra, 0.0, f64::INFINITY).unwrap();
+            hit.mat_ptr.emitted(&hit, hit.fu, hit.fv, hit.p.clone())
+        };
+        let below = spot_glow(Vec3::new(0.0, 1.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!((below - full).length() < 1e-9);
+        let above = spot_glow(Vec3::new(0.0, 1.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(above, Vec3::zero());
+        let aside = spot_glow(Vec3::new(5.0, 1.0, -5.0), Vec3::new(-1.0, 0.0, 1.0));
+        assert_eq!(aside, Vec3::zero());
+
+        let directional = r#"{
+            "asset": {"version": "2.0"},
+            "nodes": [{"extensions": {"KHR_lights_punctual": {"light": 0}}}],
+            "extensions": {"KHR_lights_punctual": {"lights": [{"type": "directional"}]}}
+        }"#;
+        assert!(GltfScene::parse(directional.as_bytes(), Path::new(""), 100, 0).is_err());
+
+        // Sizes from the file that overflow are errors, not panics or huge
+        // allocations.
+        let huge_count = json.replace(
+            r#""count": 3, "type": "VEC3""#,
+            r#""count": 1e18, "type": "VEC3""#,
+        );
+        assert!(GltfScene::parse(&glb(&huge_count), Path::new(""), 100, 0).is_err());
+        let huge_offset = json.replace(r#""byteOffset": 36"#, r#""byteOffset": 1e20"#);
+        assert!(GltfScene::parse(&glb(&huge_offset), Path::new(""), 100, 0).is_err());
+    }
+}
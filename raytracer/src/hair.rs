@@ -0,0 +1,377 @@
+use crate::hit::HitResult;
+use crate::material::*;
+use crate::microfacet::fresnel_dielectric;
+use std::f64::consts::{LN_2, PI};
+
+// Scattering orders modelled separately: R, TT and TRT. Everything after
+// them is lumped into one residual lobe.
+const P_MAX: usize = 3;
+
+fn luminance(color: &Vec3) -> f64 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+fn safe_sqrt(x: f64) -> f64 {
+    x.max(0.0).sqrt()
+}
+
+fn safe_asin(x: f64) -> f64 {
+    x.max(-1.0).min(1.0).asin()
+}
+
+// Modified Bessel function of the first kind, of order zero.
+fn i0(x: f64) -> f64 {
+    let mut value = 0.0;
+    let mut x2i = 1.0;
+    let mut factorial = 1.0;
+    let mut four_i = 1.0;
+    for i in 0..10 {
+        if i > 1 {
+            factorial *= i as f64;
+        }
+        value += x2i / (four_i * factorial * factorial);
+        x2i *= x * x;
+        four_i *= 4.0;
+    }
+    value
+}
+
+fn log_i0(x: f64) -> f64 {
+    if x > 12.0 {
+        x + 0.5 * (-(2.0 * PI).ln() + (1.0 / x).ln() + 1.0 / (8.0 * x))
+    } else {
+        i0(x).ln()
+    }
+}
+
+// Longitudinal scattering with variance `v`.
+fn mp(cos_i: f64, cos_o: f64, sin_i: f64, sin_o: f64, v: f64) -> f64 {
+    let a = cos_i * cos_o / v;
+    let b = sin_i * sin_o / v;
+    if v <= 0.1 {
+        // Evaluated in log space, where the terms would overflow.
+        (log_i0(a) - b - 1.0 / v + LN_2 + (1.0 / (2.0 * v)).ln()).exp()
+    } else {
+        (-b).exp() * i0(a) / ((1.0 / v).sinh() * 2.0 * v)
+    }
+}
+
+// Attenuation of each order, from Fresnel reflection at the surface and
+// absorption `t` along one pass through the fibre.
+fn ap(cos_o: f64, eta: f64, h: f64, t: &Vec3) -> [Vec3; P_MAX + 1] {
+    let cos_gamma_o = safe_sqrt(1.0 - h * h);
+    let f = fresnel_dielectric(cos_o * cos_gamma_o, 1.0 / eta);
+    let mut ap = [Vec3::zero(), Vec3::zero(), Vec3::zero(), Vec3::zero()];
+    ap[0] = Vec3::ones() * f;
+    ap[1] = t.clone() * ((1.0 - f) * (1.0 - f));
+    for p in 2..P_MAX {
+        ap[p] = Vec3::elemul(ap[p - 1].clone(), t.clone()) * f;
+    }
+    let tf = t.clone() * f;
+    let residual = Vec3::elemul(ap[P_MAX - 1].clone(), tf.clone());
+    ap[P_MAX] = Vec3::new(
+        residual.x / (1.0 - tf.x),
+        residual.y / (1.0 - tf.y),
+        residual.z / (1.0 - tf.z),
+    );
+    ap
+}
+
+// Azimuthal angle by which order `p` leaves the fibre.
+fn phi(p: usize, gamma_o: f64, gamma_t: f64) -> f64 {
+    let p = p as f64;
+    2.0 * p * gamma_t - 2.0 * gamma_o + p * PI
+}
+
+fn logistic(x: f64, s: f64) -> f64 {
+    let x = x.abs();
+    let e = (-x / s).exp();
+    e / (s * (1.0 + e) * (1.0 + e))
+}
+
+fn logistic_cdf(x: f64, s: f64) -> f64 {
+    1.0 / (1.0 + (-x / s).exp())
+}
+
+// The logistic distribution restricted to [-PI, PI].
+fn trimmed_logistic(x: f64, s: f64) -> f64 {
+    logistic(x, s) / (logistic_cdf(PI, s) - logistic_cdf(-PI, s))
+}
+
+fn sample_trimmed_logistic(u: f64, s: f64) -> f64 {
+    let k = logistic_cdf(PI, s) - logistic_cdf(-PI, s);
+    let x = -s * (1.0 / (u * k + logistic_cdf(-PI, s)) - 1.0).ln();
+    x.max(-PI).min(PI)
+}
+
+// Azimuthal scattering of order `p` for a turn of `dphi` around the fibre.
+fn np(dphi: f64, p: usize, s: f64, gamma_o: f64, gamma_t: f64) -> f64 {
+    let mut dphi = dphi - phi(p, gamma_o, gamma_t);
+    while dphi > PI {
+        dphi -= 2.0 * PI;
+    }
+    while dphi < -PI {
+        dphi += 2.0 * PI;
+    }
+    trimmed_logistic(dphi, s)
+}
+
+// Hair fibre scattering after Chiang et al. (2016), as in pbrt: each order
+// is a longitudinal lobe, shifted by the tilt of the cuticle scales, times
+// its attenuation and an azimuthal lobe. Meant for `Curve`s, whose `fv`
+// across the width tells where the ray entered the fibre. In the shading
+// frame x runs along the fibre.
+pub struct Hair {
+    // Absorption inside the fibre, relative to its diameter.
+    pub sigma_a: Vec3,
+    pub eta: f64,
+    // Longitudinal and azimuthal roughness, in [0, 1].
+    pub beta_m: f64,
+    pub beta_n: f64,
+    // Tilt of the cuticle scales, in degrees.
+    pub alpha: f64,
+}
+
+// Per-fibre quantities shared by `eval`, `sample` and `pdf`.
+struct Fibre {
+    v: [f64; P_MAX + 1],
+    s: f64,
+    sin_2k_alpha: [f64; 3],
+    cos_2k_alpha: [f64; 3],
+    h: f64,
+    gamma_o: f64,
+}
+
+impl Fibre {
+    // `sin_o` and `cos_o` tilted by the scales for order `p`.
+    fn tilt(&self, p: usize, sin_o: f64, cos_o: f64) -> (f64, f64) {
+        let (sin, cos) = match p {
+            0 => (
+                sin_o * self.cos_2k_alpha[1] - cos_o * self.sin_2k_alpha[1],
+                cos_o * self.cos_2k_alpha[1] + sin_o * self.sin_2k_alpha[1],
+            ),
+            1 => (
+                sin_o * self.cos_2k_alpha[0] + cos_o * self.sin_2k_alpha[0],
+                cos_o * self.cos_2k_alpha[0] - sin_o * self.sin_2k_alpha[0],
+            ),
+            2 => (
+                sin_o * self.cos_2k_alpha[2] + cos_o * self.sin_2k_alpha[2],
+                cos_o * self.cos_2k_alpha[2] - sin_o * self.sin_2k_alpha[2],
+            ),
+            _ => (sin_o, cos_o),
+        };
+        (sin, cos.abs())
+    }
+}
+
+impl Hair {
+    // Absorption of hair with the given concentrations of the two melanins;
+    // eumelanin around 8 gives black hair, 1.3 brown and 0.3 blonde, while
+    // pheomelanin adds red.
+    pub fn sigma_a_from_melanin(eumelanin: f64, pheomelanin: f64) -> Vec3 {
+        Vec3::new(0.419, 0.697, 1.37) * eumelanin + Vec3::new(0.187, 0.4, 1.05) * pheomelanin
+    }
+    // Absorption that makes hair of roughness `beta_n` come out roughly as
+    // `color` after multiple scattering.
+    pub fn sigma_a_from_color(color: Vec3, beta_n: f64) -> Vec3 {
+        let b = beta_n;
+        let d = 5.969 - 0.215 * b + 2.532 * b.powi(2) - 10.73 * b.powi(3)
+            + 5.574 * b.powi(4)
+            + 0.245 * b.powi(5);
+        let channel = |c: f64| (c.max(1e-4).ln() / d).powi(2);
+        Vec3::new(channel(color.x), channel(color.y), channel(color.z))
+    }
+    fn fibre(&self, hit_record: &HitResult, wo: &Vec3) -> Fibre {
+        let beta_m = self.beta_m;
+        let v0 = (0.726 * beta_m + 0.812 * beta_m.powi(2) + 3.7 * beta_m.powi(20)).powi(2);
+        let beta_n = self.beta_n;
+        let s =
+            (PI / 8.0).sqrt() * (0.265 * beta_n + 1.194 * beta_n.powi(2) + 5.372 * beta_n.powi(22));
+        let mut sin_2k_alpha = [self.alpha.to_radians().sin(), 0.0, 0.0];
+        let mut cos_2k_alpha = [safe_sqrt(1.0 - sin_2k_alpha[0].powi(2)), 0.0, 0.0];
+        for i in 1..3 {
+            sin_2k_alpha[i] = 2.0 * cos_2k_alpha[i - 1] * sin_2k_alpha[i - 1];
+            cos_2k_alpha[i] = cos_2k_alpha[i - 1].powi(2) - sin_2k_alpha[i - 1].powi(2);
+        }
+        // The offset from the axis is signed towards wo x tangent, which is
+        // the same side for any frame around the fibre.
+        let dpdv = hit_record.shading_frame().to_local(hit_record.dpdv.clone());
+        let h = (2.0 * hit_record.fv - 1.0).max(-1.0).min(1.0);
+        let h = if dpdv.y * wo.z - dpdv.z * wo.y < 0.0 {
+            -h
+        } else {
+            h
+        };
+        Fibre {
+            v: [v0, 0.25 * v0, 4.0 * v0, 4.0 * v0],
+            s,
+            sin_2k_alpha,
+            cos_2k_alpha,
+            h,
+            gamma_o: safe_asin(h),
+        }
+    }
+    // Transmittance of one pass through the fibre and the refracted azimuth.
+    fn refraction(&self, fibre: &Fibre, sin_o: f64, cos_o: f64) -> (Vec3, f64) {
+        let sin_t = sin_o / self.eta;
+        let cos_t = safe_sqrt(1.0 - sin_t * sin_t);
+        let etap = safe_sqrt(self.eta * self.eta - sin_o * sin_o) / cos_o;
+        let sin_gamma_t = fibre.h / etap;
+        let cos_gamma_t = safe_sqrt(1.0 - sin_gamma_t * sin_gamma_t);
+        let length = 2.0 * cos_gamma_t / cos_t;
+        let t = Vec3::new(
+            (-self.sigma_a.x * length).exp(),
+            (-self.sigma_a.y * length).exp(),
+            (-self.sigma_a.z * length).exp(),
+        );
+        (t, safe_asin(sin_gamma_t))
+    }
+    // How likely each order is to be picked when sampling.
+    fn order_pdf(&self, fibre: &Fibre, sin_o: f64, cos_o: f64) -> [f64; P_MAX + 1] {
+        let (t, _) = self.refraction(fibre, sin_o, cos_o);
+        let ap = ap(cos_o, self.eta, fibre.h, &t);
+        let total: f64 = ap.iter().map(luminance).sum();
+        let mut pdf = [0.0; P_MAX + 1];
+        for p in 0..=P_MAX {
+            pdf[p] = if total > 0.0 {
+                luminance(&ap[p]) / total
+            } else {
+                1.0 / (P_MAX + 1) as f64
+            };
+        }
+        pdf
+    }
+}
+
+impl Material for Hair {
+    fn eval(&self, hit_record: &HitResult, wo: &Vec3, wi: &Vec3) -> Vec3 {
+        let fibre = self.fibre(hit_record, wo);
+        let sin_o = wo.x;
+        let cos_o = safe_sqrt(1.0 - sin_o * sin_o);
+        let sin_i = wi.x;
+        let cos_i = safe_sqrt(1.0 - sin_i * sin_i);
+        let dphi = wi.z.atan2(wi.y) - wo.z.atan2(wo.y);
+        let (t, gamma_t) = self.refraction(&fibre, sin_o, cos_o);
+        let ap = ap(cos_o, self.eta, fibre.h, &t);
+
+        // The fibre model already includes the cosine, so this is f cos.
+        let mut f = Vec3::zero();
+        for (p, attenuation) in ap.iter().enumerate().take(P_MAX) {
+            let (sin_op, cos_op) = fibre.tilt(p, sin_o, cos_o);
+            f += attenuation.clone()
+                * (mp(cos_i, cos_op, sin_i, sin_op, fibre.v[p])
+                    * np(dphi, p, fibre.s, fibre.gamma_o, gamma_t));
+        }
+        f + ap[P_MAX].clone() * (mp(cos_i, cos_o, sin_i, sin_o, fibre.v[P_MAX]) / (2.0 * PI))
+    }
+    fn sample(&self, hit_record: &HitResult, wo: &Vec3, u: (f64, f64)) -> Option<ScatterRecord> {
+        let fibre = self.fibre(hit_record, wo);
+        let sin_o = wo.x;
+        let cos_o = safe_sqrt(1.0 - sin_o * sin_o);
+        let order_pdf = self.order_pdf(&fibre, sin_o, cos_o);
+
+        // Pick an order, then reuse what is left of u.0 within it.
+        let mut remainder = u.0;
+        let mut p = 0;
+        while p < P_MAX && remainder >= order_pdf[p] {
+            remainder -= order_pdf[p];
+            p += 1;
+        }
+        let remainder = (remainder / order_pdf[p]).max(0.0).min(1.0 - 1e-9);
+
+        let (sin_op, cos_op) = fibre.tilt(p, sin_o, cos_o);
+        let v = fibre.v[p];
+        // u.1 also gives the azimuth about the tilted cone.
+        let (u1, u_phi) = demux(u.1);
+        let u1 = u1.max(1e-5);
+        let cos_theta = 1.0 + v * (u1 + (1.0 - u1) * (-2.0 / v).exp()).ln();
+        let sin_theta = safe_sqrt(1.0 - cos_theta * cos_theta);
+        let cos_phi = (2.0 * PI * u_phi).cos();
+        let sin_i = -cos_theta * sin_op + sin_theta * cos_phi * cos_op;
+        let cos_i = safe_sqrt(1.0 - sin_i * sin_i);
+
+        let (_, gamma_t) = self.refraction(&fibre, sin_o, cos_o);
+        let dphi = if p < P_MAX {
+            phi(p, fibre.gamma_o, gamma_t) + sample_trimmed_logistic(remainder, fibre.s)
+        } else {
+            2.0 * PI * remainder
+        };
+        let phi_i = wo.z.atan2(wo.y) + dphi;
+        let wi = Vec3::new(sin_i, cos_i * phi_i.cos(), cos_i * phi_i.sin());
+        let pdf = self.pdf(hit_record, wo, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        let flags = if wi.z * wo.z > 0.0 {
+            Lobe::REFLECTION
+        } else {
+            Lobe::TRANSMISSION
+        };
+        Some(ScatterRecord {
+            f: self.eval(hit_record, wo, &wi),
+            wi,
+            pdf,
+            flags: flags | Lobe::GLOSSY,
+        })
+    }
+    fn pdf(&self, hit_record: &HitResult, wo: &Vec3, wi: &Vec3) -> f64 {
+        let fibre = self.fibre(hit_record, wo);
+        let sin_o = wo.x;
+        let cos_o = safe_sqrt(1.0 - sin_o * sin_o);
+        let sin_i = wi.x;
+        let cos_i = safe_sqrt(1.0 - sin_i * sin_i);
+        let dphi = wi.z.atan2(wi.y) - wo.z.atan2(wo.y);
+        let order_pdf = self.order_pdf(&fibre, sin_o, cos_o);
+        let (_, gamma_t) = self.refraction(&fibre, sin_o, cos_o);
+
+        let mut pdf = 0.0;
+        for (p, probability) in order_pdf.iter().enumerate().take(P_MAX) {
+            let (sin_op, cos_op) = fibre.tilt(p, sin_o, cos_o);
+            pdf += mp(cos_i, cos_op, sin_i, sin_op, fibre.v[p])
+                * probability
+                * np(dphi, p, fibre.s, fibre.gamma_o, gamma_t);
+        }
+        pdf + mp(cos_i, cos_o, sin_i, sin_o, fibre.v[P_MAX]) * order_pdf[P_MAX] / (2.0 * PI)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hit::Ray;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_hair_sampling() {
+        // Without absorption no energy is lost, and sampling must agree with
+        // `eval` and `pdf`.
+        let hair = Arc::new(Hair {
+            sigma_a: Vec3::zero(),
+            eta: 1.55,
+            beta_m: 0.3,
+            beta_n: 0.3,
+            alpha: 2.0,
+        });
+        let ray = Ray {
+            origin: Vec3::new(0.0, 0.0, 1.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+        };
+        let mut hit = HitResult::new(&ray, 1.0, Vec3::new(0.0, 0.0, 1.0), 0.5, 0.3, hair.clone());
+        hit.dpdu = Vec3::new(1.0, 0.0, 0.0);
+        hit.dpdv = Vec3::new(0.0, 1.0, 0.0);
+        let wo = Vec3::new(0.3, 0.2, 0.9).unit();
+
+        let samples = 20000;
+        let mut total = 0.0;
+        for i in 0..samples {
+            let u = ((i as f64 + 0.5) / samples as f64, random_double());
+            let scatter = hair.sample(&hit, &wo, u).unwrap();
+            let f = hair.eval(&hit, &wo, &scatter.wi);
+            assert!((f.clone() - scatter.f.clone()).length() < 1e-9);
+            assert!((hair.pdf(&hit, &wo, &scatter.wi) - scatter.pdf).abs() < 1e-9);
+            total += luminance(&scatter.f) / scatter.pdf;
+        }
+        let albedo = total / samples as f64;
+        assert!((albedo - 1.0).abs() < 0.05, "albedo {}", albedo);
+    }
+}
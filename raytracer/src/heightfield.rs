@@ -0,0 +1,264 @@
+use crate::hit::*;
+use crate::mesh::hit_triangle;
+use image::{open, ImageResult};
+use std::path::Path;
+use std::sync::Arc;
+
+// Terrain with `heights[j * nx + i]` as the y of the grid point i of `nx`
+// along x in [x0, x1] and j of `nz` along z in [z0, z1]. Each grid cell is
+// split into two triangles, shaded with normals interpolated from the
+// vertices. (u, v) run along x and z over the whole field.
+pub struct Heightfield {
+    pub nx: usize,
+    pub nz: usize,
+    pub heights: Vec<f64>,
+    pub x0: f64,
+    pub x1: f64,
+    pub z0: f64,
+    pub z1: f64,
+    pub mat_ptr: Arc<dyn Material>,
+    normals: Vec<Vec3>,
+    // Lowest and highest height of each cell, to skip cells a ray passes
+    // above or below.
+    cell_bounds: Vec<(f64, f64)>,
+    bbox: AABB,
+}
+
+impl Heightfield {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        nx: usize,
+        nz: usize,
+        heights: Vec<f64>,
+        x0: f64,
+        x1: f64,
+        z0: f64,
+        z1: f64,
+        mat_ptr: Arc<dyn Material>,
+    ) -> Self {
+        assert!(nx >= 2 && nz >= 2 && heights.len() == nx * nz);
+        let (dx, dz) = ((x1 - x0) / (nx - 1) as f64, (z1 - z0) / (nz - 1) as f64);
+        let height = |i: usize, j: usize| heights[j * nx + i];
+        let mut normals = Vec::with_capacity(nx * nz);
+        for j in 0..nz {
+            for i in 0..nx {
+                let (i0, i1) = (i.saturating_sub(1), (i + 1).min(nx - 1));
+                let (j0, j1) = (j.saturating_sub(1), (j + 1).min(nz - 1));
+                let slope_x = (height(i1, j) - height(i0, j)) / ((i1 - i0) as f64 * dx);
+                let slope_z = (height(i, j1) - height(i, j0)) / ((j1 - j0) as f64 * dz);
+                normals.push(Vec3::new(-slope_x, 1.0, -slope_z).unit());
+            }
+        }
+        let mut cell_bounds = Vec::with_capacity((nx - 1) * (nz - 1));
+        for j in 0..nz - 1 {
+            for i in 0..nx - 1 {
+                let corners = [
+                    height(i, j),
+                    height(i + 1, j),
+                    height(i, j + 1),
+                    height(i + 1, j + 1),
+                ];
+                cell_bounds.push((
+                    corners.iter().cloned().fold(f64::INFINITY, f64::min),
+                    corners.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+                ));
+            }
+        }
+        let low = heights.iter().cloned().fold(f64::INFINITY, f64::min);
+        let high = heights.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        Self {
+            bbox: AABB {
+                min: Vec3::new(x0, low - 0.0001, z0),
+                max: Vec3::new(x1, high + 0.0001, z1),
+            },
+            nx,
+            nz,
+            heights,
+            x0,
+            x1,
+            z0,
+            z1,
+            mat_ptr,
+            normals,
+            cell_bounds,
+        }
+    }
+    // Heights from the luminance of a grayscale image, black at `y0` and
+    // white at `y0 + scale`, with image rows running along z.
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_image<P: AsRef<Path>>(
+        path: P,
+        x0: f64,
+        x1: f64,
+        z0: f64,
+        z1: f64,
+        y0: f64,
+        scale: f64,
+        mat_ptr: Arc<dyn Material>,
+    ) -> ImageResult<Self> {
+        let image = open(path)?.to_luma16();
+        let (nx, nz) = (image.width() as usize, image.height() as usize);
+        let heights = image
+            .pixels()
+            .map(|pixel| y0 + scale * pixel[0] as f64 / 65535.0)
+            .collect();
+        Ok(Self::new(nx, nz, heights, x0, x1, z0, z1, mat_ptr))
+    }
+    fn point(&self, i: usize, j: usize) -> Vec3 {
+        Vec3::new(
+            self.x0 + (self.x1 - self.x0) * i as f64 / (self.nx - 1) as f64,
+            self.heights[j * self.nx + i],
+            self.z0 + (self.z1 - self.z0) * j as f64 / (self.nz - 1) as f64,
+        )
+    }
+    // Nearest crossing of the two triangles of cell (i, j).
+    fn hit_cell(&self, ra: &Ray, i: usize, j: usize, t_min: f64, t_max: f64) -> Option<HitResult> {
+        let corners = [(i, j), (i + 1, j), (i + 1, j + 1), (i, j + 1)];
+        let triangles = [
+            [corners[0], corners[1], corners[2]],
+            [corners[0], corners[2], corners[3]],
+        ];
+        let vertices = |triangle: &[(usize, usize); 3]| -> Vec<Vec3> {
+            triangle
+                .iter()
+                .map(|&(column, row)| self.point(column, row))
+                .collect()
+        };
+        let mut best: Option<(f64, f64, f64, usize)> = None;
+        for (which, triangle) in triangles.iter().enumerate() {
+            let corner = vertices(triangle);
+            let t_max = best.map_or(t_max, |best| best.0);
+            if let Some((t, b1, b2)) =
+                hit_triangle(ra, [&corner[0], &corner[1], &corner[2]], t_min, t_max)
+            {
+                best = Some((t, b1, b2, which));
+            }
+        }
+        let (t, b1, b2, which) = best?;
+        let triangle = triangles[which];
+        let corner = vertices(&triangle);
+        let normal = Vec3::cross(
+            corner[1].clone() - corner[0].clone(),
+            corner[2].clone() - corner[0].clone(),
+        )
+        .unit();
+        let normal = if normal.y < 0.0 { -normal } else { normal };
+        let shading = triangle
+            .iter()
+            .zip([1.0 - b1 - b2, b1, b2].iter())
+            .fold(Vec3::zero(), |sum, (&(column, row), &weight)| {
+                sum + self.normals[row * self.nx + column].clone() * weight
+            })
+            .unit();
+        let hit_point = ra.at(t);
+        let fu = (hit_point.x - self.x0) / (self.x1 - self.x0);
+        let fv = (hit_point.z - self.z0) / (self.z1 - self.z0);
+        let mut hit_result = HitResult::new(ra, t, normal.clone(), fu, fv, self.mat_ptr.clone());
+        hit_result.shading_normal = if hit_result.front_face {
+            shading
+        } else {
+            -shading
+        };
+        let (size_x, size_z) = (self.x1 - self.x0, self.z1 - self.z0);
+        hit_result.dpdu = Vec3::new(size_x, -size_x * normal.x / normal.y, 0.0);
+        hit_result.dpdv = Vec3::new(0.0, -size_z * normal.z / normal.y, size_z);
+        Some(hit_result)
+    }
+}
+
+impl Hitable for Heightfield {
+    // Walks the cells under the ray in order with a 2D DDA.
+    fn hit(&self, ra: &Ray, t_min: f64, t_max: f64) -> Option<HitResult> {
+        let (t_enter, t_exit) = self.bbox.clip(ra, t_min, t_max)?;
+        let cells = [self.nx - 1, self.nz - 1];
+        let origin = [self.x0, self.z0];
+        let size = [
+            (self.x1 - self.x0) / cells[0] as f64,
+            (self.z1 - self.z0) / cells[1] as f64,
+        ];
+        let start = ra.at(t_enter);
+        let (position, direction) = ([start.x, start.z], [ra.direction.x, ra.direction.z]);
+        let mut cell = [0usize; 2];
+        let mut next = [f64::INFINITY; 2];
+        let mut delta = [f64::INFINITY; 2];
+        for axis in 0..2 {
+            let c = ((position[axis] - origin[axis]) / size[axis]).floor();
+            cell[axis] = c.max(0.0).min((cells[axis] - 1) as f64) as usize;
+            let d = direction[axis];
+            if d > 0.0 {
+                let boundary = origin[axis] + (cell[axis] + 1) as f64 * size[axis];
+                next[axis] = t_enter + (boundary - position[axis]) / d;
+                delta[axis] = size[axis] / d;
+            } else if d < 0.0 {
+                let boundary = origin[axis] + cell[axis] as f64 * size[axis];
+                next[axis] = t_enter + (boundary - position[axis]) / d;
+                delta[axis] = -size[axis] / d;
+            }
+        }
+        let mut t = t_enter;
+        loop {
+            let t_next = next[0].min(next[1]).min(t_exit);
+            let (low, high) = self.cell_bounds[cell[1] * cells[0] + cell[0]];
+            let (y0, y1) = (ra.at(t).y, ra.at(t_next).y);
+            if y0.min(y1) <= high && y0.max(y1) >= low {
+                if let Some(hit_result) = self.hit_cell(ra, cell[0], cell[1], t_min, t_max) {
+                    return Some(hit_result);
+                }
+            }
+            if t_next >= t_exit {
+                return None;
+            }
+            let axis = if next[0] < next[1] { 0 } else { 1 };
+            if (direction[axis] > 0.0 && cell[axis] + 1 >= cells[axis])
+                || (direction[axis] < 0.0 && cell[axis] == 0)
+            {
+                return None;
+            }
+            if direction[axis] > 0.0 {
+                cell[axis] += 1;
+            } else {
+                cell[axis] -= 1;
+            }
+            t = t_next;
+            next[axis] += delta[axis];
+        }
+    }
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(self.bbox.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heightfield_walk() {
+        let (nx, nz) = (9, 6);
+        let heights = (0..nx * nz)
+            .map(|k| ((k * 7919) % 13) as f64 * 0.1)
+            .collect();
+        let field = Heightfield::new(nx, nz, heights, -2.0, 2.0, -1.0, 1.5, white_lambertian());
+        // The walk must find the same first hit as testing every cell.
+        for _ in 0..500 {
+            let ra = Ray {
+                origin: Vec3::random_range(-3.0, 3.0),
+                direction: Vec3::random_unit(),
+            };
+            let walked = field.hit(&ra, 0.0, f64::INFINITY).map(|h| h.t);
+            let mut brute: Option<f64> = None;
+            for j in 0..nz - 1 {
+                for i in 0..nx - 1 {
+                    if let Some(h) = field.hit_cell(&ra, i, j, 0.0, f64::INFINITY) {
+                        brute = Some(brute.map_or(h.t, |t| t.min(h.t)));
+                    }
+                }
+            }
+            match (walked, brute) {
+                (Some(a), Some(b)) => assert!((a - b).abs() < 1e-9),
+                (None, None) => {}
+                _ => panic!("walk and brute force disagree"),
+            }
+        }
+    }
+}
@@ -1,6 +1,7 @@
 pub use crate::material::*;
+pub use crate::onb::ONB;
 pub use crate::ray::Ray;
-pub use crate::vec3::Vec3;
+pub use crate::vec3::{lerp, Vec3};
 
 use core::f64::INFINITY;
 use std::f64::consts::PI;
@@ -14,6 +15,32 @@ pub fn ffmin(a: f64, b: f64) -> f64 {
         b
     }
 }
+// Bound on the relative error of n floating-point operations (PBRT's gamma).
+pub fn gamma(n: i32) -> f64 {
+    let eps = f64::EPSILON * 0.5;
+    (n as f64) * eps / (1.0 - (n as f64) * eps)
+}
+pub fn next_float_up(v: f64) -> f64 {
+    if v.is_infinite() && v > 0.0 {
+        return v;
+    }
+    let v = if v == -0.0 { 0.0 } else { v };
+    let bits = v.to_bits();
+    f64::from_bits(if v >= 0.0 { bits + 1 } else { bits - 1 })
+}
+pub fn next_float_down(v: f64) -> f64 {
+    -next_float_up(-v)
+}
+
+// Moves the origin of a ray carried into an instance's object space forward
+// past the rounding error `error` of that transform, so that a ray spawned on
+// the instance cannot hit it again; returns the parametric distance skipped.
+fn skip_transform_error(ra: &mut Ray, error: Vec3) -> f64 {
+    let dt = ra.direction.abs() * error / ra.direction.squared_length();
+    ra.origin += ra.direction.clone() * dt;
+    dt
+}
+
 pub fn ffmax(a: f64, b: f64) -> f64 {
     if a > b {
         a
@@ -29,20 +56,29 @@ pub struct AABB {
 }
 impl AABB {
     pub fn hit(&self, ra: &Ray, tmin: f64, tmax: f64) -> bool {
+        self.clip(ra, tmin, tmax).is_some()
+    }
+    // The part of [tmin, tmax] along `ra` that lies inside the box, if any.
+    pub fn clip(&self, ra: &Ray, tmin: f64, tmax: f64) -> Option<(f64, f64)> {
+        let (mut t0, mut t1) = (tmin, tmax);
         for a in 0..3 {
-            let t0 = ffmin(
-                (self.min[a] - ra.origin[a]) / ra.direction[a],
-                (self.max[a] - ra.origin[a]) / ra.direction[a],
-            );
-            let t1 = ffmax(
-                (self.min[a] - ra.origin[a]) / ra.direction[a],
-                (self.max[a] - ra.origin[a]) / ra.direction[a],
-            );
-            if tmin > t1 || tmax < t0 {
-                return false;
+            // Parallel to the slab, the ray is inside it everywhere or nowhere.
+            if ra.direction[a] == 0.0 {
+                if ra.origin[a] < self.min[a] || ra.origin[a] > self.max[a] {
+                    return None;
+                }
+                continue;
+            }
+            let inverse = 1.0 / ra.direction[a];
+            let near = (self.min[a] - ra.origin[a]) * inverse;
+            let far = (self.max[a] - ra.origin[a]) * inverse;
+            t0 = t0.max(ffmin(near, far));
+            t1 = t1.min(ffmax(near, far));
+            if t0 > t1 {
+                return None;
             }
         }
-        true
+        Some((t0, t1))
     }
     pub fn surrounding_box(box0: AABB, box1: AABB) -> AABB {
         let min = Vec3::new(
@@ -59,17 +95,167 @@ impl AABB {
     }
 }
 
+// `normal` is the geometric normal, flipped to face the incoming ray, and
+// decides `front_face`; materials shade with `shading_normal`, which normal
+// and bump maps may perturb. `dpdu`/`dpdv` are the surface tangents,
+// `footprint` the UV extent of the pixel (see `compute_differentials`) and
+// `p_error` bounds the absolute floating-point error of `p` per axis.
+// `color` is the interpolated vertex color of meshes that carry one.
+#[derive(Clone)]
 pub struct HitResult {
     pub t: f64,
     pub p: Vec3,
     pub fu: f64,
     pub fv: f64,
     pub normal: Vec3,
+    pub shading_normal: Vec3,
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
+    pub footprint: UvFootprint,
+    pub p_error: Vec3,
     pub front_face: bool,
     pub mat_ptr: Arc<dyn Material>,
+    pub color: Option<Vec3>,
 }
 
 impl HitResult {
+    pub fn new(
+        ra: &Ray,
+        t: f64,
+        outward_normal: Vec3,
+        fu: f64,
+        fv: f64,
+        mat_ptr: Arc<dyn Material>,
+    ) -> Self {
+        let mut normal = outward_normal;
+        let mut front_face = false;
+        HitResult::set_face_normal(ra, &mut normal, &mut front_face);
+        let p = ra.at(t);
+        let p_error = (ra.origin.abs() + (ra.direction.clone() * t).abs()) * gamma(3);
+        Self {
+            t,
+            p,
+            p_error,
+            fu,
+            fv,
+            shading_normal: normal.clone(),
+            normal,
+            dpdu: Vec3::zero(),
+            dpdv: Vec3::zero(),
+            footprint: UvFootprint::default(),
+            front_face,
+            mat_ptr,
+            color: None,
+        }
+    }
+    // Estimates the UV footprint from where the offset rays of a ray
+    // differential meet the tangent plane, solving p + du*dpdu + dv*dpdv = q
+    // by least squares.
+    pub fn compute_differentials(&mut self, rx: &Ray, ry: &Ray) {
+        let n = self.normal.clone();
+        let d = n.clone() * self.p.clone();
+        let intersect = |ra: &Ray| -> Option<Vec3> {
+            let denom = n.clone() * ra.direction.clone();
+            if denom == 0.0 {
+                return None;
+            }
+            let t = (d - n.clone() * ra.origin.clone()) / denom;
+            Some(ra.at(t))
+        };
+        let (px, py) = match (intersect(rx), intersect(ry)) {
+            (Some(px), Some(py)) => (px, py),
+            _ => {
+                self.footprint = UvFootprint::default();
+                return;
+            }
+        };
+        let (dpdu, dpdv) = (self.dpdu.clone(), self.dpdv.clone());
+        let (uu, uv, vv) = (
+            dpdu.clone() * dpdu.clone(),
+            dpdu.clone() * dpdv.clone(),
+            dpdv.clone() * dpdv.clone(),
+        );
+        let det = uu * vv - uv * uv;
+        if det.abs() < 1e-24 {
+            self.footprint = UvFootprint::default();
+            return;
+        }
+        let solve = |q: Vec3| -> (f64, f64) {
+            let (qu, qv) = (q.clone() * dpdu.clone(), q * dpdv.clone());
+            ((vv * qu - uv * qv) / det, (uu * qv - uv * qu) / det)
+        };
+        let (dudx, dvdx) = solve(px - self.p.clone());
+        let (dudy, dvdy) = solve(py - self.p.clone());
+        let finite = |x: f64| if x.is_finite() { x } else { 0.0 };
+        self.footprint = UvFootprint {
+            dudx: finite(dudx),
+            dvdx: finite(dvdx),
+            dudy: finite(dudy),
+            dvdy: finite(dvdy),
+        };
+    }
+    // Looks `texture` up at this point, filtered over `footprint`.
+    pub fn texture_value(&self, texture: &Arc<dyn Texture>) -> Vec3 {
+        texture.hit_value(self)
+    }
+    // Starts a ray at `p`, pushed off the surface along the geometric normal
+    // by the error bound so that it cannot re-intersect the surface it left.
+    pub fn spawn_ray(&self, direction: Vec3) -> Ray {
+        let n = self.normal.clone();
+        let d = n.abs() * self.p_error.clone();
+        let mut offset = n * d;
+        if direction.clone() * self.normal.clone() < 0.0 {
+            offset = -offset;
+        }
+        let po = self.p.clone() + offset.clone();
+        let round = |v: f64, o: f64| {
+            if o > 0.0 {
+                next_float_up(v)
+            } else if o < 0.0 {
+                next_float_down(v)
+            } else {
+                v
+            }
+        };
+        Ray {
+            origin: Vec3::new(
+                round(po.x, offset.x),
+                round(po.y, offset.y),
+                round(po.z, offset.z),
+            ),
+            direction,
+        }
+    }
+    // Bends a shading normal that faces away from `wo` (pointing back along
+    // the ray) until it is barely front-facing, so materials always see
+    // `wo.z > 0`.
+    pub fn face_shading_normal(&mut self, wo: &Vec3) {
+        let cosine = wo.clone() * self.shading_normal.clone();
+        if cosine < 0.0001 {
+            self.shading_normal =
+                (self.shading_normal.clone() + wo.clone() * (0.0001 - cosine)).unit();
+        }
+    }
+    // Whether the shading and geometric normals put `direction` on the same
+    // side; samples where they disagree would leak light through the surface.
+    pub fn sides_agree(&self, direction: &Vec3) -> bool {
+        (direction.clone() * self.normal.clone() > 0.0)
+            == (direction.clone() * self.shading_normal.clone() > 0.0)
+    }
+    pub fn outward_normal(&self) -> Vec3 {
+        if self.front_face {
+            self.normal.clone()
+        } else {
+            -self.normal.clone()
+        }
+    }
+    // For surfaces found by root finding, whose hits are only known to
+    // within `epsilon`: spawned rays must start clear of that shell, and
+    // there is no parametrization to take a tangent from.
+    pub fn set_implicit_error(&mut self, epsilon: f64) {
+        self.p_error = Vec3::ones() * (2.0 * epsilon);
+        self.dpdu = ONB::build_from_w(self.outward_normal()).u;
+    }
     pub fn set_face_normal(ra: &Ray, normal: &mut Vec3, front_face: &mut bool) {
         *front_face = ra.direction.clone() * normal.clone() < 0.0;
         *normal = if *front_face {
@@ -78,6 +264,9 @@ impl HitResult {
             -normal.clone()
         };
     }
+    pub fn shading_frame(&self) -> ONB {
+        ONB::build_from_wu(self.shading_normal.clone(), self.dpdu.clone())
+    }
     pub fn get_sphere_uv(p: Vec3, u: &mut f64, v: &mut f64) {
         let phi = p.z.atan2(p.x);
         let theta = p.y.asin();
@@ -97,6 +286,54 @@ pub trait Hitable {
     fn random(&self, _o: Vec3) -> Vec3 {
         Vec3::new(1.0, 0.0, 0.0)
     }
+    // Every stretch of the whole line through `ra` that lies inside the
+    // shape, in order. Only meaningful for closed surfaces.
+    fn spans(&self, ra: &Ray) -> Vec<Span> {
+        crossing_spans(self, ra)
+    }
+}
+
+// A stretch of a ray inside a solid, with the hits where it enters and
+// leaves.
+#[derive(Clone)]
+pub struct Span {
+    pub enter: HitResult,
+    pub exit: HitResult,
+}
+
+// Spans of a closed surface found by walking its crossings in order from far
+// behind the origin, so the surface need not be convex. Crossings alternate
+// between entries and exits, whatever way the normals face.
+pub fn crossing_spans<H: Hitable + ?Sized>(shape: &H, ra: &Ray) -> Vec<Span> {
+    let mut spans = Vec::new();
+    let mut enter: Option<HitResult> = None;
+    // Each crossing after the first is found from a ray spawned clear of the
+    // last one; `t` is still measured along `ra`.
+    let along = |p: &Vec3| {
+        (p.clone() - ra.origin.clone()) * ra.direction.clone() / ra.direction.squared_length()
+    };
+    let mut ray = ra.clone();
+    let mut t_min = -INFINITY;
+    // A bound on the crossings so that a leaky boundary cannot loop forever.
+    for _ in 0..64 {
+        let mut hit_result = match shape.hit(&ray, t_min, INFINITY) {
+            Some(hit_result) => hit_result,
+            None => break,
+        };
+        if ray != *ra {
+            hit_result.t = along(&hit_result.p);
+        }
+        ray = hit_result.spawn_ray(ra.direction.clone());
+        t_min = 0.0;
+        match enter.take() {
+            None => enter = Some(hit_result),
+            Some(enter) => spans.push(Span {
+                enter,
+                exit: hit_result,
+            }),
+        }
+    }
+    spans
 }
 
 pub fn hit(hitlist: &[Arc<dyn Hitable>], ra: &Ray, t_min: f64, t_max: f64) -> Option<HitResult> {
@@ -118,65 +355,54 @@ pub struct Sphere {
     pub mat_ptr: Arc<dyn Material>,
 }
 
+impl Sphere {
+    // Tangents of the `get_sphere_uv` parametrization; degenerate at the poles.
+    fn set_tangents(&self, hit_result: &mut HitResult, n: &Vec3) {
+        let cos_theta = (n.x * n.x + n.z * n.z).sqrt();
+        hit_result.dpdu = Vec3::new(n.z, 0.0, -n.x) * (2.0 * PI * self.radius);
+        if cos_theta > 0.0 {
+            hit_result.dpdv = Vec3::new(-n.y * n.x / cos_theta, cos_theta, -n.y * n.z / cos_theta)
+                * (PI * self.radius);
+        }
+    }
+}
+
 impl Hitable for Sphere {
     fn hit(&self, ra: &Ray, t_min: f64, t_max: f64) -> Option<HitResult> {
-        let (delta, a, b) = {
-            let oc = ra.origin.clone() - self.center.clone();
-            let a = ra.direction.squared_length();
-            let b = oc.clone() * ra.direction.clone() * 2.0;
-            let c = oc.squared_length() - self.radius * self.radius;
-            (b * b - 4.0 * a * c, a, b)
+        let oc = ra.origin.clone() - self.center.clone();
+        let a = ra.direction.squared_length();
+        let b = oc.clone() * ra.direction.clone() * 2.0;
+        let c = oc.squared_length() - self.radius * self.radius;
+        // Discriminant via the closest approach to the center, which stays
+        // accurate for rays far from large spheres.
+        let closest = oc - ra.direction.clone() * (b / (2.0 * a));
+        let length = closest.length();
+        let delta = 4.0 * a * (self.radius - length) * (self.radius + length);
+        if delta <= 0.0 {
+            return None;
+        }
+        let q = if b < 0.0 {
+            -0.5 * (b - delta.sqrt())
+        } else {
+            -0.5 * (b + delta.sqrt())
         };
-        if delta > 0.0 {
-            let temp = delta.sqrt();
-            let root = (-b - temp) / (2.0 * a);
-            if root > t_min && root < t_max {
-                let t = root;
-                let p = ra.at(t);
-
-                let mut normal = (p.clone() - self.center.clone()) / self.radius;
-                let mut fu = 0.0;
-                let mut fv = 0.0;
-
-                HitResult::get_sphere_uv(normal.clone(), &mut fu, &mut fv);
-                let mut front_face = false;
-                HitResult::set_face_normal(ra, &mut normal, &mut front_face);
-                let mat_ptr = self.mat_ptr.clone();
-                return Option::Some(HitResult {
-                    t,
-                    p,
-                    fu,
-                    fv,
-                    normal,
-                    front_face,
-                    mat_ptr,
-                });
-            }
-            let root = (-b + temp) / (2.0 * a);
-            if root > t_min && root < t_max {
-                let t = root;
-                let p = ra.at(t);
-                let mut normal = (p.clone() - self.center.clone()) / self.radius;
-
+        let (t0, t1) = (q / a, c / q);
+        let roots = if t0 < t1 { [t0, t1] } else { [t1, t0] };
+        for &t in roots.iter() {
+            if t > t_min && t < t_max {
+                let outward_normal = (ra.at(t) - self.center.clone()).unit();
                 let mut fu = 0.0;
                 let mut fv = 0.0;
-                HitResult::get_sphere_uv(normal.clone(), &mut fu, &mut fv);
-
-                let mut front_face = false;
-                HitResult::set_face_normal(ra, &mut normal, &mut front_face);
-                let mat_ptr = self.mat_ptr.clone();
-                return Option::Some(HitResult {
-                    t,
-                    p,
-                    fu,
-                    fv,
-                    normal,
-                    front_face,
-                    mat_ptr,
-                });
+                HitResult::get_sphere_uv(outward_normal.clone(), &mut fu, &mut fv);
+                let mut hit_result =
+                    HitResult::new(ra, t, outward_normal.clone(), fu, fv, self.mat_ptr.clone());
+                hit_result.p = self.center.clone() + outward_normal.clone() * self.radius;
+                hit_result.p_error = hit_result.p.abs() * gamma(5);
+                self.set_tangents(&mut hit_result, &outward_normal);
+                return Some(hit_result);
             }
         }
-        Option::None
+        None
     }
     fn bounding_box(&self) -> Option<AABB> {
         Some(AABB {
@@ -184,6 +410,44 @@ impl Hitable for Sphere {
             max: self.center.clone() + Vec3::ones() * self.radius,
         })
     }
+    // Uniform over the cone of directions from `o` that meet the sphere.
+    fn pdf_value(&self, o: Vec3, v: Vec3) -> f64 {
+        let ra = Ray {
+            origin: o.clone(),
+            direction: v,
+        };
+        if self.hit(&ra, 0.0001, INFINITY).is_none() {
+            return 0.0;
+        }
+        match self.cone_solid_angle(&o) {
+            Some(solid_angle) => 1.0 / solid_angle,
+            None => 0.0,
+        }
+    }
+    fn random(&self, o: Vec3) -> Vec3 {
+        let direction = self.center.clone() - o.clone();
+        let one_minus_cos_max = match self.cone_solid_angle(&o) {
+            Some(solid_angle) => solid_angle / (2.0 * PI),
+            None => return direction,
+        };
+        let (r1, r2) = (random_double(), random_double());
+        let z = 1.0 - r2 * one_minus_cos_max;
+        let sin_theta = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * r1;
+        ONB::build_from_w(direction).local(sin_theta * phi.cos(), sin_theta * phi.sin(), z)
+    }
+}
+
+impl Sphere {
+    // Solid angle the sphere covers seen from `o`, or None from inside. The
+    // 1 - cos(theta_max) it is made of stays accurate for small spheres.
+    fn cone_solid_angle(&self, o: &Vec3) -> Option<f64> {
+        let ratio = self.radius * self.radius / (self.center.clone() - o.clone()).squared_length();
+        if ratio >= 1.0 {
+            return None;
+        }
+        Some(2.0 * PI * ratio / (1.0 + (1.0 - ratio).sqrt()))
+    }
 }
 
 pub struct XyRect {
@@ -198,7 +462,7 @@ pub struct XyRect {
 impl Hitable for XyRect {
     fn hit(&self, ra: &Ray, t_min: f64, t_max: f64) -> Option<HitResult> {
         let t = (self.k - ra.origin.z) / ra.direction.z;
-        if t < t_min || t > t_max {
+        if t <= t_min || t > t_max {
             return None;
         }
         let x = ra.origin.x + t * ra.direction.x;
@@ -208,18 +472,19 @@ impl Hitable for XyRect {
         if x < self.x0 || x > self.x1 || y < self.y0 || y > self.y1 {
             return None;
         }
-        let mut normal = Vec3::new(0.0, 0.0, 1.0);
-        let mut front_face = false;
-        HitResult::set_face_normal(ra, &mut normal, &mut front_face);
-        Some(HitResult {
+        let mut hit_result = HitResult::new(
+            ra,
             t,
-            p: ra.at(t),
+            Vec3::new(0.0, 0.0, 1.0),
             fu,
             fv,
-            normal,
-            front_face,
-            mat_ptr: self.mat_ptr.clone(),
-        })
+            self.mat_ptr.clone(),
+        );
+        hit_result.dpdu = Vec3::new(self.x1 - self.x0, 0.0, 0.0);
+        hit_result.dpdv = Vec3::new(0.0, self.y1 - self.y0, 0.0);
+        hit_result.p.z = self.k;
+        hit_result.p_error.z = 0.0;
+        Some(hit_result)
     }
     fn bounding_box(&self) -> Option<AABB> {
         Some(AABB {
@@ -241,7 +506,7 @@ pub struct XzRect {
 impl Hitable for XzRect {
     fn hit(&self, ra: &Ray, t_min: f64, t_max: f64) -> Option<HitResult> {
         let t = (self.k - ra.origin.y) / ra.direction.y;
-        if t < t_min || t > t_max {
+        if t <= t_min || t > t_max {
             return None;
         }
         let x = ra.origin.x + t * ra.direction.x;
@@ -251,18 +516,19 @@ impl Hitable for XzRect {
         if x < self.x0 || x > self.x1 || z < self.z0 || z > self.z1 {
             return None;
         }
-        let mut normal = Vec3::new(0.0, -1.0, 0.0);
-        let mut front_face = false;
-        HitResult::set_face_normal(ra, &mut normal, &mut front_face);
-        Some(HitResult {
+        let mut hit_result = HitResult::new(
+            ra,
             t,
-            p: ra.at(t),
+            Vec3::new(0.0, -1.0, 0.0),
             fu,
             fv,
-            normal,
-            front_face,
-            mat_ptr: self.mat_ptr.clone(),
-        })
+            self.mat_ptr.clone(),
+        );
+        hit_result.dpdu = Vec3::new(self.x1 - self.x0, 0.0, 0.0);
+        hit_result.dpdv = Vec3::new(0.0, 0.0, self.z1 - self.z0);
+        hit_result.p.y = self.k;
+        hit_result.p_error.y = 0.0;
+        Some(hit_result)
     }
     fn bounding_box(&self) -> Option<AABB> {
         Some(AABB {
@@ -307,7 +573,7 @@ pub struct YzRect {
 impl Hitable for YzRect {
     fn hit(&self, ra: &Ray, t_min: f64, t_max: f64) -> Option<HitResult> {
         let t = (self.k - ra.origin.x) / ra.direction.x;
-        if t < t_min || t > t_max {
+        if t <= t_min || t > t_max {
             return None;
         }
         let z = ra.origin.z + t * ra.direction.z;
@@ -317,18 +583,19 @@ impl Hitable for YzRect {
         if z < self.z0 || z > self.z1 || y < self.y0 || y > self.y1 {
             return None;
         }
-        let mut normal = Vec3::new(1.0, 0.0, 0.0);
-        let mut front_face = false;
-        HitResult::set_face_normal(ra, &mut normal, &mut front_face);
-        Some(HitResult {
+        let mut hit_result = HitResult::new(
+            ra,
             t,
-            p: ra.at(t),
+            Vec3::new(1.0, 0.0, 0.0),
             fu,
             fv,
-            normal,
-            front_face,
-            mat_ptr: self.mat_ptr.clone(),
-        })
+            self.mat_ptr.clone(),
+        );
+        hit_result.dpdu = Vec3::new(0.0, self.y1 - self.y0, 0.0);
+        hit_result.dpdv = Vec3::new(0.0, 0.0, self.z1 - self.z0);
+        hit_result.p.x = self.k;
+        hit_result.p_error.x = 0.0;
+        Some(hit_result)
     }
     fn bounding_box(&self) -> Option<AABB> {
         Some(AABB {
@@ -422,17 +689,19 @@ pub struct Translate {
 }
 impl Hitable for Translate {
     fn hit(&self, ra: &Ray, t_min: f64, t_max: f64) -> Option<HitResult> {
-        let moved_r = Ray {
+        let mut moved_r = Ray {
             origin: ra.origin.clone() - self.offset.clone(),
             direction: ra.direction.clone(),
         };
-        if let Some(mut hit_result) = self.ptr.hit(&moved_r, t_min, t_max) {
+        let error = moved_r.origin.abs() * gamma(1);
+        let dt = skip_transform_error(&mut moved_r, error);
+        if let Some(mut hit_result) =
+            self.ptr
+                .hit(&moved_r, (t_min - dt).max(t_min.min(0.0)), t_max - dt)
+        {
+            hit_result.t += dt;
             hit_result.p += self.offset.clone();
-            HitResult::set_face_normal(
-                &moved_r,
-                &mut hit_result.normal,
-                &mut hit_result.front_face,
-            );
+            hit_result.p_error += hit_result.p.abs() * gamma(1);
             return Some(hit_result);
         };
         None
@@ -509,15 +778,35 @@ impl Hitable for RotateY {
         self.rotate1(&mut origin);
         self.rotate1(&mut direction);
 
-        let rotated_r = Ray { origin, direction };
-        if let Some(mut hit_result) = self.ptr.hit(&rotated_r, t_min, t_max) {
+        let error = Vec3::new(
+            (self.cos_theta * ra.origin.x).abs() + (self.sin_theta * ra.origin.z).abs(),
+            0.0,
+            (self.sin_theta * ra.origin.x).abs() + (self.cos_theta * ra.origin.z).abs(),
+        ) * gamma(3);
+        let mut rotated_r = Ray { origin, direction };
+        let dt = skip_transform_error(&mut rotated_r, error);
+        if let Some(mut hit_result) =
+            self.ptr
+                .hit(&rotated_r, (t_min - dt).max(t_min.min(0.0)), t_max - dt)
+        {
+            hit_result.t += dt;
+            let (p, error) = (hit_result.p.clone(), hit_result.p_error.clone());
+            hit_result.p_error = Vec3::new(
+                gamma(3) * (self.cos_theta * p.x).abs()
+                    + gamma(3) * (self.sin_theta * p.z).abs()
+                    + (1.0 + gamma(3))
+                        * (self.cos_theta.abs() * error.x + self.sin_theta.abs() * error.z),
+                error.y,
+                gamma(3) * (self.sin_theta * p.x).abs()
+                    + gamma(3) * (self.cos_theta * p.z).abs()
+                    + (1.0 + gamma(3))
+                        * (self.sin_theta.abs() * error.x + self.cos_theta.abs() * error.z),
+            );
             self.rotate2(&mut hit_result.p);
             self.rotate2(&mut hit_result.normal);
-            HitResult::set_face_normal(
-                &rotated_r,
-                &mut hit_result.normal,
-                &mut hit_result.front_face,
-            );
+            self.rotate2(&mut hit_result.shading_normal);
+            self.rotate2(&mut hit_result.dpdu);
+            self.rotate2(&mut hit_result.dpdv);
             return Some(hit_result);
         }
         None
@@ -527,6 +816,27 @@ impl Hitable for RotateY {
     }
 }
 
+// Parametric intervals of `ra` within [t_min, t_max] that lie inside the
+// closed surface `boundary`.
+pub fn inside_intervals(
+    boundary: &dyn Hitable,
+    ra: &Ray,
+    t_min: f64,
+    t_max: f64,
+) -> Vec<(f64, f64)> {
+    if let Some(bbox) = boundary.bounding_box() {
+        if !bbox.hit(ra, t_min, t_max) {
+            return Vec::new();
+        }
+    }
+    boundary
+        .spans(ra)
+        .into_iter()
+        .map(|span| (span.enter.t.max(t_min), span.exit.t.min(t_max)))
+        .filter(|(start, end)| start < end)
+        .collect()
+}
+
 pub struct ConstantMedium {
     pub density: f64,
     pub boundary: Arc<dyn Hitable>,
@@ -534,32 +844,22 @@ pub struct ConstantMedium {
 }
 impl Hitable for ConstantMedium {
     fn hit(&self, ra: &Ray, t_min: f64, t_max: f64) -> Option<HitResult> {
-        if let Some(mut rec1) = self.boundary.hit(&ra, -INFINITY, INFINITY) {
-            if let Some(mut rec2) = self.boundary.hit(&ra, rec1.t + 0.0001, INFINITY) {
-                rec1.t = rec1.t.max(t_min);
-                rec2.t = rec2.t.min(t_max);
-                if rec1.t >= rec2.t {
-                    return None;
-                }
-                rec1.t = rec1.t.max(0.0);
-
-                let ray_length = ra.direction.length();
-                let distance_inside_boundary = (rec2.t - rec1.t) * ray_length;
-                let hit_distance = (-1.0 / self.density) * random_double().ln();
-                if hit_distance > distance_inside_boundary {
-                    return None;
-                }
-                let t = rec1.t + hit_distance / ray_length;
-                return Some(HitResult {
+        let ray_length = ra.direction.length();
+        let mut hit_distance = (-1.0 / self.density) * random_double().ln();
+        for (t0, t1) in inside_intervals(&*self.boundary, ra, t_min.max(0.0), t_max) {
+            let distance_inside_boundary = (t1 - t0) * ray_length;
+            if hit_distance <= distance_inside_boundary {
+                let t = t0 + hit_distance / ray_length;
+                return Some(HitResult::new(
+                    ra,
                     t,
-                    p: ra.at(t),
-                    fu: 0.0,
-                    fv: 0.0,
-                    normal: Vec3::new(1.0, 0.0, 0.0),
-                    front_face: true,
-                    mat_ptr: self.phase_function.clone(),
-                });
+                    -ra.direction.unit(),
+                    0.0,
+                    0.0,
+                    self.phase_function.clone(),
+                ));
             }
+            hit_distance -= distance_inside_boundary;
         }
         None
     }
@@ -567,3 +867,51 @@ impl Hitable for ConstantMedium {
         self.boundary.bounding_box()
     }
 }
+
+// Fixtures for tests that don't care about the surface.
+#[cfg(test)]
+pub fn white_texture() -> Arc<dyn Texture> {
+    Arc::new(ConstantTexture {
+        color: Vec3::ones(),
+    })
+}
+#[cfg(test)]
+pub fn white_lambertian() -> Arc<dyn Material> {
+    Arc::new(Lambertian {
+        albedo: white_texture(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aabb_hit() {
+        let bbox = AABB {
+            min: Vec3::new(1.0, 1.0, -1.0),
+            max: Vec3::new(2.0, 2.0, 1.0),
+        };
+        let ray = |direction: Vec3| Ray {
+            origin: Vec3::zero(),
+            direction,
+        };
+        assert!(bbox.hit(&ray(Vec3::new(1.0, 1.0, 0.0)), 0.0, INFINITY));
+        assert!(!bbox.hit(&ray(Vec3::new(1.0, 1.0, 0.0)), 0.0, 0.5));
+        assert!(!bbox.hit(&ray(Vec3::new(-1.0, -1.0, 0.0)), 0.0, INFINITY));
+        // The ray crosses the x slab for t in [1, 2] and the y slab for t in
+        // [10, 20], each within range, but never both at once.
+        assert!(!bbox.hit(&ray(Vec3::new(1.0, 0.1, 0.0)), 0.0, INFINITY));
+        // Grazing along a face, parallel to its slab.
+        let grazing = Ray {
+            origin: Vec3::new(1.0, 0.0, 0.0),
+            direction: Vec3::new(0.0, 1.0, 0.0),
+        };
+        assert_eq!(bbox.clip(&grazing, 0.0, INFINITY), Some((1.0, 2.0)));
+        let outside = Ray {
+            origin: Vec3::new(0.5, 0.0, 0.0),
+            direction: Vec3::new(0.0, 1.0, 0.0),
+        };
+        assert!(!bbox.hit(&outside, 0.0, INFINITY));
+    }
+}
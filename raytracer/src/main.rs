@@ -1,14 +1,37 @@
+mod bump;
 mod camera;
+mod csg;
+mod curve;
+mod disney;
+mod displacement;
+mod gltf;
+mod hair;
+mod heightfield;
 mod hit;
+mod json;
+mod mapping;
 mod material;
+mod medium;
+mod mesh;
+mod metaball;
+mod microfacet;
+mod nodes;
+mod noise;
 mod onb;
 mod pdf;
 mod perlin;
+mod phase;
+mod ply;
 mod random;
 mod ray;
+mod sdf;
+mod shapes;
+mod stl;
+mod subdivision;
 mod texture;
 #[allow(clippy::float_cmp)]
 mod vec3;
+mod volume;
 mod world;
 use core::f64::INFINITY;
 use image::{ImageBuffer, RgbImage};
@@ -16,85 +39,139 @@ use indicatif::ProgressBar;
 use raytracer_codegen::make_root;
 use std::sync::Arc;
 
+pub use bump::*;
 pub use camera::Camera;
+pub use csg::*;
+pub use curve::*;
+pub use disney::*;
+pub use gltf::*;
+pub use hair::*;
+pub use heightfield::*;
 pub use hit::*;
+pub use mapping::*;
 pub use material::*;
+pub use mesh::*;
+pub use metaball::*;
+pub use nodes::*;
+pub use noise::*;
 pub use onb::ONB;
 pub use pdf::*;
+pub use phase::*;
 pub use random::*;
-pub use ray::Ray;
+pub use ray::{Ray, RayDifferential};
+pub use sdf::*;
+pub use shapes::*;
+pub use subdivision::*;
 pub use texture::*;
 pub use vec3::Vec3;
+pub use volume::*;
 pub use world::*;
 
 const WIDTH: u32 = 1600;
 const ANTIALIASING: i32 = 20;
 const MAX_DEPTH: i32 = 50;
 
-fn ray_color(ra: Ray, wor: &World, depth: i32) -> Vec3 {
-    if depth <= 0 {
-        return Vec3::zero();
+fn power_heuristic(f_pdf: f64, g_pdf: f64) -> f64 {
+    let f2 = f_pdf * f_pdf;
+    let g2 = g_pdf * g_pdf;
+    if f2 + g2 > 0.0 {
+        f2 / (f2 + g2)
+    } else {
+        0.0
     }
+}
 
-    if let Some(hit_result) = wor.hit(&ra, 0.001, INFINITY) {
-        let emitted = hit_result
-            .mat_ptr
-            .emitted(&hit_result, 0.0, 0.0, hit_result.p.clone());
-        if let Some(scatter_result) = hit_result.mat_ptr.scatter(&ra, &hit_result) {
-            if scatter_result.3 {
-                return Vec3::elemul(
-                    scatter_result.0,
-                    ray_color(scatter_result.1, &wor, depth - 1),
-                );
-            }
+// Path tracer combining BSDF sampling and light sampling with multiple
+// importance sampling.
+fn ray_color(ra: RayDifferential, wor: &World, depth: i32) -> Vec3 {
+    let mut ray = Ray {
+        origin: ra.ray.origin,
+        direction: ra.ray.direction.unit(),
+    };
+    // Only camera rays carry differentials; later bounces point sample.
+    let mut differential = Some((ra.rx, ra.ry));
+    let mut throughput = Vec3::ones();
+    let mut color = Vec3::zero();
+    // Density of the BSDF sample that produced `ray`, or None when light
+    // sampling could not have produced it (camera rays, specular bounces).
+    let mut bsdf_pdf: Option<f64> = None;
 
-            let light = Arc::new(DiffuseLight {
-                emit: Arc::new(ConstantTexture {
-                    color: Vec3::new(15.0, 15.0, 15.0),
-                }),
-            });
-            let light_shape: Arc<dyn Hitable> = Arc::new(XzRect {
-                x0: 213.0,
-                x1: 343.0,
-                z0: 227.0,
-                z1: 332.0,
-                k: 554.0,
-                mat_ptr: light,
-            });
-            let p0 = HitablePdf {
-                origin: hit_result.p.clone(),
-                ptr: light_shape,
-            };
-            let p1 = CosinePdf::new(hit_result.normal.clone());
-            let p = MixturePdf {
-                p0: Arc::new(p0),
-                p1: Arc::new(p1),
-                d0: 0.5,
-                d1: 0.5,
-            };
+    for _ in 0..depth {
+        let surface = wor.hit(&ray, 0.0, INFINITY);
+        let t_max = surface.as_ref().map_or(INFINITY, |hit_result| hit_result.t);
+        let (scatter, medium_weight) = wor.sample_media(&ray, t_max);
+        throughput = Vec3::elemul(throughput, medium_weight);
+        if throughput == Vec3::zero() {
+            break;
+        }
+        let mut hit_result = match scatter.or(surface) {
+            Some(hit_result) => hit_result,
+            None => break,
+        };
+        if let Some((rx, ry)) = differential.take() {
+            hit_result.compute_differentials(&rx, &ry);
+        }
+        hit_result.mat_ptr.clone().perturb_normal(&mut hit_result);
+        hit_result.face_shading_normal(&-ray.direction.clone());
+        let emitted = hit_result.mat_ptr.emitted(
+            &hit_result,
+            hit_result.fu,
+            hit_result.fv,
+            hit_result.p.clone(),
+        );
+        let weight = match bsdf_pdf {
+            Some(pdf) => power_heuristic(pdf, wor.light_pdf(&ray.origin, &ray.direction)),
+            None => 1.0,
+        };
+        color += Vec3::elemul(throughput.clone(), emitted) * weight;
 
-            let scattered = Ray {
-                origin: hit_result.p.clone(),
-                direction: p.generate(),
+        let mat_ptr = hit_result.mat_ptr.clone();
+        let frame = hit_result.shading_frame();
+        let wo = frame.to_local(-ray.direction.clone());
+
+        if let Some(to_light) = wor.sample_light(&hit_result.p) {
+            let light_ray = hit_result.spawn_ray(to_light.unit());
+            let light_pdf = wor.light_pdf(&hit_result.p, &light_ray.direction);
+            let wi = frame.to_local(light_ray.direction.clone());
+            let f = if hit_result.sides_agree(&light_ray.direction) {
+                mat_ptr.eval(&hit_result, &wo, &wi)
+            } else {
+                Vec3::zero()
             };
-            let pdf_value = p.value(scattered.direction.clone());
+            if light_pdf > 0.0 && f != Vec3::zero() {
+                if let Some(light_hit) = wor.hit(&light_ray, 0.0, INFINITY) {
+                    let light = light_hit.mat_ptr.emitted(
+                        &light_hit,
+                        light_hit.fu,
+                        light_hit.fv,
+                        light_hit.p.clone(),
+                    );
+                    let light = Vec3::elemul(light, wor.transmittance(&light_ray, light_hit.t));
+                    let weight = power_heuristic(light_pdf, mat_ptr.pdf(&hit_result, &wo, &wi));
+                    color += Vec3::elemul(Vec3::elemul(throughput.clone(), f), light) * weight
+                        / light_pdf;
+                }
+            }
+        }
 
-            return emitted
-                + Vec3::elemul(
-                    scatter_result.0
-                        * hit_result
-                            .mat_ptr
-                            .scattering_pdf(&ra, &hit_result, &scattered),
-                    ray_color(scattered, &wor, depth - 1),
-                ) / pdf_value;
+        let scatter = match mat_ptr.sample(&hit_result, &wo, (random_double(), random_double())) {
+            Some(scatter) => scatter,
+            None => break,
+        };
+        let direction = frame.localvec(scatter.wi.clone()).unit();
+        if !hit_result.sides_agree(&direction) {
+            break;
         }
-        return emitted;
+        throughput = Vec3::elemul(throughput, scatter.f.clone() / scatter.pdf);
+        bsdf_pdf = if scatter.is_specular() {
+            None
+        } else {
+            Some(scatter.pdf)
+        };
+        ray = hit_result.spawn_ray(direction);
     }
 
-    Vec3::zero()
-    /*let unit = ra.direction.unit();
-    let t = (unit.y + 1.0) * 0.5;
-    Vec3::lerp(Vec3::new(0.4, 0.6, 0.8), Vec3::new(0.3, 0.5, 0.5), t)*/
+    color
 }
 
 fn cornell_box() -> World {
@@ -132,7 +209,14 @@ fn cornell_box() -> World {
         }),
     });
     let image = Arc::new(Lambertian {
-        albedo: Arc::new(ImageTexture::new()),
+        albedo: Arc::new(
+            ImageTexture::from_path(
+                "raytracer/src/earthmap.jpg",
+                true,
+                (WrapMode::Repeat, WrapMode::Repeat),
+            )
+            .expect("failed to load earthmap.jpg"),
+        ),
     });
     /*let fog = Arc::new(ConstantMedium {
         density: 0.01,
@@ -226,6 +310,15 @@ fn cornell_box() -> World {
     World::new(hitlist)*/
     World {
         root: make_root! {},
+        lights: vec![Arc::new(XzRect {
+            x0: 213.0,
+            x1: 343.0,
+            z0: 227.0,
+            z1: 332.0,
+            k: 554.0,
+            mat_ptr: light,
+        })],
+        media: vec![],
     }
 }
 /*fn final_scene() -> World {
@@ -294,14 +387,20 @@ fn cornell_box() -> World {
         center: Vec3::new(400.0, 200.0, 400.0),
         radius: 100.0,
         mat_ptr: Arc::new(Lambertian {
-            albedo: Arc::new(ImageTexture::new()),
+            albedo: Arc::new(
+            ImageTexture::from_path("raytracer/src/earthmap.jpg", true, (WrapMode::Repeat, WrapMode::Repeat))
+                .expect("failed to load earthmap.jpg"),
+        ),
         }),
     }));
     hitlist.push(Arc::new(Sphere {
         center: Vec3::new(400.0, 200.0, 400.0),
         radius: 100.0,
         mat_ptr: Arc::new(Lambertian {
-            albedo: Arc::new(ImageTexture::new()),
+            albedo: Arc::new(
+            ImageTexture::from_path("raytracer/src/earthmap.jpg", true, (WrapMode::Repeat, WrapMode::Repeat))
+                .expect("failed to load earthmap.jpg"),
+        ),
         }),
     }));
     let mut cube1: Arc<dyn Hitable> = Arc::new(Sphere {
@@ -370,7 +469,7 @@ fn work(cam: Camera, wor: World) {
                         ((x as f64) + (x_step as f64) * length_per_step[0]) / (cam.width as f64);
                     let v =
                         ((y as f64) + (y_step as f64) * length_per_step[1]) / (cam.height as f64);
-                    let ra = cam.get_ray(u, v);
+                    let ra = cam.get_ray_differential(u, v);
                     //let co = ray_color(ra, &wor, MAX_DEPTH);
                     let co = ray_color(ra, &wor, MAX_DEPTH)
                         .min(Vec3::ones())
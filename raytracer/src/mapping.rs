@@ -0,0 +1,334 @@
+use crate::hit::HitResult;
+use crate::texture::*;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// Affine map p -> rows * p + offset.
+#[derive(Clone)]
+pub struct Transform3 {
+    pub rows: [Vec3; 3],
+    pub offset: Vec3,
+}
+
+impl Transform3 {
+    pub fn identity() -> Self {
+        Self::scale(Vec3::ones())
+    }
+    pub fn translate(offset: Vec3) -> Self {
+        Self {
+            offset,
+            ..Self::identity()
+        }
+    }
+    pub fn scale(scale: Vec3) -> Self {
+        Self {
+            rows: [
+                Vec3::new(scale.x, 0.0, 0.0),
+                Vec3::new(0.0, scale.y, 0.0),
+                Vec3::new(0.0, 0.0, scale.z),
+            ],
+            offset: Vec3::zero(),
+        }
+    }
+    // Rotation by `angle` degrees about `axis` (Rodrigues' formula).
+    pub fn rotate(axis: Vec3, angle: f64) -> Self {
+        let a = axis.unit();
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let k = 1.0 - cos;
+        Self {
+            rows: [
+                Vec3::new(
+                    cos + a.x * a.x * k,
+                    a.x * a.y * k - a.z * sin,
+                    a.x * a.z * k + a.y * sin,
+                ),
+                Vec3::new(
+                    a.y * a.x * k + a.z * sin,
+                    cos + a.y * a.y * k,
+                    a.y * a.z * k - a.x * sin,
+                ),
+                Vec3::new(
+                    a.z * a.x * k - a.y * sin,
+                    a.z * a.y * k + a.x * sin,
+                    cos + a.z * a.z * k,
+                ),
+            ],
+            offset: Vec3::zero(),
+        }
+    }
+    pub fn apply(&self, p: Vec3) -> Vec3 {
+        Vec3::new(
+            self.rows[0].clone() * p.clone(),
+            self.rows[1].clone() * p.clone(),
+            self.rows[2].clone() * p,
+        ) + self.offset.clone()
+    }
+    // The transform applying `self` first and `next` second.
+    pub fn then(&self, next: &Self) -> Self {
+        let column = |i: i32| Vec3::new(self.rows[0][i], self.rows[1][i], self.rows[2][i]);
+        let row = |r: &Vec3| {
+            Vec3::new(
+                r.clone() * column(0),
+                r.clone() * column(1),
+                r.clone() * column(2),
+            )
+        };
+        Self {
+            rows: [row(&next.rows[0]), row(&next.rows[1]), row(&next.rows[2])],
+            offset: next.apply(self.offset.clone()),
+        }
+    }
+    // Inverse of an invertible transform, by the cofactors of the rows.
+    pub fn inverse(&self) -> Self {
+        let [a, b, c] = &self.rows;
+        let cols = [
+            Vec3::cross(b.clone(), c.clone()),
+            Vec3::cross(c.clone(), a.clone()),
+            Vec3::cross(a.clone(), b.clone()),
+        ];
+        let det = a.clone() * cols[0].clone();
+        let rows = [
+            Vec3::new(cols[0].x, cols[1].x, cols[2].x) / det,
+            Vec3::new(cols[0].y, cols[1].y, cols[2].y) / det,
+            Vec3::new(cols[0].z, cols[1].z, cols[2].z) / det,
+        ];
+        let linear = Self {
+            rows,
+            offset: Vec3::zero(),
+        };
+        Self {
+            offset: -linear.apply(self.offset.clone()),
+            ..linear
+        }
+    }
+    // Normals transform by the inverse transpose of the linear part.
+    pub fn apply_normal(&self, n: Vec3) -> Vec3 {
+        let [a, b, c] = self.inverse().rows;
+        (a * n.x + b * n.y + c * n.z).unit()
+    }
+}
+
+// Solid texture looked up at `transform` applied to the hit point, to move,
+// scale or turn textures such as `NoiseTexture`.
+pub struct TransformedTexture {
+    pub texture: Arc<dyn Texture>,
+    pub transform: Transform3,
+}
+impl Texture for TransformedTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.texture.value(u, v, self.transform.apply(p))
+    }
+    fn filtered_value(&self, u: f64, v: f64, p: Vec3, footprint: &UvFootprint) -> Vec3 {
+        self.texture
+            .filtered_value(u, v, self.transform.apply(p), footprint)
+    }
+    fn hit_value(&self, hit_record: &HitResult) -> Vec3 {
+        let mut hit_record = hit_record.clone();
+        hit_record.p = self.transform.apply(hit_record.p);
+        hit_record.normal = self.transform.apply_normal(hit_record.normal);
+        hit_record.shading_normal = self.transform.apply_normal(hit_record.shading_normal);
+        self.texture.hit_value(&hit_record)
+    }
+}
+
+// Where a `MappedTexture` takes its texture coordinates from.
+pub enum UvMapping {
+    // The surface's own (u, v), scaled, rotated by `rotation` degrees about
+    // the centre of the unit square and then offset.
+    Uv {
+        scale: (f64, f64),
+        rotation: f64,
+        offset: (f64, f64),
+    },
+    // Projection of p onto the axes `s` and `t`; their lengths set the scale.
+    Planar {
+        s: Vec3,
+        t: Vec3,
+        offset: (f64, f64),
+    },
+    // Longitude and latitude of p seen from `center`, as on `Sphere`.
+    Spherical {
+        center: Vec3,
+    },
+    // Angle around the vertical axis through `center`, and height above it
+    // in units of `height`.
+    Cylindrical {
+        center: Vec3,
+        height: f64,
+    },
+}
+
+impl UvMapping {
+    pub fn map(&self, u: f64, v: f64, p: &Vec3) -> (f64, f64) {
+        match self {
+            UvMapping::Uv {
+                scale,
+                rotation,
+                offset,
+            } => {
+                let (sin, cos) = rotation.to_radians().sin_cos();
+                let (du, dv) = (u - 0.5, v - 0.5);
+                (
+                    (cos * du - sin * dv + 0.5) * scale.0 + offset.0,
+                    (sin * du + cos * dv + 0.5) * scale.1 + offset.1,
+                )
+            }
+            UvMapping::Planar {
+                s: s_axis,
+                t: t_axis,
+                offset,
+            } => (
+                p.clone() * s_axis.clone() + offset.0,
+                p.clone() * t_axis.clone() + offset.1,
+            ),
+            UvMapping::Spherical { center } => {
+                let (mut u, mut v) = (0.0, 0.0);
+                HitResult::get_sphere_uv((p.clone() - center.clone()).unit(), &mut u, &mut v);
+                (u, v)
+            }
+            UvMapping::Cylindrical { center, height } => {
+                let d = p.clone() - center.clone();
+                (1.0 - (d.z.atan2(d.x) + PI) / (2.0 * PI), d.y / height)
+            }
+        }
+    }
+    // The footprint in the mapped coordinates. Only the (u, v) transform is
+    // linear in the surface coordinates; projections point sample.
+    pub fn map_footprint(&self, footprint: &UvFootprint) -> UvFootprint {
+        match self {
+            UvMapping::Uv {
+                scale, rotation, ..
+            } => {
+                let (sin, cos) = rotation.to_radians().sin_cos();
+                let map = |du: f64, dv: f64| {
+                    (
+                        (cos * du - sin * dv) * scale.0,
+                        (sin * du + cos * dv) * scale.1,
+                    )
+                };
+                let (dudx, dvdx) = map(footprint.dudx, footprint.dvdx);
+                let (dudy, dvdy) = map(footprint.dudy, footprint.dvdy);
+                UvFootprint {
+                    dudx,
+                    dvdx,
+                    dudy,
+                    dvdy,
+                }
+            }
+            _ => UvFootprint::default(),
+        }
+    }
+}
+
+pub struct MappedTexture {
+    pub texture: Arc<dyn Texture>,
+    pub mapping: UvMapping,
+}
+impl Texture for MappedTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        let (u, v) = self.mapping.map(u, v, &p);
+        self.texture.value(u, v, p)
+    }
+    fn filtered_value(&self, u: f64, v: f64, p: Vec3, footprint: &UvFootprint) -> Vec3 {
+        let (u, v) = self.mapping.map(u, v, &p);
+        self.texture
+            .filtered_value(u, v, p, &self.mapping.map_footprint(footprint))
+    }
+    fn hit_value(&self, hit_record: &HitResult) -> Vec3 {
+        let mut hit_record = hit_record.clone();
+        let (u, v) = self
+            .mapping
+            .map(hit_record.fu, hit_record.fv, &hit_record.p);
+        hit_record.fu = u;
+        hit_record.fv = v;
+        hit_record.footprint = self.mapping.map_footprint(&hit_record.footprint);
+        self.texture.hit_value(&hit_record)
+    }
+}
+
+// Projects `texture` along the three world axes, `scale` repeats per unit
+// length, and blends the projections by the normal's components raised to
+// `sharpness`. Without a normal (a plain `value` call) they blend evenly.
+pub struct TriplanarTexture {
+    pub texture: Arc<dyn Texture>,
+    pub scale: f64,
+    pub sharpness: f64,
+}
+impl TriplanarTexture {
+    fn blend(&self, p: Vec3, weights: Vec3) -> Vec3 {
+        let weights = Vec3::new(
+            weights.x.abs().powf(self.sharpness),
+            weights.y.abs().powf(self.sharpness),
+            weights.z.abs().powf(self.sharpness),
+        );
+        let total = weights.x + weights.y + weights.z;
+        let q = p.clone() * self.scale;
+        (self.texture.value(q.z, q.y, p.clone()) * weights.x
+            + self.texture.value(q.x, q.z, p.clone()) * weights.y
+            + self.texture.value(q.x, q.y, p) * weights.z)
+            / total
+    }
+}
+impl Texture for TriplanarTexture {
+    fn value(&self, _u: f64, _v: f64, p: Vec3) -> Vec3 {
+        self.blend(p, Vec3::ones())
+    }
+    fn hit_value(&self, hit_record: &HitResult) -> Vec3 {
+        self.blend(hit_record.p.clone(), hit_record.outward_normal())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hit::Ray;
+    use crate::material::{Lambertian, Material};
+    use crate::mesh::VertexColorTexture;
+
+    #[test]
+    fn test_transform_compose() {
+        let transform = Transform3::rotate(Vec3::new(0.0, 1.0, 0.0), 90.0)
+            .then(&Transform3::scale(Vec3::new(2.0, 2.0, 2.0)))
+            .then(&Transform3::translate(Vec3::new(0.0, 1.0, 0.0)));
+        let p = transform.apply(Vec3::new(1.0, 0.0, 0.0));
+        assert!((p.clone() - Vec3::new(0.0, 1.0, -2.0)).length() < 1e-9);
+        let back = transform.inverse().apply(p);
+        assert!((back - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-9);
+    }
+
+    #[test]
+    fn test_wrappers_forward_hit_value() {
+        let vertex_color: Arc<dyn Texture> = Arc::new(VertexColorTexture {
+            fallback: Arc::new(ConstantTexture {
+                color: Vec3::zero(),
+            }),
+        });
+        let ray = Ray {
+            origin: Vec3::new(0.0, 0.0, 1.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+        };
+        let mat_ptr: Arc<dyn Material> = Arc::new(Lambertian {
+            albedo: vertex_color.clone(),
+        });
+        let mut hit_record = HitResult::new(&ray, 1.0, Vec3::new(0.0, 0.0, 1.0), 0.2, 0.7, mat_ptr);
+        let color = Vec3::new(0.1, 0.2, 0.3);
+        hit_record.color = Some(color.clone());
+        let wrappers: Vec<Arc<dyn Texture>> = vec![
+            Arc::new(MappedTexture {
+                texture: vertex_color.clone(),
+                mapping: UvMapping::Uv {
+                    scale: (2.0, 2.0),
+                    rotation: 30.0,
+                    offset: (0.0, 0.0),
+                },
+            }),
+            Arc::new(TransformedTexture {
+                texture: vertex_color,
+                transform: Transform3::rotate(Vec3::new(1.0, 0.0, 0.0), 90.0),
+            }),
+        ];
+        for texture in wrappers {
+            assert_eq!(hit_record.texture_value(&texture), color);
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use crate::hit::*;
+use std::sync::Arc;
+
+// Spatially varying density that scales a medium's coefficients.
+pub trait DensityField {
+    fn density(&self, p: &Vec3) -> f64;
+    // Pieces (start, end, bound) covering [t0, t1] along `ra`, each with an
+    // upper bound of the density over it.
+    fn majorants(&self, ra: &Ray, t0: f64, t1: f64) -> Vec<(f64, f64, f64)>;
+}
+
+// Density from the first channel of a solid texture times `scale`, clamped
+// to `max` so that `max` stays a valid bound for tracking.
+pub struct TextureDensity {
+    pub texture: Arc<dyn Texture>,
+    pub scale: f64,
+    pub max: f64,
+}
+impl DensityField for TextureDensity {
+    fn density(&self, p: &Vec3) -> f64 {
+        (self.texture.value(0.0, 0.0, p.clone()).x * self.scale)
+            .max(0.0)
+            .min(self.max)
+    }
+    fn majorants(&self, _ra: &Ray, t0: f64, t1: f64) -> Vec<(f64, f64, f64)> {
+        vec![(t0, t1, self.max)]
+    }
+}
+
+fn average(v: &Vec3) -> f64 {
+    (v.x + v.y + v.z) / 3.0
+}
+
+fn max_component(v: &Vec3) -> f64 {
+    v.x.max(v.y).max(v.z)
+}
+
+// Medium filling the inside of `boundary`, which may be any closed surface.
+// Absorption and scattering coefficients are per color channel and scaled by
+// `density`; scattering events are shaded with `phase_function`. Media are
+// not part of the BVH: the integrator queries them through `World`.
+pub struct HeterogeneousMedium {
+    pub boundary: Arc<dyn Hitable>,
+    pub density: Arc<dyn DensityField>,
+    pub sigma_a: Vec3,
+    pub sigma_s: Vec3,
+    pub phase_function: Arc<dyn Material>,
+}
+
+impl HeterogeneousMedium {
+    fn sigma_t(&self) -> Vec3 {
+        self.sigma_a.clone() + self.sigma_s.clone()
+    }
+    // Pieces (start, end, majorant) bounding the extinction along `ra` where
+    // it is inside the boundary, up to `t_max`. Pieces with NaN ends, from
+    // degenerate geometry, are left out.
+    fn majorants(&self, ra: &Ray, t_max: f64) -> Vec<(f64, f64, f64)> {
+        let sigma_t = max_component(&self.sigma_t());
+        inside_intervals(&*self.boundary, ra, 0.0, t_max)
+            .into_iter()
+            .flat_map(|(t0, t1)| self.density.majorants(ra, t0, t1))
+            .filter(|&(s0, s1, _)| !s0.is_nan() && !s1.is_nan())
+            .map(|(s0, s1, bound)| (s0, s1, bound * sigma_t))
+            .collect()
+    }
+    // Calls `collide` at tentative collisions of delta tracking along `ra`
+    // up to `t_max`, with the collision's t, the local density and the
+    // combined majorant. Stops when `collide` returns false.
+    fn track(&self, ra: &Ray, t_max: f64, mut collide: impl FnMut(f64, f64, f64) -> bool) {
+        let ray_length = ra.direction.length();
+        for (s0, s1, majorant) in self.majorants(ra, t_max) {
+            if majorant <= 0.0 {
+                continue;
+            }
+            let mut t = s0;
+            loop {
+                t -= (1.0 - random_double()).ln() / (majorant * ray_length);
+                if t >= s1 {
+                    break;
+                }
+                if !collide(t, self.density.density(&ra.at(t)), majorant) {
+                    return;
+                }
+            }
+        }
+    }
+    // Spectral tracking up to the surface at `t_max`; see `track_media`.
+    pub fn sample(&self, ra: &Ray, t_max: f64) -> (Option<f64>, Vec3) {
+        let (scatter, weight) = track_media(&[self], ra, t_max);
+        (scatter.map(|(t, _)| t), weight)
+    }
+    // Ratio-tracking estimate of the transmittance up to `t_max`.
+    pub fn transmittance(&self, ra: &Ray, t_max: f64) -> Vec3 {
+        let sigma_t = self.sigma_t();
+        let mut transmittance = Vec3::ones();
+        self.track(ra, t_max, |_, density, majorant| {
+            let ratio = Vec3::ones() - sigma_t.clone() * (density / majorant);
+            transmittance = Vec3::elemul(transmittance.clone(), ratio);
+            max_component(&transmittance) > 0.0
+        });
+        transmittance
+    }
+}
+
+// Spectral tracking (Kutz et al. 2017) through possibly overlapping `media`
+// at once, against the sum of their majorants, up to the surface at
+// `t_max`. Returns the scattering t and the index of the medium scattering
+// there, if any, and the throughput weight of the segment; an absorbed path
+// gets a zero weight.
+pub fn track_media(
+    media: &[&HeterogeneousMedium],
+    ra: &Ray,
+    t_max: f64,
+) -> (Option<(f64, usize)>, Vec3) {
+    let ray_length = ra.direction.length();
+    let pieces: Vec<_> = media
+        .iter()
+        .map(|medium| medium.majorants(ra, t_max))
+        .collect();
+    let mut bounds: Vec<f64> = pieces
+        .iter()
+        .flatten()
+        .flat_map(|&(s0, s1, _)| vec![s0, s1])
+        .collect();
+    bounds.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    bounds.dedup();
+    let mut weight = Vec3::ones();
+    for span in bounds.windows(2) {
+        // The media inside over this span, with their majorants.
+        let middle = 0.5 * (span[0] + span[1]);
+        let inside: Vec<(usize, f64)> = pieces
+            .iter()
+            .enumerate()
+            .filter_map(|(index, pieces)| {
+                pieces
+                    .iter()
+                    .find(|&&(s0, s1, _)| s0 <= middle && middle < s1)
+                    .map(|&(_, _, majorant)| (index, majorant))
+            })
+            .collect();
+        let majorant: f64 = inside.iter().map(|&(_, majorant)| majorant).sum();
+        if majorant <= 0.0 {
+            continue;
+        }
+        let mut t = span[0];
+        loop {
+            t -= (1.0 - random_double()).ln() / (majorant * ray_length);
+            if t >= span[1] {
+                break;
+            }
+            // Absorption, scattering by each medium and null collisions are
+            // picked in proportion to their weighted coefficients.
+            let p = ra.at(t);
+            let mut sigma_a = Vec3::zero();
+            let mut sigma_n = Vec3::ones() * majorant;
+            let sigma_s: Vec<Vec3> = inside
+                .iter()
+                .map(|&(index, _)| {
+                    let medium = media[index];
+                    let density = medium.density.density(&p);
+                    sigma_a += medium.sigma_a.clone() * density;
+                    sigma_n -= medium.sigma_t() * density;
+                    medium.sigma_s.clone() * density
+                })
+                .collect();
+            let p_a = average(&Vec3::elemul(weight.clone(), sigma_a));
+            let p_s: Vec<f64> = sigma_s
+                .iter()
+                .map(|sigma| average(&Vec3::elemul(weight.clone(), sigma.clone())))
+                .collect();
+            let p_n = average(&Vec3::elemul(weight.clone(), sigma_n.clone()));
+            let total = p_a + p_s.iter().sum::<f64>() + p_n;
+            if total <= 0.0 {
+                return (None, Vec3::zero());
+            }
+            let mut xi = random_double() * total;
+            if xi < p_a {
+                return (None, Vec3::zero());
+            }
+            xi -= p_a;
+            for (i, sigma) in sigma_s.into_iter().enumerate() {
+                if xi < p_s[i] {
+                    weight = Vec3::elemul(weight, sigma) / (majorant * p_s[i] / total);
+                    return (Some((t, inside[i].0)), weight);
+                }
+                xi -= p_s[i];
+            }
+            weight = Vec3::elemul(weight, sigma_n) / (majorant * p_n / total);
+        }
+    }
+    (None, weight)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A density with a NaN majorant piece next to a valid one.
+    struct Broken;
+    impl DensityField for Broken {
+        fn density(&self, _p: &Vec3) -> f64 {
+            0.5
+        }
+        fn majorants(&self, _ra: &Ray, t0: f64, t1: f64) -> Vec<(f64, f64, f64)> {
+            vec![(t0, t1, 1.0), (f64::NAN, f64::NAN, 1.0)]
+        }
+    }
+
+    #[test]
+    fn test_homogeneous_transmittance() {
+        let medium = HeterogeneousMedium {
+            boundary: Arc::new(Sphere {
+                center: Vec3::zero(),
+                radius: 1.0,
+                mat_ptr: white_lambertian(),
+            }),
+            density: Arc::new(TextureDensity {
+                texture: Arc::new(ConstantTexture {
+                    color: Vec3::ones() * 0.5,
+                }),
+                scale: 1.0,
+                max: 1.0,
+            }),
+            sigma_a: Vec3::new(0.2, 0.5, 1.0),
+            sigma_s: Vec3::new(0.3, 0.3, 0.3),
+            phase_function: Arc::new(Isotropic {
+                albedo: white_texture(),
+            }),
+        };
+        let ray = Ray {
+            origin: Vec3::new(0.0, 0.0, -3.0),
+            direction: Vec3::new(0.0, 0.0, 1.0),
+        };
+        let samples = 20000;
+        let mut sum = Vec3::zero();
+        for _ in 0..samples {
+            sum += medium.transmittance(&ray, f64::INFINITY);
+        }
+        let estimate = sum / samples as f64;
+        let expected = Vec3::new((-0.5f64).exp(), (-0.8f64).exp(), (-1.3f64).exp());
+        assert!((estimate - expected).length() < 0.02);
+
+        // Degenerate majorant pieces are skipped rather than panic.
+        let broken = HeterogeneousMedium {
+            density: Arc::new(Broken),
+            ..medium
+        };
+        let (_, weight) = broken.sample(&ray, f64::INFINITY);
+        assert!(!weight.x.is_nan());
+    }
+}
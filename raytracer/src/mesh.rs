@@ -0,0 +1,276 @@
+use crate::hit::*;
+use crate::shapes::area_pdf;
+use crate::world::BVHNode;
+use std::io::{Error, ErrorKind};
+use std::sync::Arc;
+
+// A malformed-file error from the loader for `format`.
+pub(crate) fn invalid(format: &str, message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("{}: {}", format, message))
+}
+
+// Ray parameter and barycentrics of the second and third vertices
+// (Moller-Trumbore).
+pub fn hit_triangle(ra: &Ray, p: [&Vec3; 3], t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+    let e1 = p[1].clone() - p[0].clone();
+    let e2 = p[2].clone() - p[0].clone();
+    let pvec = Vec3::cross(ra.direction.clone(), e2.clone());
+    let det = e1.clone() * pvec.clone();
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let tvec = ra.origin.clone() - p[0].clone();
+    let b1 = tvec.clone() * pvec / det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+    let qvec = Vec3::cross(tvec, e1);
+    let b2 = ra.direction.clone() * qvec.clone() / det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+    let t = e2 * qvec / det;
+    if t <= t_min || t > t_max {
+        return None;
+    }
+    Some((t, b1, b2))
+}
+
+// Indexed triangles. `normals`, `uvs` and `colors` are either empty or hold
+// one entry per position. Triangles wind counterclockwise seen from the
+// front, unless vertex normals say otherwise.
+#[derive(Clone, Default)]
+pub struct TriangleMesh {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>,
+    pub colors: Vec<Vec3>,
+    pub indices: Vec<[usize; 3]>,
+}
+
+impl TriangleMesh {
+    // A BVH over the triangles, all shaded with `mat_ptr`.
+    pub fn into_hitable(self, mat_ptr: Arc<dyn Material>) -> Arc<dyn Hitable> {
+        let mut triangles = self.into_triangles(mat_ptr);
+        assert!(!triangles.is_empty(), "mesh has no triangles");
+        let length = triangles.len();
+        Arc::new(BVHNode::new(&mut triangles, 0, length))
+    }
+    // The triangles one by one, for example to sample an emissive mesh as
+    // lights.
+    pub fn into_triangles(self, mat_ptr: Arc<dyn Material>) -> Vec<Arc<dyn Hitable>> {
+        let mesh = Arc::new(self);
+        (0..mesh.indices.len())
+            .map(|index| -> Arc<dyn Hitable> {
+                Arc::new(Triangle {
+                    mesh: mesh.clone(),
+                    index,
+                    mat_ptr: mat_ptr.clone(),
+                })
+            })
+            .collect()
+    }
+    // Vertex normals averaged from the faces around each vertex, weighted by
+    // face area.
+    pub fn compute_normals(&mut self) {
+        let mut normals = vec![Vec3::zero(); self.positions.len()];
+        for face in &self.indices {
+            let p = [
+                &self.positions[face[0]],
+                &self.positions[face[1]],
+                &self.positions[face[2]],
+            ];
+            let n = Vec3::cross(p[1].clone() - p[0].clone(), p[2].clone() - p[0].clone());
+            for &v in face {
+                normals[v] += n.clone();
+            }
+        }
+        self.normals = normals
+            .into_iter()
+            .map(|n| {
+                if n.squared_length() > 0.0 {
+                    n.unit()
+                } else {
+                    n
+                }
+            })
+            .collect();
+    }
+}
+
+pub struct Triangle {
+    pub mesh: Arc<TriangleMesh>,
+    pub index: usize,
+    pub mat_ptr: Arc<dyn Material>,
+}
+
+impl Triangle {
+    fn vertices(&self) -> [&Vec3; 3] {
+        let face = &self.mesh.indices[self.index];
+        [
+            &self.mesh.positions[face[0]],
+            &self.mesh.positions[face[1]],
+            &self.mesh.positions[face[2]],
+        ]
+    }
+    fn area(&self) -> f64 {
+        let p = self.vertices();
+        Vec3::cross(p[1].clone() - p[0].clone(), p[2].clone() - p[0].clone()).length() / 2.0
+    }
+}
+
+impl Hitable for Triangle {
+    fn hit(&self, ra: &Ray, t_min: f64, t_max: f64) -> Option<HitResult> {
+        let p = self.vertices();
+        let (t, b1, b2) = hit_triangle(ra, p, t_min, t_max)?;
+        let b = [1.0 - b1 - b2, b1, b2];
+        let face = &self.mesh.indices[self.index];
+        let (e1, e2) = (p[1].clone() - p[0].clone(), p[2].clone() - p[0].clone());
+        let mut normal = Vec3::cross(e1.clone(), e2.clone()).unit();
+        let interpolate = |values: &[Vec3]| {
+            values[face[0]].clone() * b[0]
+                + values[face[1]].clone() * b[1]
+                + values[face[2]].clone() * b[2]
+        };
+
+        let (uv, dpdu, dpdv) = if self.mesh.uvs.is_empty() {
+            ([(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)], e1, e2)
+        } else {
+            let uv = [
+                self.mesh.uvs[face[0]],
+                self.mesh.uvs[face[1]],
+                self.mesh.uvs[face[2]],
+            ];
+            let (du1, dv1) = (uv[1].0 - uv[0].0, uv[1].1 - uv[0].1);
+            let (du2, dv2) = (uv[2].0 - uv[0].0, uv[2].1 - uv[0].1);
+            let det = du1 * dv2 - dv1 * du2;
+            if det.abs() < 1e-12 {
+                (uv, e1, e2)
+            } else {
+                (
+                    uv,
+                    (e1.clone() * dv2 - e2.clone() * dv1) / det,
+                    (e2 * du1 - e1 * du2) / det,
+                )
+            }
+        };
+        let fu = b[0] * uv[0].0 + b[1] * uv[1].0 + b[2] * uv[2].0;
+        let fv = b[0] * uv[0].1 + b[1] * uv[1].1 + b[2] * uv[2].1;
+        let shading = if self.mesh.normals.is_empty() {
+            None
+        } else {
+            Some(interpolate(&self.mesh.normals)).filter(|n| n.squared_length() > 0.0)
+        };
+        // Authored normals decide which side is the front, whatever the
+        // winding.
+        if let Some(shading) = &shading {
+            if normal.clone() * shading.clone() < 0.0 {
+                normal = -normal;
+            }
+        }
+
+        let mut hit_result = HitResult::new(ra, t, normal, fu, fv, self.mat_ptr.clone());
+        // Barycentric interpolation is more accurate than `Ray::at`.
+        let weighted = [
+            p[0].clone() * b[0],
+            p[1].clone() * b[1],
+            p[2].clone() * b[2],
+        ];
+        hit_result.p = weighted[0].clone() + weighted[1].clone() + weighted[2].clone();
+        hit_result.p_error = (weighted[0].abs() + weighted[1].abs() + weighted[2].abs()) * gamma(7);
+        hit_result.dpdu = dpdu;
+        hit_result.dpdv = dpdv;
+        if let Some(shading) = shading {
+            let shading = shading.unit();
+            hit_result.shading_normal = if hit_result.front_face {
+                shading
+            } else {
+                -shading
+            };
+        }
+        if !self.mesh.colors.is_empty() {
+            hit_result.color = Some(interpolate(&self.mesh.colors));
+        }
+        Some(hit_result)
+    }
+    fn bounding_box(&self) -> Option<AABB> {
+        let p = self.vertices();
+        let min = p[0].min(p[1].clone()).min(p[2].clone());
+        let max = p[0].max(p[1].clone()).max(p[2].clone());
+        Some(AABB {
+            min: min - Vec3::ones() * 0.0001,
+            max: max + Vec3::ones() * 0.0001,
+        })
+    }
+    fn pdf_value(&self, o: Vec3, v: Vec3) -> f64 {
+        area_pdf(self, self.area(), o, v)
+    }
+    fn random(&self, o: Vec3) -> Vec3 {
+        let p = self.vertices();
+        let s = random_double().sqrt();
+        let r = random_double();
+        let (b1, b2) = (s * (1.0 - r), s * r);
+        p[0].clone() * (1.0 - s) + p[1].clone() * b1 + p[2].clone() * b2 - o
+    }
+}
+
+// The vertex color of the mesh that was hit, or `fallback` where there is
+// none.
+pub struct VertexColorTexture {
+    pub fallback: Arc<dyn Texture>,
+}
+impl Texture for VertexColorTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.fallback.value(u, v, p)
+    }
+    fn hit_value(&self, hit_record: &HitResult) -> Vec3 {
+        match &hit_record.color {
+            Some(color) => color.clone(),
+            None => self.fallback.hit_value(hit_record),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_triangle_interpolation() {
+        let mesh = Arc::new(TriangleMesh {
+            positions: vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(2.0, 0.0, 0.0),
+                Vec3::new(0.0, 2.0, 0.0),
+            ],
+            normals: vec![],
+            uvs: vec![(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)],
+            colors: vec![
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(0.0, 0.0, 1.0),
+            ],
+            indices: vec![[0, 1, 2]],
+        });
+        let triangle = Triangle {
+            mesh,
+            index: 0,
+            mat_ptr: Arc::new(Lambertian {
+                albedo: Arc::new(VertexColorTexture {
+                    fallback: white_texture(),
+                }),
+            }),
+        };
+        let ra = Ray {
+            origin: Vec3::new(0.5, 1.0, 3.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+        };
+        let hit = triangle.hit(&ra, 0.0, f64::INFINITY).unwrap();
+        assert!((hit.t - 3.0).abs() < 1e-9);
+        assert!(hit.front_face);
+        assert!((hit.fu - 0.25).abs() < 1e-9 && (hit.fv - 0.5).abs() < 1e-9);
+        let color = hit.color.unwrap();
+        assert!((color - Vec3::new(0.25, 0.25, 0.5)).length() < 1e-9);
+        assert!((hit.dpdu - Vec3::new(2.0, 0.0, 0.0)).length() < 1e-9);
+    }
+}
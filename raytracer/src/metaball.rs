@@ -0,0 +1,282 @@
+use crate::hit::*;
+use std::sync::Arc;
+
+// Falloff of a ball's field with s, the squared distance from its center
+// over the squared radius. Each is 1 at the center, decreases to 0 at the
+// radius and stays 0 beyond.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Falloff {
+    // (1 - s)^3.
+    Cubic,
+    // Wyvill's soft objects, which also flatten out at the center.
+    SoftObject,
+    // Nishimura's metaballs, piecewise quadratic in the distance.
+    Nishimura,
+}
+
+impl Falloff {
+    fn value(self, s: f64) -> f64 {
+        if s >= 1.0 {
+            return 0.0;
+        }
+        match self {
+            Falloff::Cubic => (1.0 - s).powi(3),
+            Falloff::SoftObject => 1.0 + s * (-22.0 + s * (17.0 - 4.0 * s)) / 9.0,
+            Falloff::Nishimura => {
+                if s <= 1.0 / 9.0 {
+                    1.0 - 3.0 * s
+                } else {
+                    1.5 * (1.0 - s.sqrt()).powi(2)
+                }
+            }
+        }
+    }
+    // Derivative with respect to s.
+    fn derivative(self, s: f64) -> f64 {
+        if s >= 1.0 {
+            return 0.0;
+        }
+        match self {
+            Falloff::Cubic => -3.0 * (1.0 - s).powi(2),
+            Falloff::SoftObject => (-22.0 + s * (34.0 - 12.0 * s)) / 9.0,
+            Falloff::Nishimura => {
+                if s <= 1.0 / 9.0 {
+                    -3.0
+                } else {
+                    -1.5 * (1.0 - s.sqrt()) / s.sqrt()
+                }
+            }
+        }
+    }
+}
+
+// A source of the field, reaching out to `radius`. Negative weights carve
+// into the other balls.
+#[derive(Clone, Debug)]
+pub struct Metaball {
+    pub center: Vec3,
+    pub radius: f64,
+    pub weight: f64,
+}
+
+// The surface where the summed field of the balls equals `threshold`,
+// which must be positive. Hits are found to within `epsilon`.
+pub struct Metaballs {
+    pub balls: Vec<Metaball>,
+    pub falloff: Falloff,
+    pub threshold: f64,
+    pub epsilon: f64,
+    pub mat_ptr: Arc<dyn Material>,
+}
+
+// A ball as seen along a ray: its squared distance from the ray is
+// a t^2 + b t + c.
+struct BallSpan<'a> {
+    ball: &'a Metaball,
+    a: f64,
+    b: f64,
+    c: f64,
+}
+
+impl BallSpan<'_> {
+    fn s(&self, t: f64) -> f64 {
+        ((self.a * t + self.b) * t + self.c) / (self.ball.radius * self.ball.radius)
+    }
+}
+
+impl Metaballs {
+    // The field decreases outwards, so the normal is against its gradient.
+    // Where the gradient vanishes, as at a saddle between balls, it faces
+    // back along the ray instead.
+    fn normal(&self, p: &Vec3, direction: &Vec3) -> Vec3 {
+        let gradient = self.balls.iter().fold(Vec3::zero(), |sum, ball| {
+            let d = p.clone() - ball.center.clone();
+            let r2 = ball.radius * ball.radius;
+            let s = d.squared_length() / r2;
+            sum + d * (ball.weight * self.falloff.derivative(s) * 2.0 / r2)
+        });
+        if gradient.squared_length() > 0.0 {
+            -gradient.unit()
+        } else {
+            -direction.unit()
+        }
+    }
+    fn field_along(&self, spans: &[BallSpan], t: f64) -> f64 {
+        spans
+            .iter()
+            .map(|span| span.ball.weight * self.falloff.value(span.s(t)))
+            .sum::<f64>()
+            - self.threshold
+    }
+    // Bounds on the field over [t0, t1]. Falloffs decrease with s, so each
+    // ball contributes between its values at the largest and smallest s.
+    fn field_bounds(&self, spans: &[BallSpan], t0: f64, t1: f64) -> (f64, f64) {
+        let mut low = -self.threshold;
+        let mut high = -self.threshold;
+        for span in spans {
+            let (s0, s1) = (span.s(t0), span.s(t1));
+            let vertex = -span.b / (2.0 * span.a);
+            let s_min = if vertex > t0 && vertex < t1 {
+                span.s(vertex)
+            } else {
+                s0.min(s1)
+            };
+            let s_max = s0.max(s1);
+            let near = span.ball.weight * self.falloff.value(s_min);
+            let far = span.ball.weight * self.falloff.value(s_max);
+            low += near.min(far);
+            high += near.max(far);
+        }
+        (low, high)
+    }
+    // The first crossing of the surface in [t0, t1]: intervals whose bounds
+    // rule out a root are dropped, the rest are halved until shorter than
+    // `tolerance`, and a change of sign across one is a hit.
+    fn first_root(&self, spans: &[BallSpan], t0: f64, t1: f64, tolerance: f64) -> Option<f64> {
+        let (low, high) = self.field_bounds(spans, t0, t1);
+        if low > 0.0 || high < 0.0 {
+            return None;
+        }
+        if t1 - t0 < tolerance {
+            let (f0, f1) = (self.field_along(spans, t0), self.field_along(spans, t1));
+            if (f0 > 0.0) == (f1 > 0.0) {
+                return None;
+            }
+            return Some(t0 + (t1 - t0) * f0 / (f0 - f1));
+        }
+        let middle = 0.5 * (t0 + t1);
+        self.first_root(spans, t0, middle, tolerance)
+            .or_else(|| self.first_root(spans, middle, t1, tolerance))
+    }
+}
+
+impl Hitable for Metaballs {
+    fn hit(&self, ra: &Ray, t_min: f64, t_max: f64) -> Option<HitResult> {
+        let (t0, t1) = self.bounding_box()?.clip(ra, t_min, t_max)?;
+        // Only the balls the ray passes through matter, and only where it
+        // passes through one of them.
+        let a = ra.direction.squared_length();
+        let mut range = (t1, t0);
+        let mut spans = Vec::new();
+        for ball in &self.balls {
+            let oc = ra.origin.clone() - ball.center.clone();
+            let b = 2.0 * (oc.clone() * ra.direction.clone());
+            let c = oc.squared_length();
+            let discriminant = b * b - 4.0 * a * (c - ball.radius * ball.radius);
+            if discriminant <= 0.0 {
+                continue;
+            }
+            let root = discriminant.sqrt();
+            let (enter, exit) = ((-b - root) / (2.0 * a), (-b + root) / (2.0 * a));
+            if exit < t0 || enter > t1 {
+                continue;
+            }
+            range = (range.0.min(enter), range.1.max(exit));
+            spans.push(BallSpan { ball, a, b, c });
+        }
+        let (t0, t1) = (t0.max(range.0), t1.min(range.1));
+        if t0 >= t1 {
+            return None;
+        }
+
+        let tolerance = self.epsilon / a.sqrt();
+        let t = self.first_root(&spans, t0, t1, tolerance)?;
+        let p = ra.at(t);
+        let mut hit_result = HitResult::new(
+            ra,
+            t,
+            self.normal(&p, &ra.direction),
+            0.0,
+            0.0,
+            self.mat_ptr.clone(),
+        );
+        hit_result.set_implicit_error(self.epsilon);
+        Some(hit_result)
+    }
+    // The surface lies within the balls that add to the field.
+    fn bounding_box(&self) -> Option<AABB> {
+        self.balls
+            .iter()
+            .filter(|ball| ball.weight > 0.0)
+            .map(|ball| AABB {
+                min: ball.center.clone() - Vec3::ones() * ball.radius,
+                max: ball.center.clone() + Vec3::ones() * ball.radius,
+            })
+            .fold(None, |bbox, ball| match bbox {
+                Some(bbox) => Some(AABB::surrounding_box(bbox, ball)),
+                None => Some(ball),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metaballs() {
+        let mat_ptr = white_lambertian();
+        let ball = |x: f64| Metaball {
+            center: Vec3::new(x, 0.0, 0.0),
+            radius: 2.0,
+            weight: 1.0,
+        };
+        // Alone, a cubic ball reaches 1/8 where s = 1/2.
+        let single = Metaballs {
+            balls: vec![ball(0.0)],
+            falloff: Falloff::Cubic,
+            threshold: 0.125,
+            epsilon: 1e-6,
+            mat_ptr: mat_ptr.clone(),
+        };
+        let ray = Ray {
+            origin: Vec3::new(0.0, 0.0, 5.0),
+            direction: Vec3::new(0.0, 0.0, -2.0),
+        };
+        let hit = single.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        assert!((hit.t - (5.0 - 2.0f64.sqrt()) / 2.0).abs() < 1e-5);
+        assert!((hit.normal.clone() - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-6);
+        // From inside, the ray finds the far side.
+        let inside = Ray {
+            origin: Vec3::zero(),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+        };
+        let hit = single.hit(&inside, 0.0, f64::INFINITY).unwrap();
+        assert!((hit.t - 2.0f64.sqrt()).abs() < 1e-5);
+        assert!(!hit.front_face);
+
+        // Two balls 3 apart do not reach the threshold alone at the midpoint,
+        // but together they bridge the gap.
+        let pair = Metaballs {
+            balls: vec![ball(-1.5), ball(1.5)],
+            falloff: Falloff::SoftObject,
+            threshold: 0.25,
+            epsilon: 1e-6,
+            mat_ptr,
+        };
+        let bbox = pair.bounding_box().unwrap();
+        assert_eq!(bbox.min, Vec3::new(-3.5, -2.0, -2.0));
+        assert_eq!(bbox.max, Vec3::new(3.5, 2.0, 2.0));
+        assert!(Falloff::SoftObject.value(1.5 * 1.5 / 4.0) < 0.25);
+        let hit = pair.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        let field: f64 = pair
+            .balls
+            .iter()
+            .map(|ball| {
+                Falloff::SoftObject
+                    .value((hit.p.clone() - ball.center.clone()).squared_length() / 4.0)
+            })
+            .sum();
+        assert!((field - 0.25).abs() < 1e-6);
+        assert!(hit.normal.z > 0.99);
+        let miss = Ray {
+            origin: Vec3::new(0.0, 1.9, 5.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+        };
+        assert!(pair.hit(&miss, 0.0, f64::INFINITY).is_none());
+
+        // The gradient cancels midway between the balls.
+        let normal = pair.normal(&Vec3::zero(), &Vec3::new(0.0, 0.0, -2.0));
+        assert_eq!(normal, Vec3::new(0.0, 0.0, 1.0));
+    }
+}
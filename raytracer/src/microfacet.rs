@@ -0,0 +1,140 @@
+pub use crate::vec3::Vec3;
+use std::f64::consts::PI;
+
+// Trowbridge-Reitz (GGX) distribution, in shading space where the normal is +z.
+pub struct Ggx {
+    pub alpha_x: f64,
+    pub alpha_y: f64,
+}
+
+impl Ggx {
+    pub fn new(roughness: f64) -> Self {
+        let alpha = roughness * roughness;
+        Self {
+            alpha_x: alpha,
+            alpha_y: alpha,
+        }
+    }
+    pub fn anisotropic(roughness: f64, anisotropic: f64) -> Self {
+        let aspect = (1.0 - 0.9 * anisotropic).sqrt();
+        let alpha = roughness * roughness;
+        Self {
+            alpha_x: (alpha / aspect).max(0.0001),
+            alpha_y: (alpha * aspect).max(0.0001),
+        }
+    }
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < 0.001
+    }
+    pub fn d(&self, m: &Vec3) -> f64 {
+        if m.z <= 0.0 {
+            return 0.0;
+        }
+        let e = m.x * m.x / (self.alpha_x * self.alpha_x)
+            + m.y * m.y / (self.alpha_y * self.alpha_y)
+            + m.z * m.z;
+        1.0 / (PI * self.alpha_x * self.alpha_y * e * e)
+    }
+    pub fn lambda(&self, w: &Vec3) -> f64 {
+        if w.z == 0.0 {
+            return 0.0;
+        }
+        let tan2 = (self.alpha_x * self.alpha_x * w.x * w.x
+            + self.alpha_y * self.alpha_y * w.y * w.y)
+            / (w.z * w.z);
+        ((1.0 + tan2).sqrt() - 1.0) * 0.5
+    }
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+    // Samples a microfacet normal proportionally to D(m) * cos(theta_m).
+    pub fn sample_m(&self, u1: f64, u2: f64) -> Vec3 {
+        let phi =
+            (self.alpha_y * (2.0 * PI * u2).sin()).atan2(self.alpha_x * (2.0 * PI * u2).cos());
+        let (sin_phi, cos_phi) = phi.sin_cos();
+        let inv_alpha2 = cos_phi * cos_phi / (self.alpha_x * self.alpha_x)
+            + sin_phi * sin_phi / (self.alpha_y * self.alpha_y);
+        let tan2_theta = u1 / ((1.0 - u1).max(1e-12) * inv_alpha2);
+        let cos_theta = 1.0 / (1.0 + tan2_theta).sqrt();
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        Vec3::new(sin_theta * cos_phi, sin_theta * sin_phi, cos_theta)
+    }
+    pub fn pdf_m(&self, m: &Vec3) -> f64 {
+        self.d(m) * m.z.abs()
+    }
+}
+
+pub fn fresnel_dielectric(cos_i: f64, etai_over_etat: f64) -> f64 {
+    let cos_i = cos_i.abs().min(1.0);
+    let sin2_t = etai_over_etat * etai_over_etat * (1.0 - cos_i * cos_i);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let rs = (etai_over_etat * cos_i - cos_t) / (etai_over_etat * cos_i + cos_t);
+    let rp = (cos_i - etai_over_etat * cos_t) / (cos_i + etai_over_etat * cos_t);
+    0.5 * (rs * rs + rp * rp)
+}
+
+pub fn reflect_about(wo: &Vec3, m: &Vec3) -> Vec3 {
+    m.clone() * (wo.clone() * m.clone()) * 2.0 - wo.clone()
+}
+
+// wo points away from the surface; returns None on total internal reflection.
+pub fn refract_about(wo: &Vec3, m: &Vec3, etai_over_etat: f64) -> Option<Vec3> {
+    let cos_i = wo.clone() * m.clone();
+    let sin2_t = etai_over_etat * etai_over_etat * (1.0 - cos_i * cos_i).max(0.0);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let sign = if cos_i < 0.0 { -1.0 } else { 1.0 };
+    Some(-wo.clone() * etai_over_etat + m.clone() * (etai_over_etat * cos_i - sign * cos_t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::random_double;
+
+    #[test]
+    fn test_ggx_sampling() {
+        // D(m) cos(theta_m) integrates to one over the hemisphere, and
+        // `sample_m` draws normals with that density.
+        for ggx in &[Ggx::new(0.5), Ggx::anisotropic(0.7, 0.8)] {
+            let steps = 400;
+            let (mut total, mut mean_z, mut mean_x2) = (0.0, 0.0, 0.0);
+            for i in 0..steps {
+                let theta = (i as f64 + 0.5) / steps as f64 * PI / 2.0;
+                for j in 0..steps {
+                    let phi = (j as f64 + 0.5) / steps as f64 * 2.0 * PI;
+                    let m = Vec3::new(
+                        theta.sin() * phi.cos(),
+                        theta.sin() * phi.sin(),
+                        theta.cos(),
+                    );
+                    let weight = ggx.pdf_m(&m) * theta.sin() * (PI / 2.0) * (2.0 * PI)
+                        / (steps * steps) as f64;
+                    total += weight;
+                    mean_z += weight * m.z;
+                    mean_x2 += weight * m.x * m.x;
+                }
+            }
+            assert!((total - 1.0).abs() < 0.01, "integral {}", total);
+
+            let samples = 20000;
+            let (mut sampled_z, mut sampled_x2) = (0.0, 0.0);
+            for i in 0..samples {
+                let m = ggx.sample_m((i as f64 + 0.5) / samples as f64, random_double());
+                assert!((m.length() - 1.0).abs() < 1e-9 && m.z > 0.0);
+                sampled_z += m.z / samples as f64;
+                sampled_x2 += m.x * m.x / samples as f64;
+            }
+            assert!((sampled_z - mean_z).abs() < 0.01);
+            assert!((sampled_x2 - mean_x2).abs() < 0.01);
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use crate::hit::HitResult;
+use crate::texture::*;
+use std::sync::Arc;
+
+// A texture computed from other textures. `combine` is handed the lookup to
+// apply to each input, so footprint filtering and hit-dependent lookups such
+// as triplanar projection reach the leaves of the graph.
+pub trait TextureNode {
+    fn combine(&self, input: &dyn Fn(&Arc<dyn Texture>) -> Vec3) -> Vec3;
+}
+
+impl<T: TextureNode> Texture for T {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.combine(&|texture| texture.value(u, v, p.clone()))
+    }
+    fn filtered_value(&self, u: f64, v: f64, p: Vec3, footprint: &UvFootprint) -> Vec3 {
+        self.combine(&|texture| texture.filtered_value(u, v, p.clone(), footprint))
+    }
+    fn hit_value(&self, hit_record: &HitResult) -> Vec3 {
+        self.combine(&|texture| texture.hit_value(hit_record))
+    }
+}
+
+// Per channel, `a` where `factor` is 0 and `b` where it is 1.
+pub struct MixTexture {
+    pub a: Arc<dyn Texture>,
+    pub b: Arc<dyn Texture>,
+    pub factor: Arc<dyn Texture>,
+}
+impl TextureNode for MixTexture {
+    fn combine(&self, input: &dyn Fn(&Arc<dyn Texture>) -> Vec3) -> Vec3 {
+        let (a, b) = (input(&self.a), input(&self.b));
+        a.clone() + Vec3::elemul(b - a, input(&self.factor))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Arithmetic {
+    Add,
+    Subtract,
+    Multiply,
+}
+
+pub struct ArithmeticTexture {
+    pub op: Arithmetic,
+    pub a: Arc<dyn Texture>,
+    pub b: Arc<dyn Texture>,
+}
+impl TextureNode for ArithmeticTexture {
+    fn combine(&self, input: &dyn Fn(&Arc<dyn Texture>) -> Vec3) -> Vec3 {
+        let (a, b) = (input(&self.a), input(&self.b));
+        match self.op {
+            Arithmetic::Add => a + b,
+            Arithmetic::Subtract => a - b,
+            Arithmetic::Multiply => Vec3::elemul(a, b),
+        }
+    }
+}
+
+// One minus the input.
+pub struct InvertTexture {
+    pub texture: Arc<dyn Texture>,
+}
+impl TextureNode for InvertTexture {
+    fn combine(&self, input: &dyn Fn(&Arc<dyn Texture>) -> Vec3) -> Vec3 {
+        Vec3::ones() - input(&self.texture)
+    }
+}
+
+pub struct ClampTexture {
+    pub texture: Arc<dyn Texture>,
+    pub min: f64,
+    pub max: f64,
+}
+impl TextureNode for ClampTexture {
+    fn combine(&self, input: &dyn Fn(&Arc<dyn Texture>) -> Vec3) -> Vec3 {
+        input(&self.texture)
+            .max(Vec3::ones() * self.min)
+            .min(Vec3::ones() * self.max)
+    }
+}
+
+pub fn rgb_to_hsv(c: &Vec3) -> Vec3 {
+    let max = c.x.max(c.y).max(c.z);
+    let min = c.x.min(c.y).min(c.z);
+    let delta = max - min;
+    let hue = if delta <= 0.0 {
+        0.0
+    } else if max == c.x {
+        ((c.y - c.z) / delta).rem_euclid(6.0) / 6.0
+    } else if max == c.y {
+        ((c.z - c.x) / delta + 2.0) / 6.0
+    } else {
+        ((c.x - c.y) / delta + 4.0) / 6.0
+    };
+    let saturation = if max > 0.0 { delta / max } else { 0.0 };
+    Vec3::new(hue, saturation, max)
+}
+
+pub fn hsv_to_rgb(hsv: &Vec3) -> Vec3 {
+    let sector = hsv.x.rem_euclid(1.0) * 6.0;
+    let chroma = hsv.z * hsv.y;
+    let second = chroma * (1.0 - (sector.rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match sector as i32 {
+        0 => (chroma, second, 0.0),
+        1 => (second, chroma, 0.0),
+        2 => (0.0, chroma, second),
+        3 => (0.0, second, chroma),
+        4 => (second, 0.0, chroma),
+        _ => (chroma, 0.0, second),
+    };
+    Vec3::ones() * (hsv.z - chroma) + Vec3::new(r, g, b)
+}
+
+// Shifts the hue by `hue` turns and scales saturation and value.
+pub struct HsvTexture {
+    pub texture: Arc<dyn Texture>,
+    pub hue: f64,
+    pub saturation: f64,
+    pub value: f64,
+}
+impl TextureNode for HsvTexture {
+    fn combine(&self, input: &dyn Fn(&Arc<dyn Texture>) -> Vec3) -> Vec3 {
+        let hsv = rgb_to_hsv(&input(&self.texture));
+        hsv_to_rgb(&Vec3::new(
+            hsv.x + self.hue,
+            (hsv.y * self.saturation).max(0.0).min(1.0),
+            (hsv.z * self.value).max(0.0),
+        ))
+    }
+}
+
+// Colors the first channel of `input` through `ramp`.
+pub struct RampTexture {
+    pub input: Arc<dyn Texture>,
+    pub ramp: ColorRamp,
+}
+impl TextureNode for RampTexture {
+    fn combine(&self, input: &dyn Fn(&Arc<dyn Texture>) -> Vec3) -> Vec3 {
+        self.ramp.value(input(&self.input).x)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Channel {
+    Red,
+    Green,
+    Blue,
+    Luminance,
+}
+
+// One channel of the input, as gray.
+pub struct ChannelTexture {
+    pub texture: Arc<dyn Texture>,
+    pub channel: Channel,
+}
+impl TextureNode for ChannelTexture {
+    fn combine(&self, input: &dyn Fn(&Arc<dyn Texture>) -> Vec3) -> Vec3 {
+        let c = input(&self.texture);
+        Vec3::ones()
+            * match self.channel {
+                Channel::Red => c.x,
+                Channel::Green => c.y,
+                Channel::Blue => c.z,
+                Channel::Luminance => 0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z,
+            }
+    }
+}
+
+// Scalar ramps from 0 to 1 to feed `RampTexture` or `MixTexture`.
+pub enum Gradient {
+    U,
+    V,
+    // Along the segment from `start` to `end`, clamped beyond it.
+    Linear { start: Vec3, end: Vec3 },
+    // Distance from `center` in units of `radius`, clamped at 1.
+    Spherical { center: Vec3, radius: f64 },
+}
+
+pub struct GradientTexture {
+    pub gradient: Gradient,
+}
+impl Texture for GradientTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        let t = match &self.gradient {
+            Gradient::U => u,
+            Gradient::V => v,
+            Gradient::Linear { start, end } => {
+                let axis = end.clone() - start.clone();
+                (p - start.clone()) * axis.clone() / axis.squared_length()
+            }
+            Gradient::Spherical { center, radius } => (p - center.clone()).length() / radius,
+        };
+        Vec3::ones() * t.max(0.0).min(1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hsv_round_trip() {
+        for c in &[
+            Vec3::new(0.8, 0.2, 0.1),
+            Vec3::new(0.1, 0.7, 0.3),
+            Vec3::new(0.2, 0.3, 0.9),
+            Vec3::new(0.5, 0.5, 0.5),
+        ] {
+            let back = hsv_to_rgb(&rgb_to_hsv(c));
+            assert!((back - c.clone()).length() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,446 @@
+use crate::perlin::Perlin;
+use crate::texture::*;
+use std::sync::Arc;
+
+// Scalar noise field. Gradient noises (`Perlin`, `Simplex`) return values
+// roughly in [-1, 1]; `Worley` returns a non-negative distance.
+pub trait Noise {
+    fn noise(&self, p: Vec3) -> f64;
+}
+
+impl Noise for Perlin {
+    fn noise(&self, p: Vec3) -> f64 {
+        Perlin::noise(self, p)
+    }
+}
+
+const GRADIENTS: [[f64; 3]; 12] = [
+    [1.0, 1.0, 0.0],
+    [-1.0, 1.0, 0.0],
+    [1.0, -1.0, 0.0],
+    [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0],
+    [-1.0, 0.0, 1.0],
+    [1.0, 0.0, -1.0],
+    [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0],
+    [0.0, -1.0, 1.0],
+    [0.0, 1.0, -1.0],
+    [0.0, -1.0, -1.0],
+];
+
+// Gustavson's 3D simplex noise over a seeded permutation table.
+pub struct Simplex {
+    pub perm: Vec<usize>,
+}
+
+impl Simplex {
+    pub fn new() -> Self {
+        Self::with_seed(random_seed())
+    }
+    pub fn with_seed(seed: u64) -> Self {
+        let mut rng = SeededRng::new(seed);
+        let mut perm: Vec<usize> = (0..256).collect();
+        Perlin::permute(&mut perm, &mut rng);
+        let doubled = perm.iter().chain(perm.iter()).cloned().collect();
+        Self { perm: doubled }
+    }
+    #[allow(clippy::many_single_char_names)]
+    fn corner(&self, i: usize, j: usize, k: usize, x: f64, y: f64, z: f64) -> f64 {
+        let t = 0.6 - x * x - y * y - z * z;
+        if t < 0.0 {
+            return 0.0;
+        }
+        let g = GRADIENTS[self.perm[i + self.perm[j + self.perm[k]]] % 12];
+        t * t * t * t * (g[0] * x + g[1] * y + g[2] * z)
+    }
+}
+impl Default for Simplex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Noise for Simplex {
+    #[allow(clippy::many_single_char_names)]
+    fn noise(&self, p: Vec3) -> f64 {
+        let f3 = 1.0 / 3.0;
+        let g3 = 1.0 / 6.0;
+        let s = (p.x + p.y + p.z) * f3;
+        let (i, j, k) = ((p.x + s).floor(), (p.y + s).floor(), (p.z + s).floor());
+        let t = (i + j + k) * g3;
+        let (x0, y0, z0) = (p.x - (i - t), p.y - (j - t), p.z - (k - t));
+        // Which of the six tetrahedra of the skewed cube holds the point.
+        let (o1, o2) = if x0 >= y0 {
+            if y0 >= z0 {
+                ((1, 0, 0), (1, 1, 0))
+            } else if x0 >= z0 {
+                ((1, 0, 0), (1, 0, 1))
+            } else {
+                ((0, 0, 1), (1, 0, 1))
+            }
+        } else if y0 < z0 {
+            ((0, 0, 1), (0, 1, 1))
+        } else if x0 < z0 {
+            ((0, 1, 0), (0, 1, 1))
+        } else {
+            ((0, 1, 0), (1, 1, 0))
+        };
+        let (ii, jj, kk) = (
+            (i as i64 & 255) as usize,
+            (j as i64 & 255) as usize,
+            (k as i64 & 255) as usize,
+        );
+        let n0 = self.corner(ii, jj, kk, x0, y0, z0);
+        let n1 = self.corner(
+            ii + o1.0,
+            jj + o1.1,
+            kk + o1.2,
+            x0 - o1.0 as f64 + g3,
+            y0 - o1.1 as f64 + g3,
+            z0 - o1.2 as f64 + g3,
+        );
+        let n2 = self.corner(
+            ii + o2.0,
+            jj + o2.1,
+            kk + o2.2,
+            x0 - o2.0 as f64 + 2.0 * g3,
+            y0 - o2.1 as f64 + 2.0 * g3,
+            z0 - o2.2 as f64 + 2.0 * g3,
+        );
+        let n3 = self.corner(
+            ii + 1,
+            jj + 1,
+            kk + 1,
+            x0 - 1.0 + 3.0 * g3,
+            y0 - 1.0 + 3.0 * g3,
+            z0 - 1.0 + 3.0 * g3,
+        );
+        32.0 * (n0 + n1 + n2 + n3)
+    }
+}
+
+// Cellular noise with one jittered feature point per unit cell.
+pub struct Worley {
+    pub seed: u64,
+}
+
+impl Worley {
+    pub fn new() -> Self {
+        Self::with_seed(random_seed())
+    }
+    pub fn with_seed(seed: u64) -> Self {
+        Self { seed }
+    }
+    fn feature_point(&self, i: i64, j: i64, k: i64) -> Vec3 {
+        let hash = self.seed
+            ^ (i as u64).wrapping_mul(0x8da6_b343)
+            ^ (j as u64).wrapping_mul(0xd816_3841)
+            ^ (k as u64).wrapping_mul(0xcb1a_b31f);
+        let mut rng = SeededRng::new(hash);
+        Vec3::new(
+            i as f64 + rng.next_double(),
+            j as f64 + rng.next_double(),
+            k as f64 + rng.next_double(),
+        )
+    }
+    // Distances from `p` to the nearest and second nearest feature points.
+    pub fn distances(&self, p: Vec3) -> (f64, f64) {
+        let cell = (p.x.floor() as i64, p.y.floor() as i64, p.z.floor() as i64);
+        let (mut f1, mut f2) = (f64::INFINITY, f64::INFINITY);
+        for di in -1..=1 {
+            for dj in -1..=1 {
+                for dk in -1..=1 {
+                    let feature = self.feature_point(cell.0 + di, cell.1 + dj, cell.2 + dk);
+                    let distance = (feature - p.clone()).length();
+                    if distance < f1 {
+                        f2 = f1;
+                        f1 = distance;
+                    } else if distance < f2 {
+                        f2 = distance;
+                    }
+                }
+            }
+        }
+        (f1, f2)
+    }
+}
+impl Default for Worley {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Noise for Worley {
+    fn noise(&self, p: Vec3) -> f64 {
+        self.distances(p).0
+    }
+}
+
+// Fractal sum of `octaves` copies of a noise, each `lacunarity` times finer
+// and `gain` times weaker than the last.
+#[derive(Clone)]
+pub struct Fbm {
+    pub octaves: u32,
+    pub lacunarity: f64,
+    pub gain: f64,
+}
+
+impl Default for Fbm {
+    fn default() -> Self {
+        Self {
+            octaves: 7,
+            lacunarity: 2.0,
+            gain: 0.5,
+        }
+    }
+}
+
+impl Fbm {
+    fn sum(&self, p: Vec3, mut octave: impl FnMut(Vec3) -> f64) -> f64 {
+        let mut accum = 0.0;
+        let mut weight = 1.0;
+        let mut temp_p = p;
+        for _ in 0..self.octaves {
+            accum += weight * octave(temp_p.clone());
+            weight *= self.gain;
+            temp_p *= self.lacunarity;
+        }
+        accum
+    }
+    pub fn fbm(&self, noise: &dyn Noise, p: Vec3) -> f64 {
+        self.sum(p, |q| noise.noise(q))
+    }
+    pub fn turbulence(&self, noise: &dyn Noise, p: Vec3) -> f64 {
+        self.sum(p, |q| noise.noise(q).abs())
+    }
+    // Musgrave's ridged multifractal: sharp creases where the noise crosses
+    // zero, each octave weighted by the ridges of the one before. Roughly in
+    // [0, 1].
+    pub fn ridged(&self, noise: &dyn Noise, p: Vec3) -> f64 {
+        let mut previous = 1.0;
+        let mut total = 0.0;
+        let mut norm = 0.0;
+        let mut amplitude = 1.0;
+        let mut temp_p = p;
+        for _ in 0..self.octaves {
+            let ridge = 1.0 - noise.noise(temp_p.clone()).abs();
+            let signal = ridge * ridge * previous;
+            total += amplitude * signal;
+            norm += amplitude;
+            previous = signal.max(0.0).min(1.0);
+            amplitude *= self.gain;
+            temp_p *= self.lacunarity;
+        }
+        total / norm
+    }
+    // fBm looked up at `p` displaced by `strength` times a vector of fBm
+    // values (Quilez's domain warping).
+    pub fn warped(&self, noise: &dyn Noise, p: Vec3, strength: f64) -> f64 {
+        let q = Vec3::new(
+            self.fbm(noise, p.clone()),
+            self.fbm(noise, p.clone() + Vec3::new(5.2, 1.3, 2.8)),
+            self.fbm(noise, p.clone() + Vec3::new(1.7, 9.2, 4.1)),
+        );
+        self.fbm(noise, p + q * strength)
+    }
+}
+
+// How `FractalTexture` combines the octaves of its noise.
+#[derive(Clone, Copy, PartialEq)]
+pub enum FractalMode {
+    Fbm,
+    Turbulence,
+    Ridged,
+    // fBm with its domain warped by `strength`.
+    Warped { strength: f64 },
+}
+
+// Any noise summed over octaves in one of the `FractalMode`s at `scale`
+// times the hit point, and colored by `ramp`. Signed modes are shifted
+// from [-1, 1] to [0, 1] first, so a ramp over [0, 1] suits every mode.
+pub struct FractalTexture {
+    pub noise: Arc<dyn Noise>,
+    pub fbm: Fbm,
+    pub mode: FractalMode,
+    pub scale: f64,
+    pub ramp: ColorRamp,
+}
+impl FractalTexture {
+    // Grayscale, at unit scale and with the default octaves.
+    pub fn new(noise: Arc<dyn Noise>, mode: FractalMode) -> Self {
+        Self {
+            noise,
+            fbm: Fbm::default(),
+            mode,
+            scale: 1.0,
+            ramp: ColorRamp::new(vec![(0.0, Vec3::zero()), (1.0, Vec3::ones())]),
+        }
+    }
+}
+impl Texture for FractalTexture {
+    fn value(&self, _u: f64, _v: f64, p: Vec3) -> Vec3 {
+        let (noise, q) = (&*self.noise, p * self.scale);
+        let t = match self.mode {
+            FractalMode::Fbm => 0.5 * (1.0 + self.fbm.fbm(noise, q)),
+            FractalMode::Turbulence => self.fbm.turbulence(noise, q),
+            FractalMode::Ridged => self.fbm.ridged(noise, q),
+            FractalMode::Warped { strength } => 0.5 * (1.0 + self.fbm.warped(noise, q, strength)),
+        };
+        self.ramp.value(t)
+    }
+}
+
+// Veined marble: bands along z, distorted by turbulence.
+pub struct MarbleTexture {
+    pub noise: Arc<dyn Noise>,
+    pub fbm: Fbm,
+    pub scale: f64,
+    pub distortion: f64,
+    pub ramp: ColorRamp,
+}
+impl MarbleTexture {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            noise: Arc::new(Perlin::with_seed(seed)),
+            fbm: Fbm::default(),
+            scale: 4.0,
+            distortion: 10.0,
+            ramp: ColorRamp::new(vec![
+                (0.0, Vec3::new(0.2, 0.2, 0.22)),
+                (0.3, Vec3::new(0.75, 0.75, 0.73)),
+                (1.0, Vec3::new(0.95, 0.95, 0.93)),
+            ]),
+        }
+    }
+}
+impl Texture for MarbleTexture {
+    fn value(&self, _u: f64, _v: f64, p: Vec3) -> Vec3 {
+        let phase = self.scale * p.z + self.distortion * self.fbm.turbulence(&*self.noise, p);
+        self.ramp.value(0.5 * (1.0 + phase.sin()))
+    }
+}
+
+// Wood: rings around the y axis, `rings` per unit radius, wobbled by fBm.
+pub struct WoodTexture {
+    pub noise: Arc<dyn Noise>,
+    pub fbm: Fbm,
+    pub rings: f64,
+    pub distortion: f64,
+    pub ramp: ColorRamp,
+}
+impl WoodTexture {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            noise: Arc::new(Perlin::with_seed(seed)),
+            fbm: Fbm {
+                octaves: 4,
+                ..Fbm::default()
+            },
+            rings: 8.0,
+            distortion: 0.8,
+            ramp: ColorRamp::new(vec![
+                (0.0, Vec3::new(0.55, 0.35, 0.17)),
+                (0.7, Vec3::new(0.42, 0.25, 0.11)),
+                (1.0, Vec3::new(0.3, 0.17, 0.07)),
+            ]),
+        }
+    }
+}
+impl Texture for WoodTexture {
+    fn value(&self, _u: f64, _v: f64, p: Vec3) -> Vec3 {
+        let radius = (p.x * p.x + p.z * p.z).sqrt();
+        let rings = radius * self.rings + self.distortion * self.fbm.fbm(&*self.noise, p);
+        self.ramp.value(rings - rings.floor())
+    }
+}
+
+// Granite: Worley cells with dark grain between them and fine speckle.
+pub struct GraniteTexture {
+    pub cells: Worley,
+    pub noise: Arc<dyn Noise>,
+    pub fbm: Fbm,
+    pub scale: f64,
+    pub ramp: ColorRamp,
+}
+impl GraniteTexture {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            cells: Worley::with_seed(seed),
+            noise: Arc::new(Simplex::with_seed(seed)),
+            fbm: Fbm {
+                octaves: 4,
+                ..Fbm::default()
+            },
+            scale: 8.0,
+            ramp: ColorRamp::new(vec![
+                (0.0, Vec3::new(0.08, 0.08, 0.08)),
+                (0.4, Vec3::new(0.45, 0.42, 0.4)),
+                (0.8, Vec3::new(0.7, 0.62, 0.58)),
+                (1.0, Vec3::new(0.85, 0.82, 0.8)),
+            ]),
+        }
+    }
+}
+impl Texture for GraniteTexture {
+    fn value(&self, _u: f64, _v: f64, p: Vec3) -> Vec3 {
+        let q = p * self.scale;
+        let (f1, f2) = self.cells.distances(q.clone());
+        let grain = (f2 - f1).min(1.0);
+        let speckle = 0.5 * (1.0 + self.fbm.fbm(&*self.noise, q * 4.0));
+        self.ramp.value(0.5 * grain + 0.5 * speckle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeded_noise() {
+        let p = Vec3::new(0.3, 1.7, -2.2);
+        assert_eq!(
+            Perlin::with_seed(7).noise(p.clone()),
+            Perlin::with_seed(7).noise(p.clone())
+        );
+        assert_eq!(
+            Simplex::with_seed(7).noise(p.clone()),
+            Simplex::with_seed(7).noise(p.clone())
+        );
+        let (f1, f2) = Worley::with_seed(7).distances(p);
+        assert!(f1 >= 0.0 && f1 <= f2);
+        for i in 0..100 {
+            let q = Vec3::new(i as f64 * 0.37, i as f64 * 0.11, i as f64 * -0.23);
+            assert!(Simplex::with_seed(3).noise(q).abs() <= 1.0);
+        }
+    }
+
+    #[test]
+    fn test_fractal_modes() {
+        struct Flat;
+        impl Noise for Flat {
+            fn noise(&self, _p: Vec3) -> f64 {
+                0.0
+            }
+        }
+        let value = |mode: FractalMode| {
+            FractalTexture::new(Arc::new(Flat), mode)
+                .value(0.0, 0.0, Vec3::new(0.1, 0.2, 0.3))
+                .x
+        };
+        let gray = FractalTexture::new(Arc::new(Flat), FractalMode::Fbm).ramp;
+        assert!((gray.value(0.25) - Vec3::ones() * 0.25).length() < 1e-12);
+        // Zero noise lies midway for the signed modes, and on a ridge.
+        assert!((value(FractalMode::Fbm) - 0.5).abs() < 1e-12);
+        assert!(value(FractalMode::Turbulence).abs() < 1e-12);
+        assert!((value(FractalMode::Ridged) - 1.0).abs() < 1e-12);
+        assert!((value(FractalMode::Warped { strength: 2.0 }) - 0.5).abs() < 1e-12);
+
+        let mut texture = FractalTexture::new(Arc::new(Simplex::with_seed(5)), FractalMode::Ridged);
+        texture.fbm.octaves = 3;
+        texture.scale = 3.0;
+        for i in 0..50 {
+            let p = Vec3::new(i as f64 * 0.13, i as f64 * -0.07, i as f64 * 0.21);
+            let color = texture.value(0.0, 0.0, p);
+            assert!(color.x >= 0.0 && color.x <= 1.0);
+        }
+    }
+}
@@ -14,6 +14,13 @@ impl ONB {
     pub fn localvec(&self, p: Vec3) -> Vec3 {
         self.u.clone() * p[0] + self.v.clone() * p[1] + self.w.clone() * p[2]
     }
+    pub fn to_local(&self, p: Vec3) -> Vec3 {
+        Vec3::new(
+            p.clone() * self.u.clone(),
+            p.clone() * self.v.clone(),
+            p * self.w.clone(),
+        )
+    }
     pub fn build_from_w(normal: Vec3) -> Self {
         let w = normal.unit();
         let temp = if w.x.abs() > 0.9 {
@@ -25,4 +32,15 @@ impl ONB {
         let u = Vec3::cross(w.clone(), v.clone());
         Self { u, v, w }
     }
+    // Frame around `normal` with `u` along `tangent`, if the tangent is usable.
+    pub fn build_from_wu(normal: Vec3, tangent: Vec3) -> Self {
+        let w = normal.unit();
+        let u = tangent.clone() - w.clone() * (tangent * w.clone());
+        if u.squared_length() < 1e-12 {
+            return Self::build_from_w(w);
+        }
+        let u = u.unit();
+        let v = Vec3::cross(w.clone(), u.clone());
+        Self { u, v, w }
+    }
 }
@@ -1,5 +1,6 @@
 #![allow(clippy::needless_range_loop)]
 
+use crate::noise::Fbm;
 pub use crate::random::*;
 pub use crate::vec3::Vec3;
 
@@ -22,10 +23,10 @@ fn trilinear_interp(c: [[[Vec3; 2]; 2]; 2], fu: f64, fv: f64, fw: f64) -> f64 {
 }
 
 pub struct Perlin {
-    pub ranvec: [Vec3; POINTCOUNT],
-    pub perm_x: [usize; POINTCOUNT],
-    pub perm_y: [usize; POINTCOUNT],
-    pub perm_z: [usize; POINTCOUNT],
+    pub ranvec: Vec<Vec3>,
+    pub perm_x: Vec<usize>,
+    pub perm_y: Vec<usize>,
+    pub perm_z: Vec<usize>,
 }
 
 impl Default for Perlin {
@@ -35,285 +36,35 @@ impl Default for Perlin {
 }
 
 impl Perlin {
-    pub fn permute(p: &mut [usize; POINTCOUNT]) {
-        for i in 0..POINTCOUNT {
-            let j = random_int_range(i as i32, POINTCOUNT as i32) as usize;
+    pub fn permute(p: &mut [usize], rng: &mut SeededRng) {
+        for i in 0..p.len() {
+            let j = rng.next_range(i, p.len());
             p.swap(i, j);
         }
     }
-    pub fn perlin_generate_perm() -> [usize; POINTCOUNT] {
-        let mut p: [usize; POINTCOUNT] = [0; POINTCOUNT];
-        for i in 0..POINTCOUNT {
-            p[i] = i;
-        }
-        Self::permute(&mut p);
+    pub fn perlin_generate_perm(rng: &mut SeededRng) -> Vec<usize> {
+        let mut p: Vec<usize> = (0..POINTCOUNT).collect();
+        Self::permute(&mut p, rng);
         p
     }
     pub fn new() -> Self {
-        let mut ranvec = [
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-            Vec3::default(),
-        ];
-        for i in 0..POINTCOUNT {
-            ranvec[i] = Vec3::random_range(-1.0, 1.0);
-        }
-        let perm_x = Self::perlin_generate_perm();
-        let perm_y = Self::perlin_generate_perm();
-        let perm_z = Self::perlin_generate_perm();
+        Self::with_seed(random_seed())
+    }
+    // The same seed always gives the same noise.
+    pub fn with_seed(seed: u64) -> Self {
+        let mut rng = SeededRng::new(seed);
+        let ranvec = (0..POINTCOUNT)
+            .map(|_| {
+                Vec3::new(
+                    rng.next_double() * 2.0 - 1.0,
+                    rng.next_double() * 2.0 - 1.0,
+                    rng.next_double() * 2.0 - 1.0,
+                )
+            })
+            .collect();
+        let perm_x = Self::perlin_generate_perm(&mut rng);
+        let perm_y = Self::perlin_generate_perm(&mut rng);
+        let perm_z = Self::perlin_generate_perm(&mut rng);
         Self {
             ranvec,
             perm_x,
@@ -359,15 +110,8 @@ impl Perlin {
         }
         trilinear_interp(cc, fu, fv, fw)
     }
+    // Magnitude of seven octaves of fBm, as `NoiseTexture` has always used.
     pub fn turb(&self, p: Vec3) -> f64 {
-        let mut accum = 0.0;
-        let mut weight = 1.0;
-        let mut temp_p = p;
-        for _i in 0..7 {
-            accum += weight * self.noise(temp_p.clone());
-            weight *= 0.5;
-            temp_p *= 2.0;
-        }
-        accum.abs()
+        Fbm::default().fbm(self, p).abs()
     }
 }
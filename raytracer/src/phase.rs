@@ -0,0 +1,129 @@
+use crate::hit::HitResult;
+use crate::material::*;
+use crate::onb::ONB;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// Angular distribution of light scattered in a medium. `wo` and `wi` both
+// point away from the scattering point, so forward scattering has
+// wi = -wo. Phase functions are normalized over the sphere and sampled
+// exactly, so `p` is also the density of `sample_p`.
+pub trait PhaseFunction {
+    fn p(&self, wo: &Vec3, wi: &Vec3) -> f64;
+    fn sample_p(&self, wo: &Vec3, u: (f64, f64)) -> Vec3;
+}
+
+pub struct IsotropicPhase;
+impl PhaseFunction for IsotropicPhase {
+    fn p(&self, _wo: &Vec3, _wi: &Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+    fn sample_p(&self, _wo: &Vec3, u: (f64, f64)) -> Vec3 {
+        Vec3::sphere_direction(u.0, u.1)
+    }
+}
+
+// Henyey-Greenstein: `g` in (-1, 1) is the mean cosine of the scattering
+// angle, positive for forward scattering.
+pub struct HenyeyGreenstein {
+    pub g: f64,
+}
+impl HenyeyGreenstein {
+    pub fn density(g: f64, cos_theta: f64) -> f64 {
+        let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * PI * denom * denom.sqrt())
+    }
+    pub fn sample(g: f64, wo: &Vec3, u: (f64, f64)) -> Vec3 {
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u.0
+        } else {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u.0);
+            ((1.0 + g * g - s * s) / (2.0 * g)).max(-1.0).min(1.0)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u.1;
+        ONB::build_from_w(-wo.clone()).local(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        )
+    }
+}
+impl PhaseFunction for HenyeyGreenstein {
+    fn p(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        Self::density(self.g, -(wo.clone() * wi.clone()))
+    }
+    fn sample_p(&self, wo: &Vec3, u: (f64, f64)) -> Vec3 {
+        Self::sample(self.g, wo, u)
+    }
+}
+
+// Blend of a forward and a backward Henyey-Greenstein lobe, `weight` of the
+// first, as used for clouds and smoke.
+pub struct DoubleHenyeyGreenstein {
+    pub g1: f64,
+    pub g2: f64,
+    pub weight: f64,
+}
+impl PhaseFunction for DoubleHenyeyGreenstein {
+    fn p(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        let cos_theta = -(wo.clone() * wi.clone());
+        self.weight * HenyeyGreenstein::density(self.g1, cos_theta)
+            + (1.0 - self.weight) * HenyeyGreenstein::density(self.g2, cos_theta)
+    }
+    fn sample_p(&self, wo: &Vec3, u: (f64, f64)) -> Vec3 {
+        let g = if random_double() < self.weight {
+            self.g1
+        } else {
+            self.g2
+        };
+        HenyeyGreenstein::sample(g, wo, u)
+    }
+}
+
+// Scattering inside a medium with a phase function and an albedo, usable
+// wherever media take a material. Exposes its density for light sampling.
+pub struct PhaseMaterial {
+    pub albedo: Arc<dyn Texture>,
+    pub phase: Arc<dyn PhaseFunction>,
+}
+impl Material for PhaseMaterial {
+    fn eval(&self, hit_record: &HitResult, wo: &Vec3, wi: &Vec3) -> Vec3 {
+        hit_record.texture_value(&self.albedo) * self.phase.p(wo, wi)
+    }
+    fn sample(&self, hit_record: &HitResult, wo: &Vec3, u: (f64, f64)) -> Option<ScatterRecord> {
+        let wi = self.phase.sample_p(wo, u);
+        let pdf = self.phase.p(wo, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(ScatterRecord {
+            f: hit_record.texture_value(&self.albedo) * pdf,
+            wi,
+            pdf,
+            flags: Lobe::DIFFUSE,
+        })
+    }
+    fn pdf(&self, _hit_record: &HitResult, wo: &Vec3, wi: &Vec3) -> f64 {
+        self.phase.p(wo, wi)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_henyey_greenstein_mean_cosine() {
+        let wo = Vec3::new(0.0, 0.0, 1.0);
+        for &g in &[-0.5, 0.0, 0.3, 0.8] {
+            let samples = 20000;
+            let mut mean = 0.0;
+            for _ in 0..samples {
+                let wi = HenyeyGreenstein::sample(g, &wo, (random_double(), random_double()));
+                mean += -(wo.clone() * wi) / samples as f64;
+            }
+            assert!((mean - g).abs() < 0.02);
+        }
+    }
+}
@@ -0,0 +1,327 @@
+use crate::mesh::{invalid, TriangleMesh};
+use crate::subdivision::PolygonMesh;
+use crate::vec3::Vec3;
+use std::fs;
+use std::io::Result;
+use std::path::Path;
+use std::str::SplitAsciiWhitespace;
+
+// List counts and indices are read as numbers of any type; they must be
+// whole and non-negative.
+fn whole(value: f64) -> Result<usize> {
+    if value >= 0.0 && value.fract() == 0.0 && value < u32::MAX as f64 {
+        Ok(value as usize)
+    } else {
+        Err(invalid("ply", "list count or index is not a whole number"))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Result<Self> {
+        Ok(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return Err(invalid("ply", "unknown property type")),
+        })
+    }
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+    // Scale that maps integer colors to [0, 1].
+    fn color_scale(self) -> f64 {
+        match self {
+            Scalar::U8 => 1.0 / 255.0,
+            Scalar::U16 => 1.0 / 65535.0,
+            _ => 1.0,
+        }
+    }
+}
+
+enum Property {
+    Scalar(String, Scalar),
+    List(String, Scalar, Scalar),
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+// Reads the values of the body one at a time, whatever the format.
+struct Body<'a> {
+    format: Format,
+    data: &'a [u8],
+    tokens: Option<SplitAsciiWhitespace<'a>>,
+}
+
+impl<'a> Body<'a> {
+    fn read(&mut self, scalar: Scalar) -> Result<f64> {
+        if let Some(tokens) = &mut self.tokens {
+            return tokens
+                .next()
+                .and_then(|token| token.parse().ok())
+                .ok_or_else(|| invalid("ply", "bad or missing value"));
+        }
+        let size = scalar.size();
+        if self.data.len() < size {
+            return Err(invalid("ply", "unexpected end of file"));
+        }
+        let mut bytes = [0u8; 8];
+        bytes[..size].copy_from_slice(&self.data[..size]);
+        self.data = &self.data[size..];
+        if self.format == Format::BigEndian {
+            bytes[..size].reverse();
+        }
+        let b = bytes;
+        Ok(match scalar {
+            Scalar::I8 => b[0] as i8 as f64,
+            Scalar::U8 => b[0] as f64,
+            Scalar::I16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+            Scalar::U16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+            Scalar::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::F64 => f64::from_le_bytes(b),
+        })
+    }
+}
+
+impl TriangleMesh {
+    // Loads ASCII or binary PLY. Vertices may carry normals (nx, ny, nz),
+    // colors (red, green, blue) and texture coordinates (u, v or s, t);
+    // faces with more than three vertices are triangulated as fans. Other
+    // elements are skipped.
+    pub fn from_ply<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::parse_ply(&fs::read(path)?)
+    }
+    fn parse_ply(bytes: &[u8]) -> Result<Self> {
+        let (mut mesh, faces) = Self::parse_ply_polygons(bytes)?;
+        for face in faces {
+            for k in 1..face.len().saturating_sub(1) {
+                mesh.indices.push([face[0], face[k], face[k + 1]]);
+            }
+        }
+        Ok(mesh)
+    }
+    // The vertices, without triangles, and the faces as listed.
+    fn parse_ply_polygons(bytes: &[u8]) -> Result<(Self, Vec<Vec<usize>>)> {
+        const END: &[u8] = b"end_header";
+        let end = bytes
+            .windows(END.len())
+            .position(|window| window == END)
+            .ok_or_else(|| invalid("ply", "missing end_header"))?;
+        let body_start = bytes[end..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(bytes.len(), |newline| end + newline + 1);
+        let header =
+            std::str::from_utf8(&bytes[..end]).map_err(|_| invalid("ply", "header is not text"))?;
+
+        let mut format = None;
+        let mut elements: Vec<Element> = Vec::new();
+        for (number, line) in header.lines().enumerate() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["ply"] if number == 0 => {}
+                _ if number == 0 => return Err(invalid("ply", "not a ply file")),
+                ["format", kind, _] => {
+                    format = Some(match *kind {
+                        "ascii" => Format::Ascii,
+                        "binary_little_endian" => Format::LittleEndian,
+                        "binary_big_endian" => Format::BigEndian,
+                        _ => return Err(invalid("ply", "unknown format")),
+                    })
+                }
+                ["element", name, count] => elements.push(Element {
+                    name: name.to_string(),
+                    count: count
+                        .parse()
+                        .map_err(|_| invalid("ply", "bad element count"))?,
+                    properties: Vec::new(),
+                }),
+                ["property", "list", count, item, name] => elements
+                    .last_mut()
+                    .ok_or_else(|| invalid("ply", "property outside an element"))?
+                    .properties
+                    .push(Property::List(
+                        name.to_string(),
+                        Scalar::parse(count)?,
+                        Scalar::parse(item)?,
+                    )),
+                ["property", kind, name] => elements
+                    .last_mut()
+                    .ok_or_else(|| invalid("ply", "property outside an element"))?
+                    .properties
+                    .push(Property::Scalar(name.to_string(), Scalar::parse(kind)?)),
+                _ => {}
+            }
+        }
+        let format = format.ok_or_else(|| invalid("ply", "missing format"))?;
+        let data = &bytes[body_start..];
+        let tokens = if format == Format::Ascii {
+            Some(
+                std::str::from_utf8(data)
+                    .map_err(|_| invalid("ply", "body is not text"))?
+                    .split_ascii_whitespace(),
+            )
+        } else {
+            None
+        };
+        let mut body = Body {
+            format,
+            data,
+            tokens,
+        };
+
+        let mut mesh = TriangleMesh::default();
+        let mut faces = Vec::new();
+        for element in &elements {
+            let has = |names: &[&str]| {
+                element.properties.iter().any(|property| match property {
+                    Property::Scalar(name, _) => names.contains(&name.as_str()),
+                    _ => false,
+                })
+            };
+            let has_normals = has(&["nx"]);
+            let has_colors = has(&["red"]);
+            let has_uvs = has(&["u", "s", "texture_u", "texture_s"]);
+            for _ in 0..element.count {
+                let (mut position, mut normal, mut color) =
+                    (Vec3::zero(), Vec3::zero(), Vec3::zero());
+                let mut uv = (0.0, 0.0);
+                for property in &element.properties {
+                    match property {
+                        Property::Scalar(name, scalar) => {
+                            let value = body.read(*scalar)?;
+                            match name.as_str() {
+                                "x" => position.x = value,
+                                "y" => position.y = value,
+                                "z" => position.z = value,
+                                "nx" => normal.x = value,
+                                "ny" => normal.y = value,
+                                "nz" => normal.z = value,
+                                "red" => color.x = value * scalar.color_scale(),
+                                "green" => color.y = value * scalar.color_scale(),
+                                "blue" => color.z = value * scalar.color_scale(),
+                                "u" | "s" | "texture_u" | "texture_s" => uv.0 = value,
+                                "v" | "t" | "texture_v" | "texture_t" => uv.1 = value,
+                                _ => {}
+                            }
+                        }
+                        Property::List(name, count, item) => {
+                            let count = whole(body.read(*count)?)?;
+                            let mut face = Vec::new();
+                            for _ in 0..count {
+                                face.push(whole(body.read(*item)?)?);
+                            }
+                            let is_face = element.name == "face"
+                                && (name == "vertex_indices" || name == "vertex_index");
+                            if is_face {
+                                faces.push(face);
+                            }
+                        }
+                    }
+                }
+                if element.name == "vertex" {
+                    mesh.positions.push(position);
+                    if has_normals {
+                        mesh.normals.push(normal);
+                    }
+                    if has_colors {
+                        mesh.colors.push(color);
+                    }
+                    if has_uvs {
+                        mesh.uvs.push(uv);
+                    }
+                }
+            }
+        }
+        let vertices = mesh.positions.len();
+        if faces.iter().flatten().any(|&i| i >= vertices) {
+            return Err(invalid("ply", "face index out of range"));
+        }
+        Ok((mesh, faces))
+    }
+}
+
+impl PolygonMesh {
+    // Loads PLY keeping faces with more than three vertices whole, for
+    // Catmull-Clark subdivision.
+    pub fn from_ply<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let (vertices, faces) = TriangleMesh::parse_ply_polygons(&fs::read(path)?)?;
+        Ok(Self::from_polygons(&vertices, &faces))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ply() {
+        let ascii = b"ply\nformat ascii 1.0\ncomment quad\nelement vertex 4\n\
+            property float x\nproperty float y\nproperty float z\n\
+            property uchar red\nproperty uchar green\nproperty uchar blue\n\
+            element face 1\nproperty list uchar int vertex_indices\nend_header\n\
+            0 0 0 255 0 0\n1 0 0 0 255 0\n1 1 0 0 0 255\n0 1 0 255 255 255\n4 0 1 2 3\n";
+        let mesh = TriangleMesh::parse_ply(ascii).unwrap();
+        assert_eq!(mesh.indices, vec![[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(mesh.colors[1], Vec3::new(0.0, 1.0, 0.0));
+
+        let mut binary = b"ply\nformat binary_big_endian 1.0\nelement vertex 3\n\
+            property double x\nproperty double y\nproperty double z\n\
+            element face 1\nproperty list uchar uint vertex_indices\nend_header\n"
+            .to_vec();
+        for &v in &[0.0f64, 0.0, 0.0, 2.0, 0.0, 0.0, 0.0, 3.0, 0.5] {
+            binary.extend_from_slice(&v.to_be_bytes());
+        }
+        binary.push(3);
+        for &i in &[0u32, 1, 2] {
+            binary.extend_from_slice(&i.to_be_bytes());
+        }
+        let mesh = TriangleMesh::parse_ply(&binary).unwrap();
+        assert_eq!(mesh.positions[2], Vec3::new(0.0, 3.0, 0.5));
+        assert_eq!(mesh.indices, vec![[0, 1, 2]]);
+        assert!(mesh.colors.is_empty() && mesh.normals.is_empty());
+
+        let header = "ply\nformat ascii 1.0\nelement vertex 3\n\
+            property float x\nproperty float y\nproperty float z\n\
+            element face 1\nproperty list uchar float vertex_indices\nend_header\n\
+            0 0 0\n1 0 0\n0 1 0\n";
+        for face in &["3 0 1 -1\n", "3 0 1 1.5\n", "-3 0 1 2\n"] {
+            let ply = format!("{}{}", header, face);
+            assert!(TriangleMesh::parse_ply(ply.as_bytes()).is_err());
+        }
+    }
+}
@@ -11,3 +11,49 @@ pub fn random_double_range(min: f64, max: f64) -> f64 {
 pub fn random_int_range(min: i32, max: i32) -> i32 {
     (random_double_range(min as f64, max as f64)).floor() as i32
 }
+
+// Splits one sample in [0, 1) into two by de-interleaving the bits of its
+// 32-bit fixed-point form, so that a stratified sample stays stratified in
+// both.
+pub fn demux(u: f64) -> (f64, f64) {
+    let bits = (u * 4_294_967_296.0).max(0.0).min(4_294_967_295.0) as u64;
+    let compact = |bits: u64| {
+        let mut x = bits & 0x5555_5555;
+        x = (x | (x >> 1)) & 0x3333_3333;
+        x = (x | (x >> 2)) & 0x0f0f_0f0f;
+        x = (x | (x >> 4)) & 0x00ff_00ff;
+        x = (x | (x >> 8)) & 0x0000_ffff;
+        x as f64 / 65536.0
+    };
+    (compact(bits), compact(bits >> 1))
+}
+
+// Small deterministic generator (SplitMix64) for reproducible procedural
+// content; rendering itself keeps using `random_double`.
+pub struct SeededRng {
+    pub state: u64,
+}
+
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+    pub fn next_double(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+    pub fn next_range(&mut self, min: usize, max: usize) -> usize {
+        min + (self.next_double() * (max - min) as f64) as usize
+    }
+}
+
+// A fresh seed for generators that were not given one.
+pub fn random_seed() -> u64 {
+    (random_double() * u32::MAX as f64) as u64
+}
@@ -11,3 +11,12 @@ impl Ray {
         self.origin.clone() + self.direction.clone() * t
     }
 }
+
+// A camera ray together with the rays through the neighbouring pixels in x
+// and y, used to estimate the footprint of a pixel on the surfaces it hits.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RayDifferential {
+    pub ray: Ray,
+    pub rx: Ray,
+    pub ry: Ray,
+}
@@ -0,0 +1,219 @@
+use crate::hit::*;
+use std::sync::Arc;
+
+// Signed distance to a surface, negative inside. It may underestimate the
+// distance but never overestimate it, or sphere tracing steps through the
+// surface. Closures `Fn(&Vec3) -> f64` are distance functions too.
+pub trait Sdf {
+    fn distance(&self, p: &Vec3) -> f64;
+}
+
+impl<F: Fn(&Vec3) -> f64> Sdf for F {
+    fn distance(&self, p: &Vec3) -> f64 {
+        self(p)
+    }
+}
+
+pub struct SphereSdf {
+    pub center: Vec3,
+    pub radius: f64,
+}
+impl Sdf for SphereSdf {
+    fn distance(&self, p: &Vec3) -> f64 {
+        (p.clone() - self.center.clone()).length() - self.radius
+    }
+}
+
+// Box of `half_size` around `center` with its edges rounded by `radius`,
+// which the box grows by.
+pub struct RoundedBoxSdf {
+    pub center: Vec3,
+    pub half_size: Vec3,
+    pub radius: f64,
+}
+impl Sdf for RoundedBoxSdf {
+    fn distance(&self, p: &Vec3) -> f64 {
+        let q = (p.clone() - self.center.clone()).abs() - self.half_size.clone();
+        q.max(Vec3::zero()).length() + q.x.max(q.y).max(q.z).min(0.0) - self.radius
+    }
+}
+
+// Segment from `a` to `b` thickened by `radius`.
+pub struct CapsuleSdf {
+    pub a: Vec3,
+    pub b: Vec3,
+    pub radius: f64,
+}
+impl Sdf for CapsuleSdf {
+    fn distance(&self, p: &Vec3) -> f64 {
+        let pa = p.clone() - self.a.clone();
+        let ba = self.b.clone() - self.a.clone();
+        let h = (pa.clone() * ba.clone() / ba.squared_length())
+            .max(0.0)
+            .min(1.0);
+        (pa - ba * h).length() - self.radius
+    }
+}
+
+// Torus around the vertical axis through `center`.
+pub struct TorusSdf {
+    pub center: Vec3,
+    pub major_radius: f64,
+    pub minor_radius: f64,
+}
+impl Sdf for TorusSdf {
+    fn distance(&self, p: &Vec3) -> f64 {
+        let d = p.clone() - self.center.clone();
+        let ring = (d.x * d.x + d.z * d.z).sqrt() - self.major_radius;
+        (ring * ring + d.y * d.y).sqrt() - self.minor_radius
+    }
+}
+
+// Union of `a` and `b` blended over a distance of about `k`.
+pub struct SmoothUnion {
+    pub a: Arc<dyn Sdf>,
+    pub b: Arc<dyn Sdf>,
+    pub k: f64,
+}
+impl Sdf for SmoothUnion {
+    fn distance(&self, p: &Vec3) -> f64 {
+        let (a, b) = (self.a.distance(p), self.b.distance(p));
+        let h = (0.5 + 0.5 * (b - a) / self.k).max(0.0).min(1.0);
+        lerp(a, b, h) - self.k * h * (1.0 - h)
+    }
+}
+
+// `a` with `b` carved out, the cut blended over a distance of about `k`.
+pub struct SmoothSubtraction {
+    pub a: Arc<dyn Sdf>,
+    pub b: Arc<dyn Sdf>,
+    pub k: f64,
+}
+impl Sdf for SmoothSubtraction {
+    fn distance(&self, p: &Vec3) -> f64 {
+        let (a, b) = (self.a.distance(p), self.b.distance(p));
+        let h = (0.5 - 0.5 * (a + b) / self.k).max(0.0).min(1.0);
+        lerp(-b, a, h) + self.k * h * (1.0 - h)
+    }
+}
+
+// Infinite copies of `sdf` every `period` along each axis, modelled around
+// the origin; a zero component leaves that axis alone. Copies should stay
+// within their own cell.
+pub struct RepeatSdf {
+    pub sdf: Arc<dyn Sdf>,
+    pub period: Vec3,
+}
+impl Sdf for RepeatSdf {
+    fn distance(&self, p: &Vec3) -> f64 {
+        let wrap = |x: f64, period: f64| {
+            if period > 0.0 {
+                x - period * (x / period).round()
+            } else {
+                x
+            }
+        };
+        let q = Vec3::new(
+            wrap(p.x, self.period.x),
+            wrap(p.y, self.period.y),
+            wrap(p.z, self.period.z),
+        );
+        self.sdf.distance(&q)
+    }
+}
+
+// Surface where `sdf` is zero, sphere traced inside `bbox` until within
+// `epsilon` of it or out of `max_steps`.
+pub struct SdfShape {
+    pub sdf: Arc<dyn Sdf>,
+    pub bbox: AABB,
+    pub epsilon: f64,
+    pub max_steps: usize,
+    pub mat_ptr: Arc<dyn Material>,
+}
+
+impl SdfShape {
+    // Central-difference gradient of the distance.
+    fn normal(&self, p: &Vec3) -> Vec3 {
+        let h = self.epsilon;
+        let axis = |e: Vec3| {
+            self.sdf.distance(&(p.clone() + e.clone())) - self.sdf.distance(&(p.clone() - e))
+        };
+        Vec3::new(
+            axis(Vec3::new(h, 0.0, 0.0)),
+            axis(Vec3::new(0.0, h, 0.0)),
+            axis(Vec3::new(0.0, 0.0, h)),
+        )
+        .unit()
+    }
+}
+
+impl Hitable for SdfShape {
+    fn hit(&self, ra: &Ray, t_min: f64, t_max: f64) -> Option<HitResult> {
+        let (mut t, t_end) = self.bbox.clip(ra, t_min, t_max)?;
+        let length = ra.direction.length();
+        let mut distance = self.sdf.distance(&ra.at(t)).abs();
+        let mut steps = 0;
+        // A ray starting on the surface, such as the next query of a walk
+        // along the ray, first leaves it.
+        while distance < self.epsilon && t <= t_end && steps < self.max_steps {
+            t += self.epsilon / length;
+            distance = self.sdf.distance(&ra.at(t)).abs();
+            steps += 1;
+        }
+        while t <= t_end && steps < self.max_steps {
+            if distance < self.epsilon {
+                let p = ra.at(t);
+                let mut hit_result =
+                    HitResult::new(ra, t, self.normal(&p), 0.0, 0.0, self.mat_ptr.clone());
+                hit_result.set_implicit_error(self.epsilon);
+                return Some(hit_result);
+            }
+            t += distance / length;
+            distance = self.sdf.distance(&ra.at(t)).abs();
+            steps += 1;
+        }
+        None
+    }
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(self.bbox.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sphere_tracing() {
+        let sdf = SmoothUnion {
+            a: Arc::new(RoundedBoxSdf {
+                center: Vec3::zero(),
+                half_size: Vec3::new(1.0, 0.5, 0.5),
+                radius: 0.1,
+            }),
+            b: Arc::new(|p: &Vec3| (p.clone() - Vec3::new(5.0, 0.0, 0.0)).length() - 1.0),
+            k: 0.2,
+        };
+        let shape = SdfShape {
+            sdf: Arc::new(sdf),
+            bbox: AABB {
+                min: Vec3::new(-2.0, -2.0, -2.0),
+                max: Vec3::new(7.0, 2.0, 2.0),
+            },
+            epsilon: 1e-6,
+            max_steps: 256,
+            mat_ptr: white_lambertian(),
+        };
+        let ra = Ray {
+            origin: Vec3::new(0.0, 0.0, -5.0),
+            direction: Vec3::new(0.0, 0.0, 2.0),
+        };
+        let hit = shape.hit(&ra, 0.0, f64::INFINITY).unwrap();
+        assert!((hit.t - 2.2).abs() < 1e-5);
+        assert!((hit.normal - Vec3::new(0.0, 0.0, -1.0)).length() < 1e-4);
+        let exit = shape.hit(&ra, hit.t + 0.0001, f64::INFINITY).unwrap();
+        assert!((exit.t - 2.8).abs() < 1e-5);
+        assert!(!exit.front_face);
+    }
+}
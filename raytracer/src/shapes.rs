@@ -0,0 +1,731 @@
+use crate::hit::*;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// Real roots of a x^2 + b x + c in increasing order, using the stable form
+// of the quadratic formula.
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    if a == 0.0 {
+        return if b == 0.0 { vec![] } else { vec![-c / b] };
+    }
+    let delta = b * b - 4.0 * a * c;
+    if delta < 0.0 {
+        return vec![];
+    }
+    let q = -0.5 * (b + b.signum() * delta.sqrt());
+    if q == 0.0 {
+        return vec![0.0];
+    }
+    let (t0, t1) = (q / a, c / q);
+    if t0 < t1 {
+        vec![t0, t1]
+    } else {
+        vec![t1, t0]
+    }
+}
+
+// Real roots of x^3 + a x^2 + b x + c.
+#[allow(clippy::many_single_char_names)]
+pub fn solve_cubic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    let q = (a * a - 3.0 * b) / 9.0;
+    let r = (2.0 * a * a * a - 9.0 * a * b + 27.0 * c) / 54.0;
+    if r * r < q * q * q {
+        let theta = (r / (q * q * q).sqrt()).max(-1.0).min(1.0).acos();
+        let s = -2.0 * q.sqrt();
+        (0..3)
+            .map(|k| s * ((theta + 2.0 * PI * k as f64) / 3.0).cos() - a / 3.0)
+            .collect()
+    } else {
+        let big = -r.signum() * (r.abs() + (r * r - q * q * q).sqrt()).cbrt();
+        let small = if big == 0.0 { 0.0 } else { q / big };
+        vec![big + small - a / 3.0]
+    }
+}
+
+// Real roots of c[4] x^4 + c[3] x^3 + c[2] x^2 + c[1] x + c[0] by Ferrari's
+// method, polished with Newton steps on the original polynomial.
+#[allow(clippy::many_single_char_names)]
+pub fn solve_quartic(c: [f64; 5]) -> Vec<f64> {
+    let (b, cc, d, e) = (c[3] / c[4], c[2] / c[4], c[1] / c[4], c[0] / c[4]);
+    // Depressed quartic y^4 + p y^2 + q y + r with x = y - b / 4.
+    let p = cc - 3.0 * b * b / 8.0;
+    let q = d - b * cc / 2.0 + b * b * b / 8.0;
+    let r = e - b * d / 4.0 + b * b * cc / 16.0 - 3.0 * b * b * b * b / 256.0;
+    let mut ys = vec![];
+    if q.abs() < 1e-12 {
+        for z in solve_quadratic(1.0, p, r) {
+            if z >= 0.0 {
+                ys.push(z.sqrt());
+                ys.push(-z.sqrt());
+            }
+        }
+    } else {
+        let m = solve_cubic(p, p * p / 4.0 - r, -q * q / 8.0)
+            .into_iter()
+            .fold(f64::NEG_INFINITY, f64::max);
+        if m <= 0.0 {
+            return vec![];
+        }
+        let s = (2.0 * m).sqrt();
+        ys.extend(solve_quadratic(1.0, s, p / 2.0 + m - q / (2.0 * s)));
+        ys.extend(solve_quadratic(1.0, -s, p / 2.0 + m + q / (2.0 * s)));
+    }
+    let f = |x: f64| (((c[4] * x + c[3]) * x + c[2]) * x + c[1]) * x + c[0];
+    let df = |x: f64| ((4.0 * c[4] * x + 3.0 * c[3]) * x + 2.0 * c[2]) * x + c[1];
+    ys.into_iter()
+        .map(|y| {
+            let mut x = y - b / 4.0;
+            for _ in 0..2 {
+                let slope = df(x);
+                if slope != 0.0 {
+                    x -= f(x) / slope;
+                }
+            }
+            x
+        })
+        .collect()
+}
+
+// Solid-angle density of the direction `v` from `origin` when points are
+// picked uniformly over `area` of `shape`, summed over every crossing of the
+// shape along the ray.
+pub fn area_pdf(shape: &dyn Hitable, area: f64, origin: Vec3, v: Vec3) -> f64 {
+    let mut ra = Ray {
+        origin: origin.clone(),
+        direction: v.clone(),
+    };
+    let mut pdf = 0.0;
+    let mut t_min = 0.001;
+    // Each further crossing is found from a ray spawned clear of the last.
+    while let Some(rec) = shape.hit(&ra, t_min, f64::INFINITY) {
+        let distance_squared = (rec.p.clone() - origin.clone()).squared_length();
+        let cosine = (v.clone() * rec.normal.clone()).abs() / v.length();
+        pdf += distance_squared / (cosine * area);
+        ra = rec.spawn_ray(v.clone());
+        t_min = 0.0;
+    }
+    pdf
+}
+
+// Shapes other than `Quad` are modelled around the z axis, with their base
+// at `center` and z along `axis`.
+struct Frame {
+    center: Vec3,
+    onb: ONB,
+}
+
+// A hit found in the shape's own frame.
+struct LocalHit {
+    t: f64,
+    normal: Vec3,
+    dpdu: Vec3,
+    dpdv: Vec3,
+    u: f64,
+    v: f64,
+}
+
+impl Frame {
+    fn new(center: &Vec3, axis: &Vec3) -> Self {
+        Self {
+            center: center.clone(),
+            onb: ONB::build_from_w(axis.clone()),
+        }
+    }
+    // The frame is rigid, so ray parameters carry over unchanged.
+    fn to_local(&self, ra: &Ray) -> Ray {
+        Ray {
+            origin: self.onb.to_local(ra.origin.clone() - self.center.clone()),
+            direction: self.onb.to_local(ra.direction.clone()),
+        }
+    }
+    fn point(&self, p: Vec3) -> Vec3 {
+        self.center.clone() + self.onb.localvec(p)
+    }
+    fn finish(&self, ra: &Ray, hit: LocalHit, mat_ptr: &Arc<dyn Material>) -> HitResult {
+        let mut hit_result = HitResult::new(
+            ra,
+            hit.t,
+            self.onb.localvec(hit.normal).unit(),
+            hit.u,
+            hit.v,
+            mat_ptr.clone(),
+        );
+        hit_result.dpdu = self.onb.localvec(hit.dpdu);
+        hit_result.dpdv = self.onb.localvec(hit.dpdv);
+        // Roots of the local equations carry more error than `Ray::at`.
+        hit_result.p_error += (hit_result.p.clone() - self.center.clone()).abs() * gamma(7);
+        hit_result
+    }
+    fn bounding_box(&self, min: Vec3, max: Vec3) -> AABB {
+        let mut lo = Vec3::ones() * f64::INFINITY;
+        let mut hi = -Vec3::ones() * f64::INFINITY;
+        for i in 0..8 {
+            let corner = Vec3::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            );
+            let p = self.point(corner);
+            lo = lo.min(p.clone());
+            hi = hi.max(p);
+        }
+        AABB { min: lo, max: hi }
+    }
+}
+
+// Angle around the z axis in [0, 2 pi).
+fn azimuth(x: f64, y: f64) -> f64 {
+    let phi = y.atan2(x);
+    if phi < 0.0 {
+        phi + 2.0 * PI
+    } else {
+        phi
+    }
+}
+
+// Crossing of a local ray with the ring inner_radius <= r <= radius of the
+// plane z = height, facing +z or -z.
+fn hit_ring(
+    ra: &Ray,
+    height: f64,
+    up: bool,
+    inner_radius: f64,
+    radius: f64,
+    t_min: f64,
+    t_max: f64,
+) -> Option<LocalHit> {
+    if ra.direction.z == 0.0 {
+        return None;
+    }
+    let t = (height - ra.origin.z) / ra.direction.z;
+    if t.is_nan() || t <= t_min || t > t_max {
+        return None;
+    }
+    let p = ra.at(t);
+    let r = (p.x * p.x + p.y * p.y).sqrt();
+    if r > radius || r < inner_radius {
+        return None;
+    }
+    let side = if up { 1.0 } else { -1.0 };
+    Some(LocalHit {
+        t,
+        normal: Vec3::new(0.0, 0.0, side),
+        dpdu: Vec3::new(-p.y, p.x, 0.0) * (2.0 * PI),
+        dpdv: if r > 0.0 {
+            Vec3::new(p.x, p.y, 0.0) * ((inner_radius - radius) / r)
+        } else {
+            Vec3::zero()
+        },
+        u: azimuth(p.x, p.y) / (2.0 * PI),
+        v: (radius - r) / (radius - inner_radius),
+    })
+}
+
+// Uniform point on a ring of the plane z = height.
+fn sample_ring(height: f64, inner_radius: f64, radius: f64) -> Vec3 {
+    let r2 = inner_radius * inner_radius;
+    let r = (r2 + random_double() * (radius * radius - r2)).sqrt();
+    let phi = 2.0 * PI * random_double();
+    Vec3::new(r * phi.cos(), r * phi.sin(), height)
+}
+
+fn closest(hits: Vec<Option<LocalHit>>) -> Option<LocalHit> {
+    hits.into_iter()
+        .flatten()
+        .filter(|hit| !hit.t.is_nan())
+        .min_by(|a, b| a.t.partial_cmp(&b.t).unwrap())
+}
+
+// Parallelogram with corner `q` and edges `u` and `v`; (u, v) run along the
+// edges and the normal is u x v.
+pub struct Quad {
+    pub q: Vec3,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub mat_ptr: Arc<dyn Material>,
+}
+
+impl Hitable for Quad {
+    fn hit(&self, ra: &Ray, t_min: f64, t_max: f64) -> Option<HitResult> {
+        let n = Vec3::cross(self.u.clone(), self.v.clone());
+        let denom = n.clone() * ra.direction.clone();
+        if denom.abs() < 1e-12 {
+            return None;
+        }
+        let t = (n.clone() * (self.q.clone() - ra.origin.clone())) / denom;
+        if t.is_nan() || t <= t_min || t > t_max {
+            return None;
+        }
+        let h = ra.at(t) - self.q.clone();
+        let w = n.clone() / n.squared_length();
+        let alpha = w.clone() * Vec3::cross(h.clone(), self.v.clone());
+        let beta = w * Vec3::cross(self.u.clone(), h);
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+        let mut hit_result = HitResult::new(ra, t, n.unit(), alpha, beta, self.mat_ptr.clone());
+        hit_result.dpdu = self.u.clone();
+        hit_result.dpdv = self.v.clone();
+        Some(hit_result)
+    }
+    fn bounding_box(&self) -> Option<AABB> {
+        let corners = [
+            self.q.clone(),
+            self.q.clone() + self.u.clone(),
+            self.q.clone() + self.v.clone(),
+            self.q.clone() + self.u.clone() + self.v.clone(),
+        ];
+        let mut min = corners[0].clone();
+        let mut max = corners[0].clone();
+        for c in corners.iter() {
+            min = min.min(c.clone());
+            max = max.max(c.clone());
+        }
+        Some(AABB {
+            min: min - Vec3::ones() * 0.0001,
+            max: max + Vec3::ones() * 0.0001,
+        })
+    }
+    fn pdf_value(&self, o: Vec3, v: Vec3) -> f64 {
+        let area = Vec3::cross(self.u.clone(), self.v.clone()).length();
+        area_pdf(self, area, o, v)
+    }
+    fn random(&self, o: Vec3) -> Vec3 {
+        self.q.clone() + self.u.clone() * random_double() + self.v.clone() * random_double() - o
+    }
+}
+
+// Disk of `radius` facing `axis`, or an annulus when `inner_radius` is
+// positive. u runs around the axis and v inwards.
+pub struct Disk {
+    pub center: Vec3,
+    pub axis: Vec3,
+    pub radius: f64,
+    pub inner_radius: f64,
+    pub mat_ptr: Arc<dyn Material>,
+}
+
+impl Hitable for Disk {
+    fn hit(&self, ra: &Ray, t_min: f64, t_max: f64) -> Option<HitResult> {
+        let frame = Frame::new(&self.center, &self.axis);
+        let local = frame.to_local(ra);
+        hit_ring(
+            &local,
+            0.0,
+            true,
+            self.inner_radius,
+            self.radius,
+            t_min,
+            t_max,
+        )
+        .map(|hit| frame.finish(ra, hit, &self.mat_ptr))
+    }
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(Frame::new(&self.center, &self.axis).bounding_box(
+            Vec3::new(-self.radius, -self.radius, -0.0001),
+            Vec3::new(self.radius, self.radius, 0.0001),
+        ))
+    }
+    fn pdf_value(&self, o: Vec3, v: Vec3) -> f64 {
+        let area = PI * (self.radius * self.radius - self.inner_radius * self.inner_radius);
+        area_pdf(self, area, o, v)
+    }
+    fn random(&self, o: Vec3) -> Vec3 {
+        let frame = Frame::new(&self.center, &self.axis);
+        frame.point(sample_ring(0.0, self.inner_radius, self.radius)) - o
+    }
+}
+
+// Cylinder of `radius` from `center` to `center + height * axis`, closed by
+// disks at both ends when `capped`. On the side u runs around the axis and v
+// along it; the caps are parametrized as `Disk`.
+pub struct Cylinder {
+    pub center: Vec3,
+    pub axis: Vec3,
+    pub radius: f64,
+    pub height: f64,
+    pub capped: bool,
+    pub mat_ptr: Arc<dyn Material>,
+}
+
+impl Cylinder {
+    fn hit_side(&self, ra: &Ray, t_min: f64, t_max: f64) -> Option<LocalHit> {
+        let (o, d) = (&ra.origin, &ra.direction);
+        let roots = solve_quadratic(
+            d.x * d.x + d.y * d.y,
+            2.0 * (o.x * d.x + o.y * d.y),
+            o.x * o.x + o.y * o.y - self.radius * self.radius,
+        );
+        for t in roots {
+            if t.is_nan() || t <= t_min || t > t_max {
+                continue;
+            }
+            let p = ra.at(t);
+            if p.z < 0.0 || p.z > self.height {
+                continue;
+            }
+            return Some(LocalHit {
+                t,
+                normal: Vec3::new(p.x, p.y, 0.0) / self.radius,
+                dpdu: Vec3::new(-p.y, p.x, 0.0) * (2.0 * PI),
+                dpdv: Vec3::new(0.0, 0.0, self.height),
+                u: azimuth(p.x, p.y) / (2.0 * PI),
+                v: p.z / self.height,
+            });
+        }
+        None
+    }
+    fn side_area(&self) -> f64 {
+        2.0 * PI * self.radius * self.height
+    }
+    fn cap_area(&self) -> f64 {
+        if self.capped {
+            PI * self.radius * self.radius
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Hitable for Cylinder {
+    fn hit(&self, ra: &Ray, t_min: f64, t_max: f64) -> Option<HitResult> {
+        let frame = Frame::new(&self.center, &self.axis);
+        let local = frame.to_local(ra);
+        let mut hits = vec![self.hit_side(&local, t_min, t_max)];
+        if self.capped {
+            hits.push(hit_ring(&local, 0.0, false, 0.0, self.radius, t_min, t_max));
+            hits.push(hit_ring(
+                &local,
+                self.height,
+                true,
+                0.0,
+                self.radius,
+                t_min,
+                t_max,
+            ));
+        }
+        closest(hits).map(|hit| frame.finish(ra, hit, &self.mat_ptr))
+    }
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(Frame::new(&self.center, &self.axis).bounding_box(
+            Vec3::new(-self.radius, -self.radius, 0.0),
+            Vec3::new(self.radius, self.radius, self.height),
+        ))
+    }
+    fn pdf_value(&self, o: Vec3, v: Vec3) -> f64 {
+        let area = self.side_area() + 2.0 * self.cap_area();
+        area_pdf(self, area, o, v)
+    }
+    fn random(&self, o: Vec3) -> Vec3 {
+        let (side, cap) = (self.side_area(), self.cap_area());
+        let pick = random_double() * (side + 2.0 * cap);
+        let p = if pick < side {
+            let phi = 2.0 * PI * random_double();
+            Vec3::new(
+                self.radius * phi.cos(),
+                self.radius * phi.sin(),
+                self.height * random_double(),
+            )
+        } else if pick < side + cap {
+            sample_ring(0.0, 0.0, self.radius)
+        } else {
+            sample_ring(self.height, 0.0, self.radius)
+        };
+        Frame::new(&self.center, &self.axis).point(p) - o
+    }
+}
+
+// Cone with a base of `radius` at `center` and its apex at
+// `center + height * axis`, closed by the base disk when `capped`.
+// u runs around the axis and v up towards the apex.
+pub struct Cone {
+    pub center: Vec3,
+    pub axis: Vec3,
+    pub radius: f64,
+    pub height: f64,
+    pub capped: bool,
+    pub mat_ptr: Arc<dyn Material>,
+}
+
+impl Cone {
+    fn hit_side(&self, ra: &Ray, t_min: f64, t_max: f64) -> Option<LocalHit> {
+        let (o, d) = (&ra.origin, &ra.direction);
+        let k = (self.radius / self.height) * (self.radius / self.height);
+        let h = self.height - o.z;
+        let roots = solve_quadratic(
+            d.x * d.x + d.y * d.y - k * d.z * d.z,
+            2.0 * (o.x * d.x + o.y * d.y + k * h * d.z),
+            o.x * o.x + o.y * o.y - k * h * h,
+        );
+        for t in roots {
+            if t.is_nan() || t <= t_min || t > t_max {
+                continue;
+            }
+            let hit_point = ra.at(t);
+            if hit_point.z < 0.0 || hit_point.z > self.height {
+                continue;
+            }
+            let phi = azimuth(hit_point.x, hit_point.y);
+            return Some(LocalHit {
+                t,
+                normal: Vec3::new(hit_point.x, hit_point.y, k * (self.height - hit_point.z)).unit(),
+                dpdu: Vec3::new(-hit_point.y, hit_point.x, 0.0) * (2.0 * PI),
+                dpdv: Vec3::new(
+                    -self.radius * phi.cos(),
+                    -self.radius * phi.sin(),
+                    self.height,
+                ),
+                u: phi / (2.0 * PI),
+                v: hit_point.z / self.height,
+            });
+        }
+        None
+    }
+    fn side_area(&self) -> f64 {
+        PI * self.radius * (self.radius * self.radius + self.height * self.height).sqrt()
+    }
+    fn cap_area(&self) -> f64 {
+        if self.capped {
+            PI * self.radius * self.radius
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Hitable for Cone {
+    fn hit(&self, ra: &Ray, t_min: f64, t_max: f64) -> Option<HitResult> {
+        let frame = Frame::new(&self.center, &self.axis);
+        let local = frame.to_local(ra);
+        let mut hits = vec![self.hit_side(&local, t_min, t_max)];
+        if self.capped {
+            hits.push(hit_ring(&local, 0.0, false, 0.0, self.radius, t_min, t_max));
+        }
+        closest(hits).map(|hit| frame.finish(ra, hit, &self.mat_ptr))
+    }
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(Frame::new(&self.center, &self.axis).bounding_box(
+            Vec3::new(-self.radius, -self.radius, 0.0),
+            Vec3::new(self.radius, self.radius, self.height),
+        ))
+    }
+    fn pdf_value(&self, o: Vec3, v: Vec3) -> f64 {
+        area_pdf(self, self.side_area() + self.cap_area(), o, v)
+    }
+    fn random(&self, o: Vec3) -> Vec3 {
+        let (side, cap) = (self.side_area(), self.cap_area());
+        let p = if random_double() * (side + cap) < side {
+            // Area grows linearly with the distance from the apex.
+            let s = random_double().sqrt();
+            let phi = 2.0 * PI * random_double();
+            Vec3::new(
+                self.radius * s * phi.cos(),
+                self.radius * s * phi.sin(),
+                self.height * (1.0 - s),
+            )
+        } else {
+            sample_ring(0.0, 0.0, self.radius)
+        };
+        Frame::new(&self.center, &self.axis).point(p) - o
+    }
+}
+
+// Torus around `axis` through `center`: a tube of `minor_radius` around a
+// circle of `major_radius`. u runs around the axis and v around the tube.
+pub struct Torus {
+    pub center: Vec3,
+    pub axis: Vec3,
+    pub major_radius: f64,
+    pub minor_radius: f64,
+    pub mat_ptr: Arc<dyn Material>,
+}
+
+impl Torus {
+    #[allow(clippy::many_single_char_names)]
+    fn hit_local(&self, ra: &Ray, t_min: f64, t_max: f64) -> Option<LocalHit> {
+        let length = ra.direction.length();
+        let d = ra.direction.clone() / length;
+        // Solve from the point of the ray closest to the center, which keeps
+        // the coefficients small for distant rays.
+        let shift = -(ra.origin.clone() * d.clone());
+        let o = ra.origin.clone() + d.clone() * shift;
+        let (big, small) = (self.major_radius, self.minor_radius);
+        let od = o.clone() * d.clone();
+        let k = o.squared_length() + big * big - small * small;
+        let mut roots = solve_quartic([
+            k * k - 4.0 * big * big * (o.x * o.x + o.y * o.y),
+            4.0 * od * k - 8.0 * big * big * (o.x * d.x + o.y * d.y),
+            2.0 * k + 4.0 * od * od - 4.0 * big * big * (d.x * d.x + d.y * d.y),
+            4.0 * od,
+            1.0,
+        ]);
+        // Near-degenerate quartics may give NaN roots, which are no hits.
+        roots.retain(|root| root.is_finite());
+        roots.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        for s in roots {
+            let t = (s + shift) / length;
+            if t.is_nan() || t <= t_min || t > t_max {
+                continue;
+            }
+            let p = ra.at(t);
+            let rho = (p.x * p.x + p.y * p.y).sqrt();
+            let phi = azimuth(p.x, p.y);
+            let theta = azimuth(rho - big, p.z);
+            let ring = Vec3::new(phi.cos(), phi.sin(), 0.0) * big;
+            return Some(LocalHit {
+                t,
+                normal: (p.clone() - ring).unit(),
+                dpdu: Vec3::new(-p.y, p.x, 0.0) * (2.0 * PI),
+                dpdv: Vec3::new(
+                    -small * theta.sin() * phi.cos(),
+                    -small * theta.sin() * phi.sin(),
+                    small * theta.cos(),
+                ) * (2.0 * PI),
+                u: phi / (2.0 * PI),
+                v: theta / (2.0 * PI),
+            });
+        }
+        None
+    }
+}
+
+impl Hitable for Torus {
+    fn hit(&self, ra: &Ray, t_min: f64, t_max: f64) -> Option<HitResult> {
+        let frame = Frame::new(&self.center, &self.axis);
+        self.hit_local(&frame.to_local(ra), t_min, t_max)
+            .map(|hit| frame.finish(ra, hit, &self.mat_ptr))
+    }
+    fn bounding_box(&self) -> Option<AABB> {
+        let r = self.major_radius + self.minor_radius;
+        Some(Frame::new(&self.center, &self.axis).bounding_box(
+            Vec3::new(-r, -r, -self.minor_radius),
+            Vec3::new(r, r, self.minor_radius),
+        ))
+    }
+    fn pdf_value(&self, o: Vec3, v: Vec3) -> f64 {
+        let area = 4.0 * PI * PI * self.major_radius * self.minor_radius;
+        area_pdf(self, area, o, v)
+    }
+    #[allow(clippy::many_single_char_names)]
+    fn random(&self, o: Vec3) -> Vec3 {
+        let (big, small) = (self.major_radius, self.minor_radius);
+        // The area element grows with the distance from the axis.
+        let theta = loop {
+            let theta = 2.0 * PI * random_double();
+            if random_double() * (big + small) < big + small * theta.cos() {
+                break theta;
+            }
+        };
+        let phi = 2.0 * PI * random_double();
+        let rho = big + small * theta.cos();
+        let p = Vec3::new(rho * phi.cos(), rho * phi.sin(), small * theta.sin());
+        Frame::new(&self.center, &self.axis).point(p) - o
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_solve_quartic() {
+        // (x - 1)(x - 2)(x + 3)(x - 0.5)
+        let mut roots = solve_quartic([-3.0, 9.5, -7.0, -0.5, 1.0]);
+        roots.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let expected = [-3.0, 0.5, 1.0, 2.0];
+        assert_eq!(roots.len(), 4);
+        for (root, e) in roots.iter().zip(expected.iter()) {
+            assert!((root - e).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_area_sampling() {
+        let mat_ptr = white_lambertian();
+        let axis = Vec3::new(0.2, 0.5, 1.0);
+        let shapes: Vec<Arc<dyn Hitable>> = vec![
+            Arc::new(Sphere {
+                center: Vec3::new(0.3, 0.0, 0.0),
+                radius: 0.6,
+                mat_ptr: mat_ptr.clone(),
+            }),
+            Arc::new(Quad {
+                q: Vec3::new(-1.0, -0.5, 0.0),
+                u: Vec3::new(2.0, 0.0, 0.5),
+                v: Vec3::new(0.0, 1.5, 0.0),
+                mat_ptr: mat_ptr.clone(),
+            }),
+            Arc::new(Disk {
+                center: Vec3::zero(),
+                axis: axis.clone(),
+                radius: 1.0,
+                inner_radius: 0.4,
+                mat_ptr: mat_ptr.clone(),
+            }),
+            Arc::new(Cylinder {
+                center: Vec3::zero(),
+                axis: axis.clone(),
+                radius: 0.5,
+                height: 1.0,
+                capped: true,
+                mat_ptr: mat_ptr.clone(),
+            }),
+            Arc::new(Cone {
+                center: Vec3::zero(),
+                axis: axis.clone(),
+                radius: 0.7,
+                height: 1.2,
+                capped: false,
+                mat_ptr: mat_ptr.clone(),
+            }),
+            Arc::new(Torus {
+                center: Vec3::zero(),
+                axis,
+                major_radius: 0.8,
+                minor_radius: 0.3,
+                mat_ptr,
+            }),
+        ];
+        let origin = Vec3::new(0.5, 0.4, 3.0);
+        let samples = 40000;
+        let mut rng = SeededRng::new(41);
+        for shape in shapes {
+            // With a correct pdf, E[1 / pdf] over sampled directions is the
+            // solid angle the shape subtends; compare it with the fraction of
+            // directions that hit it, drawn with a fixed seed from the cone
+            // around the shape's bounding sphere.
+            let bbox = shape.bounding_box().unwrap();
+            let center = (bbox.min.clone() + bbox.max.clone()) * 0.5;
+            let radius = (bbox.max.clone() - bbox.min.clone()).length() * 0.5;
+            let to_center = center - origin.clone();
+            let cos_max = (1.0 - radius * radius / to_center.squared_length()).sqrt();
+            let onb = ONB::build_from_w(to_center);
+            let mut inverse_pdf = 0.0;
+            let mut hits = 0;
+            for _ in 0..samples {
+                let v = shape.random(origin.clone());
+                inverse_pdf += 1.0 / shape.pdf_value(origin.clone(), v) / samples as f64;
+                let cos_theta = 1.0 - rng.next_double() * (1.0 - cos_max);
+                let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+                let phi = 2.0 * PI * rng.next_double();
+                let ra = Ray {
+                    origin: origin.clone(),
+                    direction: onb.local(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta),
+                };
+                if shape.hit(&ra, 0.001, f64::INFINITY).is_some() {
+                    hits += 1;
+                }
+            }
+            let cone = 2.0 * PI * (1.0 - cos_max);
+            let solid_angle = cone * hits as f64 / samples as f64;
+            assert!((inverse_pdf - solid_angle).abs() < 0.05 * solid_angle);
+
+            // A broken ray misses rather than panics.
+            let broken = Ray {
+                origin: origin.clone(),
+                direction: Vec3::new(f64::NAN, 0.0, -1.0),
+            };
+            assert!(shape.hit(&broken, 0.001, f64::INFINITY).is_none());
+        }
+    }
+}
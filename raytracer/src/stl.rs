@@ -0,0 +1,106 @@
+use crate::mesh::{invalid, TriangleMesh};
+use crate::vec3::Vec3;
+use std::fs;
+use std::io::Result;
+use std::path::Path;
+
+impl TriangleMesh {
+    // Loads binary or ASCII STL. Facets do not share vertices and their
+    // stored normals are ignored in favour of the winding.
+    pub fn from_stl<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::parse_stl(&fs::read(path)?)
+    }
+    fn parse_stl(bytes: &[u8]) -> Result<Self> {
+        // Binary files may also start with "solid", so trust the size first.
+        // Some exporters pad binary files past the last facet, so a longer
+        // file is binary too unless it reads as text.
+        let ascii = bytes.starts_with(b"solid") && std::str::from_utf8(bytes).is_ok();
+        if bytes.len() >= 84 {
+            let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+            let size = 84 + 50 * count;
+            if bytes.len() == size || (bytes.len() > size && !ascii) {
+                return Ok(Self::parse_binary_stl(&bytes[84..], count));
+            }
+        }
+        if ascii {
+            return Self::parse_ascii_stl(bytes);
+        }
+        Err(invalid("stl", "not an stl file"))
+    }
+    fn parse_binary_stl(data: &[u8], count: usize) -> Self {
+        let mut mesh = TriangleMesh::default();
+        for facet in data.chunks_exact(50).take(count) {
+            let float = |k: usize| {
+                let b = &facet[12 + 4 * k..16 + 4 * k];
+                f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64
+            };
+            let first = mesh.positions.len();
+            for v in 0..3 {
+                mesh.positions
+                    .push(Vec3::new(float(3 * v), float(3 * v + 1), float(3 * v + 2)));
+            }
+            mesh.indices.push([first, first + 1, first + 2]);
+        }
+        mesh
+    }
+    fn parse_ascii_stl(bytes: &[u8]) -> Result<Self> {
+        let text = std::str::from_utf8(bytes).map_err(|_| invalid("stl", "not text"))?;
+        let mut mesh = TriangleMesh::default();
+        let mut tokens = text.split_ascii_whitespace();
+        while let Some(token) = tokens.next() {
+            if token != "vertex" {
+                continue;
+            }
+            let mut coordinate = || -> Result<f64> {
+                tokens
+                    .next()
+                    .and_then(|value| value.parse().ok())
+                    .ok_or_else(|| invalid("stl", "bad vertex"))
+            };
+            let (x, y, z) = (coordinate()?, coordinate()?, coordinate()?);
+            mesh.positions.push(Vec3::new(x, y, z));
+            if mesh.positions.len() % 3 == 0 {
+                let first = mesh.positions.len() - 3;
+                mesh.indices.push([first, first + 1, first + 2]);
+            }
+        }
+        if mesh.positions.len() % 3 != 0 {
+            return Err(invalid("stl", "facet with missing vertices"));
+        }
+        Ok(mesh)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stl() {
+        let ascii = b"solid tri\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\n\
+            vertex 1 0 0\nvertex 0 1 0\nendloop\nendfacet\nendsolid tri\n";
+        let mesh = TriangleMesh::parse_stl(ascii).unwrap();
+        assert_eq!(mesh.positions[1], Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(mesh.indices, vec![[0, 1, 2]]);
+
+        // A binary file whose header happens to start with "solid".
+        let mut binary = b"solid".to_vec();
+        binary.resize(80, 0);
+        binary.extend_from_slice(&1u32.to_le_bytes());
+        for &v in &[
+            0.0f32, 0.0, 1.0, 0.0, 0.0, 0.0, 2.0, 0.0, 0.0, 0.0, 2.0, 0.0,
+        ] {
+            binary.extend_from_slice(&v.to_le_bytes());
+        }
+        binary.extend_from_slice(&[0, 0]);
+        let mesh = TriangleMesh::parse_stl(&binary).unwrap();
+        assert_eq!(mesh.positions[2], Vec3::new(0.0, 2.0, 0.0));
+        assert_eq!(mesh.indices, vec![[0, 1, 2]]);
+
+        // The same file padded past its last facet.
+        binary.extend_from_slice(&[0xff; 16]);
+        let mesh = TriangleMesh::parse_stl(&binary).unwrap();
+        assert_eq!(mesh.positions[2], Vec3::new(0.0, 2.0, 0.0));
+        assert_eq!(mesh.indices, vec![[0, 1, 2]]);
+    }
+}
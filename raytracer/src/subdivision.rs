@@ -0,0 +1,685 @@
+use crate::mesh::TriangleMesh;
+use crate::vec3::Vec3;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+// Polygons sharing their vertices, for subdivision before triangulation.
+// `uvs` and `colors` are either empty or hold a value for each corner of each
+// face, so seams need no duplicate vertices. An edge in `creases` stays sharp
+// for as many levels as its sharpness (fractional values blend, infinity is
+// sharp for good); boundary edges are always sharp and boundary vertices of a
+// single face are kept as corners.
+#[derive(Clone, Default)]
+pub struct PolygonMesh {
+    pub positions: Vec<Vec3>,
+    pub faces: Vec<Vec<usize>>,
+    pub uvs: Vec<Vec<(f64, f64)>>,
+    pub colors: Vec<Vec<Vec3>>,
+    pub creases: Vec<([usize; 2], f64)>,
+}
+
+fn edge_key(a: usize, b: usize) -> [usize; 2] {
+    if a < b {
+        [a, b]
+    } else {
+        [b, a]
+    }
+}
+
+pub fn position_key(p: &Vec3) -> [u64; 3] {
+    // Adding zero turns -0 into 0.
+    [
+        (p.x + 0.0).to_bits(),
+        (p.y + 0.0).to_bits(),
+        (p.z + 0.0).to_bits(),
+    ]
+}
+
+// Adjacency of a polygon mesh. Side i of a face runs from its corner i to
+// corner i + 1.
+struct Topology {
+    edges: Vec<[usize; 2]>,
+    edge_faces: Vec<Vec<usize>>,
+    sharpness: Vec<f64>,
+    face_edges: Vec<Vec<usize>>,
+    vertex_edges: Vec<Vec<usize>>,
+    vertex_faces: Vec<Vec<usize>>,
+}
+
+impl Topology {
+    fn new(mesh: &PolygonMesh) -> Self {
+        let mut index: HashMap<[usize; 2], usize> = HashMap::new();
+        let mut topology = Topology {
+            edges: Vec::new(),
+            edge_faces: Vec::new(),
+            sharpness: Vec::new(),
+            face_edges: Vec::with_capacity(mesh.faces.len()),
+            vertex_edges: vec![Vec::new(); mesh.positions.len()],
+            vertex_faces: vec![Vec::new(); mesh.positions.len()],
+        };
+        for (f, face) in mesh.faces.iter().enumerate() {
+            let mut sides = Vec::with_capacity(face.len());
+            for (i, &a) in face.iter().enumerate() {
+                let key = edge_key(a, face[(i + 1) % face.len()]);
+                let e = *index.entry(key).or_insert_with(|| {
+                    topology.edges.push(key);
+                    topology.edge_faces.push(Vec::new());
+                    topology.vertex_edges[key[0]].push(topology.edges.len() - 1);
+                    topology.vertex_edges[key[1]].push(topology.edges.len() - 1);
+                    topology.edges.len() - 1
+                });
+                topology.edge_faces[e].push(f);
+                topology.vertex_faces[a].push(f);
+                sides.push(e);
+            }
+            topology.face_edges.push(sides);
+        }
+        topology.sharpness = topology
+            .edge_faces
+            .iter()
+            .map(|faces| if faces.len() == 2 { 0.0 } else { f64::INFINITY })
+            .collect();
+        for &(edge, sharpness) in &mesh.creases {
+            if let Some(&e) = index.get(&edge_key(edge[0], edge[1])) {
+                topology.sharpness[e] = topology.sharpness[e].max(sharpness);
+            }
+        }
+        topology
+    }
+    fn other(&self, e: usize, v: usize) -> usize {
+        let [a, b] = self.edges[e];
+        if a == v {
+            b
+        } else {
+            a
+        }
+    }
+    // The new position of vertex `v` given its `smooth` position: the crease
+    // rule along two sharp edges, kept in place at corners and blended by
+    // the sharpness of the edges when below one.
+    fn vertex_point(&self, positions: &[Vec3], v: usize, smooth: Vec3) -> Vec3 {
+        let sharp: Vec<usize> = self.vertex_edges[v]
+            .iter()
+            .cloned()
+            .filter(|&e| self.sharpness[e] > 0.0)
+            .collect();
+        if sharp.len() < 2 {
+            return smooth;
+        }
+        let p = &positions[v];
+        let sharp_point = if sharp.len() > 2 || self.vertex_faces[v].len() == 1 {
+            p.clone()
+        } else {
+            p.clone() * 0.75
+                + (positions[self.other(sharp[0], v)].clone()
+                    + positions[self.other(sharp[1], v)].clone())
+                    * 0.125
+        };
+        let sharpness = sharp.iter().map(|&e| self.sharpness[e]).sum::<f64>() / sharp.len() as f64;
+        if sharpness >= 1.0 {
+            sharp_point
+        } else {
+            Vec3::lerp(sharp_point, smooth, sharpness)
+        }
+    }
+    fn edge_point(&self, positions: &[Vec3], e: usize, smooth: Vec3) -> Vec3 {
+        let [a, b] = self.edges[e];
+        let middle = (positions[a].clone() + positions[b].clone()) * 0.5;
+        let sharpness = self.sharpness[e];
+        if sharpness >= 1.0 {
+            middle
+        } else if sharpness <= 0.0 {
+            smooth
+        } else {
+            Vec3::lerp(middle, smooth, sharpness)
+        }
+    }
+    // Creases of the two halves of each sharp interior edge, one level
+    // softer; edge e is split at new vertex `first_edge_vertex + e`.
+    fn child_creases(&self, first_edge_vertex: usize) -> Vec<([usize; 2], f64)> {
+        let mut creases = Vec::new();
+        for (e, &[a, b]) in self.edges.iter().enumerate() {
+            let sharpness = self.sharpness[e] - 1.0;
+            if self.edge_faces[e].len() == 2 && sharpness > 0.0 {
+                creases.push(([a, first_edge_vertex + e], sharpness));
+                creases.push(([first_edge_vertex + e, b], sharpness));
+            }
+        }
+        creases
+    }
+}
+
+// For each corner of a child face, the weighted corners of its parent.
+type Child = Vec<Vec<(usize, f64)>>;
+
+// Splits per-corner values the way faces are split: `children[n]` lists the
+// children of a face of n corners.
+fn split_corner_values<T: Clone>(
+    values: &[Vec<T>],
+    children: &[Vec<Child>],
+    faces: &[Vec<usize>],
+    add: impl Fn(T, T) -> T + Copy,
+    scale: impl Fn(T, f64) -> T + Copy,
+) -> Vec<Vec<T>> {
+    if values.is_empty() {
+        return Vec::new();
+    }
+    let mut result = Vec::new();
+    for (f, face) in faces.iter().enumerate() {
+        let corners = &values[f];
+        for child in &children[face.len()] {
+            result.push(
+                child
+                    .iter()
+                    .map(|weights| {
+                        weights[1..].iter().fold(
+                            scale(corners[weights[0].0].clone(), weights[0].1),
+                            |sum, &(i, w)| add(sum, scale(corners[i].clone(), w)),
+                        )
+                    })
+                    .collect(),
+            );
+        }
+    }
+    result
+}
+
+fn add_uv(a: (f64, f64), b: (f64, f64)) -> (f64, f64) {
+    (a.0 + b.0, a.1 + b.1)
+}
+
+fn scale_uv(a: (f64, f64), s: f64) -> (f64, f64) {
+    (a.0 * s, a.1 * s)
+}
+
+impl PolygonMesh {
+    // Polygons over the vertices of `mesh`. Vertices at the same position are
+    // merged so that seams do not open up, their attributes moving to the
+    // face corners; where vertex normals differ across an edge, the edge is
+    // made an infinitely sharp crease.
+    pub fn from_polygons(mesh: &TriangleMesh, faces: &[Vec<usize>]) -> Self {
+        let mut welded: HashMap<[u64; 3], usize> = HashMap::new();
+        let mut positions = Vec::new();
+        let remap: Vec<usize> = mesh
+            .positions
+            .iter()
+            .map(|p| {
+                *welded.entry(position_key(p)).or_insert_with(|| {
+                    positions.push(p.clone());
+                    positions.len() - 1
+                })
+            })
+            .collect();
+        // Faces that collapse when welding are dropped.
+        let faces: Vec<&Vec<usize>> = faces
+            .iter()
+            .filter(|face| {
+                face.len() >= 3
+                    && (0..face.len()).all(|i| remap[face[i]] != remap[face[(i + 1) % face.len()]])
+            })
+            .collect();
+        let corners = |values: &[(f64, f64)]| -> Vec<Vec<(f64, f64)>> {
+            faces
+                .iter()
+                .map(|face| face.iter().map(|&v| values[v]).collect())
+                .collect()
+        };
+        let uvs = if mesh.uvs.is_empty() {
+            Vec::new()
+        } else {
+            corners(&mesh.uvs)
+        };
+        let colors = if mesh.colors.is_empty() {
+            Vec::new()
+        } else {
+            faces
+                .iter()
+                .map(|face| face.iter().map(|&v| mesh.colors[v].clone()).collect())
+                .collect()
+        };
+        let mut creases = Vec::new();
+        if !mesh.normals.is_empty() {
+            // The original vertices at both ends of each side of each face.
+            let mut sides: HashMap<[usize; 2], Vec<[usize; 2]>> = HashMap::new();
+            for face in &faces {
+                for i in 0..face.len() {
+                    let (a, b) = (face[i], face[(i + 1) % face.len()]);
+                    let entry = sides.entry(edge_key(remap[a], remap[b])).or_default();
+                    entry.push(if remap[a] < remap[b] { [a, b] } else { [b, a] });
+                }
+            }
+            let same =
+                |a: usize, b: usize| mesh.normals[a].clone() * mesh.normals[b].clone() > 1.0 - 1e-6;
+            for (edge, ends) in sides {
+                if ends.len() == 2
+                    && !(same(ends[0][0], ends[1][0]) && same(ends[0][1], ends[1][1]))
+                {
+                    creases.push((edge, f64::INFINITY));
+                }
+            }
+        }
+        Self {
+            positions,
+            faces: faces
+                .iter()
+                .map(|face| face.iter().map(|&v| remap[v]).collect())
+                .collect(),
+            uvs,
+            colors,
+            creases,
+        }
+    }
+    pub fn from_triangles(mesh: &TriangleMesh) -> Self {
+        let faces: Vec<Vec<usize>> = mesh.indices.iter().map(|face| face.to_vec()).collect();
+        Self::from_polygons(mesh, &faces)
+    }
+    fn is_triangles(&self) -> bool {
+        self.faces.iter().all(|face| face.len() == 3)
+    }
+    // `levels` rounds of Loop subdivision if every face is a triangle and of
+    // Catmull-Clark otherwise.
+    pub fn subdivide(&self, levels: usize) -> Self {
+        let mut mesh = self.clone();
+        for _ in 0..levels {
+            mesh = if mesh.is_triangles() {
+                mesh.loop_subdivide()
+            } else {
+                mesh.catmull_clark()
+            };
+        }
+        mesh
+    }
+    // One round of Catmull-Clark subdivision, which turns every n-gon into n
+    // quads. Corner attributes are interpolated linearly within each face.
+    pub fn catmull_clark(&self) -> Self {
+        let topology = Topology::new(self);
+        let p = &self.positions;
+        let (vertex_count, edge_count) = (p.len(), topology.edges.len());
+        let face_points: Vec<Vec3> = self
+            .faces
+            .iter()
+            .map(|face| {
+                face.iter().fold(Vec3::zero(), |sum, &v| sum + p[v].clone()) / face.len() as f64
+            })
+            .collect();
+        let edge_points: Vec<Vec3> = (0..edge_count)
+            .map(|e| {
+                let [a, b] = topology.edges[e];
+                let faces = &topology.edge_faces[e];
+                let smooth = if faces.len() == 2 {
+                    (p[a].clone()
+                        + p[b].clone()
+                        + face_points[faces[0]].clone()
+                        + face_points[faces[1]].clone())
+                        * 0.25
+                } else {
+                    (p[a].clone() + p[b].clone()) * 0.5
+                };
+                topology.edge_point(p, e, smooth)
+            })
+            .collect();
+        let vertex_points: Vec<Vec3> = (0..vertex_count)
+            .map(|v| {
+                let (edges, faces) = (&topology.vertex_edges[v], &topology.vertex_faces[v]);
+                if edges.is_empty() {
+                    return p[v].clone();
+                }
+                let n = edges.len() as f64;
+                let q = faces
+                    .iter()
+                    .fold(Vec3::zero(), |sum, &f| sum + face_points[f].clone())
+                    / faces.len() as f64;
+                let r = edges.iter().fold(Vec3::zero(), |sum, &e| {
+                    sum + (p[v].clone() + p[topology.other(e, v)].clone()) * 0.5
+                }) / n;
+                let smooth = (q + r * 2.0 + p[v].clone() * (n - 3.0)) / n;
+                topology.vertex_point(p, v, smooth)
+            })
+            .collect();
+
+        let mut faces = Vec::new();
+        for (f, face) in self.faces.iter().enumerate() {
+            let n = face.len();
+            let sides = &topology.face_edges[f];
+            for i in 0..n {
+                faces.push(vec![
+                    face[i],
+                    vertex_count + sides[i],
+                    vertex_count + edge_count + f,
+                    vertex_count + sides[(i + n - 1) % n],
+                ]);
+            }
+        }
+        // Child i of an n-gon has the corner i, the middles of the sides on
+        // either side of it and the center.
+        let largest = self.faces.iter().map(Vec::len).max().unwrap_or(0);
+        let children: Vec<Vec<Child>> = (0..=largest)
+            .map(|n| {
+                (0..n)
+                    .map(|i| {
+                        let center = (0..n).map(|k| (k, 1.0 / n as f64)).collect();
+                        vec![
+                            vec![(i, 1.0)],
+                            vec![(i, 0.5), ((i + 1) % n, 0.5)],
+                            center,
+                            vec![(i, 0.5), ((i + n - 1) % n, 0.5)],
+                        ]
+                    })
+                    .collect()
+            })
+            .collect();
+        Self {
+            positions: vertex_points
+                .into_iter()
+                .chain(edge_points)
+                .chain(face_points)
+                .collect(),
+            uvs: split_corner_values(&self.uvs, &children, &self.faces, add_uv, scale_uv),
+            colors: split_corner_values(
+                &self.colors,
+                &children,
+                &self.faces,
+                |a, b| a + b,
+                |a, s| a * s,
+            ),
+            faces,
+            creases: topology.child_creases(vertex_count),
+        }
+    }
+    // One round of Loop subdivision, which splits every triangle into four.
+    // Corner attributes are interpolated linearly within each face.
+    pub fn loop_subdivide(&self) -> Self {
+        assert!(self.is_triangles(), "loop subdivision needs triangles");
+        let topology = Topology::new(self);
+        let p = &self.positions;
+        let (vertex_count, edge_count) = (p.len(), topology.edges.len());
+        let edge_points = (0..edge_count).map(|e| {
+            let [a, b] = topology.edges[e];
+            let faces = &topology.edge_faces[e];
+            let smooth = if faces.len() == 2 {
+                let opposite = |f: usize| {
+                    let face = &self.faces[f];
+                    p[face.iter().cloned().find(|&v| v != a && v != b).unwrap()].clone()
+                };
+                (p[a].clone() + p[b].clone()) * 0.375
+                    + (opposite(faces[0]) + opposite(faces[1])) * 0.125
+            } else {
+                (p[a].clone() + p[b].clone()) * 0.5
+            };
+            topology.edge_point(p, e, smooth)
+        });
+        let vertex_points = (0..vertex_count).map(|v| {
+            let edges = &topology.vertex_edges[v];
+            if edges.is_empty() {
+                return p[v].clone();
+            }
+            let n = edges.len() as f64;
+            let c = 0.375 + 0.25 * (2.0 * PI / n).cos();
+            let beta = (0.625 - c * c) / n;
+            let neighbors = edges.iter().fold(Vec3::zero(), |sum, &e| {
+                sum + p[topology.other(e, v)].clone()
+            });
+            let smooth = p[v].clone() * (1.0 - n * beta) + neighbors * beta;
+            topology.vertex_point(p, v, smooth)
+        });
+        let positions: Vec<Vec3> = vertex_points.chain(edge_points).collect();
+
+        let mut faces = Vec::new();
+        for (f, face) in self.faces.iter().enumerate() {
+            let sides = &topology.face_edges[f];
+            let (ab, bc, ca) = (
+                vertex_count + sides[0],
+                vertex_count + sides[1],
+                vertex_count + sides[2],
+            );
+            faces.push(vec![face[0], ab, ca]);
+            faces.push(vec![ab, face[1], bc]);
+            faces.push(vec![ca, bc, face[2]]);
+            faces.push(vec![ab, bc, ca]);
+        }
+        let half = |i: usize, j: usize| vec![(i, 0.5), (j, 0.5)];
+        let mut children = vec![Vec::new(); 4];
+        children[3] = vec![
+            vec![vec![(0, 1.0)], half(0, 1), half(2, 0)],
+            vec![half(0, 1), vec![(1, 1.0)], half(1, 2)],
+            vec![half(2, 0), half(1, 2), vec![(2, 1.0)]],
+            vec![half(0, 1), half(1, 2), half(2, 0)],
+        ];
+        Self {
+            positions,
+            uvs: split_corner_values(&self.uvs, &children, &self.faces, add_uv, scale_uv),
+            colors: split_corner_values(
+                &self.colors,
+                &children,
+                &self.faces,
+                |a, b| a + b,
+                |a, s| a * s,
+            ),
+            faces,
+            creases: topology.child_creases(vertex_count),
+        }
+    }
+    // Triangulates the polygons as fans. Vertex normals are averaged over
+    // the faces around each vertex up to the sharp edges, so creases stay
+    // visible; vertices are split wherever normals, UVs or colors differ.
+    pub fn to_triangles(&self) -> TriangleMesh {
+        let topology = Topology::new(self);
+        let mut first_corner = Vec::with_capacity(self.faces.len());
+        let mut corner_count = 0;
+        for face in &self.faces {
+            first_corner.push(corner_count);
+            corner_count += face.len();
+        }
+        // Corners around a vertex joined across smooth edges.
+        let mut parent: Vec<usize> = (0..corner_count).collect();
+        fn root(parent: &mut [usize], mut c: usize) -> usize {
+            while parent[c] != c {
+                parent[c] = parent[parent[c]];
+                c = parent[c];
+            }
+            c
+        }
+        for (e, faces) in topology.edge_faces.iter().enumerate() {
+            if faces.len() != 2 || topology.sharpness[e] > 0.0 {
+                continue;
+            }
+            for &v in &topology.edges[e] {
+                let corner = |f: usize| {
+                    first_corner[f] + self.faces[f].iter().position(|&w| w == v).unwrap()
+                };
+                let (a, b) = (
+                    root(&mut parent, corner(faces[0])),
+                    root(&mut parent, corner(faces[1])),
+                );
+                parent[a] = b;
+            }
+        }
+        // Newell's method gives polygon normals weighted by area.
+        let mut normals = vec![Vec3::zero(); corner_count];
+        for (f, face) in self.faces.iter().enumerate() {
+            let mut normal = Vec3::zero();
+            for i in 0..face.len() {
+                let (a, b) = (
+                    &self.positions[face[i]],
+                    &self.positions[face[(i + 1) % face.len()]],
+                );
+                normal += Vec3::cross(a.clone(), b.clone());
+            }
+            for c in first_corner[f]..first_corner[f] + face.len() {
+                let r = root(&mut parent, c);
+                normals[r] += normal.clone();
+            }
+        }
+
+        let mut mesh = TriangleMesh::default();
+        let mut vertices: HashMap<(usize, [u64; 2], [u64; 3]), usize> = HashMap::new();
+        for (f, face) in self.faces.iter().enumerate() {
+            let mut indices = Vec::with_capacity(face.len());
+            for (i, &v) in face.iter().enumerate() {
+                let group = root(&mut parent, first_corner[f] + i);
+                let uv = self.uvs.get(f).map(|uvs| uvs[i]);
+                let color = self.colors.get(f).map(|colors| colors[i].clone());
+                let key = (
+                    group,
+                    uv.map_or([0; 2], |uv| [uv.0.to_bits(), uv.1.to_bits()]),
+                    color.as_ref().map_or([0; 3], position_key),
+                );
+                let index = *vertices.entry(key).or_insert_with(|| {
+                    mesh.positions.push(self.positions[v].clone());
+                    let normal = normals[group].clone();
+                    mesh.normals.push(if normal.squared_length() > 0.0 {
+                        normal.unit()
+                    } else {
+                        normal
+                    });
+                    if let Some(uv) = uv {
+                        mesh.uvs.push(uv);
+                    }
+                    if let Some(color) = color.clone() {
+                        mesh.colors.push(color);
+                    }
+                    mesh.positions.len() - 1
+                });
+                indices.push(index);
+            }
+            for k in 1..indices.len() - 1 {
+                mesh.indices.push([indices[0], indices[k], indices[k + 1]]);
+            }
+        }
+        mesh
+    }
+}
+
+impl TriangleMesh {
+    // The mesh after `levels` rounds of Loop subdivision, with smooth normals
+    // except along edges where the vertex normals were split.
+    pub fn subdivide(&self, levels: usize) -> TriangleMesh {
+        PolygonMesh::from_triangles(self)
+            .subdivide(levels)
+            .to_triangles()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cube() -> PolygonMesh {
+        let positions = (0..8)
+            .map(|i| {
+                let coordinate = |bit: usize| if i & bit != 0 { 1.0 } else { -1.0 };
+                Vec3::new(coordinate(1), coordinate(2), coordinate(4))
+            })
+            .collect();
+        let faces = vec![
+            vec![0, 2, 3, 1],
+            vec![4, 5, 7, 6],
+            vec![0, 1, 5, 4],
+            vec![2, 6, 7, 3],
+            vec![0, 4, 6, 2],
+            vec![1, 3, 7, 5],
+        ];
+        PolygonMesh {
+            positions,
+            uvs: faces
+                .iter()
+                .map(|_| vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)])
+                .collect(),
+            faces,
+            ..PolygonMesh::default()
+        }
+    }
+
+    #[test]
+    fn test_catmull_clark() {
+        let smooth = cube().catmull_clark();
+        assert_eq!((smooth.positions.len(), smooth.faces.len()), (26, 24));
+        let corner = Vec3::ones() * (5.0 / 9.0);
+        assert!((smooth.positions[7].clone() - corner).length() < 1e-12);
+        // Each face keeps its UV square, split in four.
+        assert!(smooth
+            .uvs
+            .iter()
+            .all(|uvs| uvs.iter().all(|uv| (0.0..=1.0).contains(&uv.0))));
+        assert_eq!(smooth.uvs[0][2], (0.5, 0.5));
+
+        // With every edge sharp the cube keeps its shape and flat faces.
+        let mut sharp = cube();
+        let topology = Topology::new(&sharp);
+        sharp.creases = topology.edges.iter().map(|&e| (e, f64::INFINITY)).collect();
+        let mesh = sharp.subdivide(2).to_triangles();
+        for (p, n) in mesh.positions.iter().zip(&mesh.normals) {
+            assert!((p.x.abs().max(p.y.abs()).max(p.z.abs()) - 1.0).abs() < 1e-12);
+            assert!((n.x.abs().max(n.y.abs()).max(n.z.abs()) - 1.0).abs() < 1e-12);
+        }
+
+        // A sharpness of one rounds less than none but more than infinity.
+        let mut creased = cube();
+        creased.creases = vec![([0, 2], 1.0)];
+        let rounded = creased.subdivide(3).positions[0].length();
+        assert!(rounded > cube().subdivide(3).positions[0].length());
+        assert!(rounded < sharp.subdivide(3).positions[0].length());
+        // Fractional sharpness blends continuously towards the next whole one.
+        creased.creases = vec![([0, 2], 0.999)];
+        let nearly = creased.subdivide(3).positions[0].length();
+        creased.creases = vec![([0, 2], 1.0)];
+        assert!((nearly - creased.subdivide(3).positions[0].length()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_loop_subdivision() {
+        // A regular tetrahedron around the origin: the vertex rule moves
+        // each vertex to a quarter of its distance.
+        let mesh = TriangleMesh {
+            positions: vec![
+                Vec3::new(1.0, 1.0, 1.0),
+                Vec3::new(1.0, -1.0, -1.0),
+                Vec3::new(-1.0, 1.0, -1.0),
+                Vec3::new(-1.0, -1.0, 1.0),
+            ],
+            indices: vec![[0, 2, 1], [0, 1, 3], [0, 3, 2], [1, 2, 3]],
+            ..TriangleMesh::default()
+        };
+        let once = PolygonMesh::from_triangles(&mesh).loop_subdivide();
+        assert_eq!((once.positions.len(), once.faces.len()), (10, 16));
+        assert!((once.positions[0].clone() - Vec3::ones() * 0.25).length() < 1e-12);
+
+        // A flat open triangle pair stays flat and keeps the corners that
+        // belong to one triangle only; the vertices split along the seam of
+        // the UVs are merged, and authored normals that differ across an
+        // edge make it a crease.
+        let quad = TriangleMesh {
+            positions: vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(1.0, 1.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 1.0, 0.0),
+            ],
+            normals: vec![Vec3::new(0.0, 0.0, 1.0); 6],
+            uvs: vec![
+                (0.0, 0.0),
+                (1.0, 0.0),
+                (1.0, 1.0),
+                (0.0, 1.0),
+                (0.5, 0.5),
+                (0.5, 0.5),
+            ],
+            colors: vec![],
+            indices: vec![[0, 1, 2], [4, 5, 3]],
+        };
+        let polygons = PolygonMesh::from_triangles(&quad);
+        assert_eq!(polygons.positions.len(), 4);
+        assert!(polygons.creases.is_empty());
+        let fine = quad.subdivide(2);
+        assert_eq!(fine.indices.len(), 32);
+        assert!(fine.positions.iter().all(|p| p.z == 0.0));
+        assert!(fine.positions.contains(&Vec3::new(1.0, 0.0, 0.0)));
+        assert!(fine.uvs.contains(&(1.0, 0.0)) && fine.uvs.contains(&(0.5, 0.5)));
+
+        let mut bent = quad;
+        bent.normals[4] = Vec3::new(0.0, 1.0, 0.0);
+        assert_eq!(PolygonMesh::from_triangles(&bent).creases.len(), 1);
+    }
+}
@@ -1,21 +1,63 @@
+use crate::hit::HitResult;
 pub use crate::perlin::Perlin;
 pub use crate::random::*;
 pub use crate::vec3::Vec3;
 
-use image::{open, RgbImage};
+use image::codecs::hdr::HdrDecoder;
+use image::{open, ColorType, DynamicImage, GenericImageView, ImageResult};
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
 use std::sync::Arc;
 
+// Screen-space derivatives of the texture coordinates at a shading point;
+// all zero when the ray carried no differentials.
+#[derive(Clone, Default)]
+pub struct UvFootprint {
+    pub dudx: f64,
+    pub dvdx: f64,
+    pub dudy: f64,
+    pub dvdy: f64,
+}
+
 pub trait Texture {
     fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3;
+    // Lookup averaged over the pixel footprint. Textures that wrap others
+    // forward it; the rest have nothing to prefilter and point sample.
+    fn filtered_value(&self, u: f64, v: f64, p: Vec3, _footprint: &UvFootprint) -> Vec3 {
+        self.value(u, v, p)
+    }
+    // Lookup at a surface point, for textures that need more of the hit than
+    // its coordinates, such as the triplanar projection.
+    fn hit_value(&self, hit_record: &HitResult) -> Vec3 {
+        self.filtered_value(
+            hit_record.fu,
+            hit_record.fv,
+            hit_record.p.clone(),
+            &hit_record.footprint,
+        )
+    }
 }
 
 pub struct ConstantTexture {
     pub color: Vec3,
 }
 
+// Solid checker: alternates in cubes of side pi / `frequency` in space.
 pub struct CheckerTexture {
     pub odd: Arc<dyn Texture>,
     pub even: Arc<dyn Texture>,
+    pub frequency: f64,
+}
+
+// Checker in texture space with `u_frequency` by `v_frequency` squares over
+// the unit square.
+pub struct UvCheckerTexture {
+    pub odd: Arc<dyn Texture>,
+    pub even: Arc<dyn Texture>,
+    pub u_frequency: f64,
+    pub v_frequency: f64,
 }
 
 impl Texture for ConstantTexture {
@@ -26,13 +68,79 @@ impl Texture for ConstantTexture {
 
 impl Texture for CheckerTexture {
     fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
-        let sines = (10.0 * p.x).sin() * (10.0 * p.y).sin() * (10.0 * p.z).sin();
+        let sines = (self.frequency * p.x).sin()
+            * (self.frequency * p.y).sin()
+            * (self.frequency * p.z).sin();
         if sines < 0.0 {
             self.odd.value(u, v, p)
         } else {
             self.even.value(u, v, p)
         }
     }
+    fn filtered_value(&self, u: f64, v: f64, p: Vec3, footprint: &UvFootprint) -> Vec3 {
+        let sines = (self.frequency * p.x).sin()
+            * (self.frequency * p.y).sin()
+            * (self.frequency * p.z).sin();
+        if sines < 0.0 {
+            self.odd.filtered_value(u, v, p, footprint)
+        } else {
+            self.even.filtered_value(u, v, p, footprint)
+        }
+    }
+}
+
+impl UvCheckerTexture {
+    fn is_odd(&self, u: f64, v: f64) -> bool {
+        let cell = (u * self.u_frequency).floor() + (v * self.v_frequency).floor();
+        (cell as i64).rem_euclid(2) == 1
+    }
+}
+impl Texture for UvCheckerTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        if self.is_odd(u, v) {
+            self.odd.value(u, v, p)
+        } else {
+            self.even.value(u, v, p)
+        }
+    }
+    fn filtered_value(&self, u: f64, v: f64, p: Vec3, footprint: &UvFootprint) -> Vec3 {
+        if self.is_odd(u, v) {
+            self.odd.filtered_value(u, v, p, footprint)
+        } else {
+            self.even.filtered_value(u, v, p, footprint)
+        }
+    }
+}
+
+// Piecewise-linear map from [0, 1] to colors through stops sorted by
+// position; values outside the stops take the nearest end color.
+#[derive(Clone)]
+pub struct ColorRamp {
+    pub stops: Vec<(f64, Vec3)>,
+}
+impl ColorRamp {
+    pub fn new(mut stops: Vec<(f64, Vec3)>) -> Self {
+        stops.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+        Self { stops }
+    }
+    pub fn value(&self, t: f64) -> Vec3 {
+        let (first, last) = match (self.stops.first(), self.stops.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Vec3::zero(),
+        };
+        if t <= first.0 {
+            return first.1.clone();
+        }
+        for pair in self.stops.windows(2) {
+            let ((t0, c0), (t1, c1)) = (&pair[0], &pair[1]);
+            if t <= *t1 {
+                let width = t1 - t0;
+                let f = if width > 0.0 { (t - t0) / width } else { 1.0 };
+                return Vec3::lerp(c1.clone(), c0.clone(), f);
+            }
+        }
+        last.1.clone()
+    }
 }
 
 pub struct NoiseTexture {
@@ -46,46 +154,540 @@ impl Texture for NoiseTexture {
     }
 }
 
-pub struct ImageTexture {
-    pub img: RgbImage,
+#[derive(Clone, Copy, PartialEq)]
+pub enum WrapMode {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+impl WrapMode {
+    fn texel(self, i: i64, n: usize) -> usize {
+        let n = n as i64;
+        let i = match self {
+            WrapMode::Repeat => i.rem_euclid(n),
+            WrapMode::Clamp => i.max(0).min(n - 1),
+            WrapMode::Mirror => {
+                let m = i.rem_euclid(2 * n);
+                if m < n {
+                    m
+                } else {
+                    2 * n - 1 - m
+                }
+            }
+        };
+        i as usize
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+    Bicubic,
+}
+
+pub fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// Catmull-Rom weights of the four texels around a sample at fraction `t`.
+fn cubic_weights(t: f64) -> [f64; 4] {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    [
+        0.5 * (-t3 + 2.0 * t2 - t),
+        0.5 * (3.0 * t3 - 5.0 * t2 + 2.0),
+        0.5 * (-3.0 * t3 + 4.0 * t2 + t),
+        0.5 * (t3 - t2),
+    ]
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum MipFilter {
+    None,
+    Trilinear,
+    Ewa,
+}
+
+// The texels of a row `from` long under each texel of one `to` long
+// stretched over it, weighted by how much of the latter they cover.
+fn box_weights(from: usize, to: usize) -> Vec<Vec<(usize, f64)>> {
+    let ratio = from as f64 / to as f64;
+    (0..to)
+        .map(|i| {
+            let (start, end) = (i as f64 * ratio, (i + 1) as f64 * ratio);
+            (start.floor() as usize..(end.ceil() as usize).min(from))
+                .map(|k| {
+                    let cover = end.min((k + 1) as f64) - start.max(k as f64);
+                    (k, cover / ratio)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// One level of a mip pyramid. Texels are stored linear, row by row from the
+// top of the image; (0, 0) in texture space is the bottom-left corner.
+pub struct MipLevel {
+    pub data: Vec<Vec3>,
     pub width: usize,
     pub height: usize,
 }
+impl MipLevel {
+    pub fn texel(&self, wrap: (WrapMode, WrapMode), i: i64, j: i64) -> Vec3 {
+        let i = wrap.0.texel(i, self.width);
+        let j = wrap.1.texel(j, self.height);
+        self.data[j * self.width + i].clone()
+    }
+    // Box-filters the level to half its size, rounded down. Each new texel
+    // averages the area it covers, so with odd sizes the texels straddling
+    // two blocks are shared between them rather than dropped.
+    fn downsample(&self) -> Self {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let (columns, rows) = (
+            box_weights(self.width, width),
+            box_weights(self.height, height),
+        );
+        let mut data = Vec::with_capacity(width * height);
+        for row in &rows {
+            for column in &columns {
+                let mut color = Vec3::zero();
+                for &(j, weight_y) in row {
+                    for &(i, weight_x) in column {
+                        color += self.data[j * self.width + i].clone() * (weight_x * weight_y);
+                    }
+                }
+                data.push(color);
+            }
+        }
+        Self {
+            data,
+            width,
+            height,
+        }
+    }
+    fn lookup(&self, wrap: (WrapMode, WrapMode), filter: Filter, u: f64, v: f64) -> Vec3 {
+        // Continuous texel coordinates with texel centers at half integers.
+        let x = u * self.width as f64 - 0.5;
+        let y = (1.0 - v) * self.height as f64 - 0.5;
+        match filter {
+            Filter::Nearest => self.texel(wrap, (x + 0.5).floor() as i64, (y + 0.5).floor() as i64),
+            Filter::Bilinear => {
+                let (x0, y0) = (x.floor(), y.floor());
+                let (tx, ty) = (x - x0, y - y0);
+                let (column, row) = (x0 as i64, y0 as i64);
+                self.texel(wrap, column, row) * ((1.0 - tx) * (1.0 - ty))
+                    + self.texel(wrap, column + 1, row) * (tx * (1.0 - ty))
+                    + self.texel(wrap, column, row + 1) * ((1.0 - tx) * ty)
+                    + self.texel(wrap, column + 1, row + 1) * (tx * ty)
+            }
+            Filter::Bicubic => {
+                let (x0, y0) = (x.floor(), y.floor());
+                let wx = cubic_weights(x - x0);
+                let wy = cubic_weights(y - y0);
+                let (column, row) = (x0 as i64, y0 as i64);
+                let mut color = Vec3::zero();
+                for (dj, weight_y) in wy.iter().enumerate() {
+                    for (di, weight_x) in wx.iter().enumerate() {
+                        color += self.texel(wrap, column + di as i64 - 1, row + dj as i64 - 1)
+                            * (weight_x * weight_y);
+                    }
+                }
+                color
+            }
+        }
+    }
+    // Gaussian-weighted average over the ellipse spanned by the UV axes
+    // `d0` and `d1` (Heckbert's EWA).
+    #[allow(clippy::many_single_char_names)]
+    fn ewa(
+        &self,
+        wrap: (WrapMode, WrapMode),
+        u: f64,
+        v: f64,
+        d0: (f64, f64),
+        d1: (f64, f64),
+    ) -> Vec3 {
+        let (w, h) = (self.width as f64, self.height as f64);
+        let (s, t) = (u * w - 0.5, (1.0 - v) * h - 0.5);
+        let (d0, d1) = ((d0.0 * w, -d0.1 * h), (d1.0 * w, -d1.1 * h));
+        let a = d0.1 * d0.1 + d1.1 * d1.1 + 1.0;
+        let b = -2.0 * (d0.0 * d0.1 + d1.0 * d1.1);
+        let c = d0.0 * d0.0 + d1.0 * d1.0 + 1.0;
+        let inv_f = 1.0 / (a * c - b * b * 0.25);
+        let (a, b, c) = (a * inv_f, b * inv_f, c * inv_f);
+        let det = 4.0 * a * c - b * b;
+        let s_extent = 2.0 * (det * c).sqrt() / det;
+        let t_extent = 2.0 * (det * a).sqrt() / det;
+        let (s0, s1) = ((s - s_extent).ceil() as i64, (s + s_extent).floor() as i64);
+        let (t0, t1) = ((t - t_extent).ceil() as i64, (t + t_extent).floor() as i64);
+        let mut sum = Vec3::zero();
+        let mut weight_sum = 0.0;
+        for it in t0..=t1 {
+            let tt = it as f64 - t;
+            for is in s0..=s1 {
+                let ss = is as f64 - s;
+                let r2 = a * ss * ss + b * ss * tt + c * tt * tt;
+                if r2 < 1.0 {
+                    let weight = (-2.0 * r2).exp() - (-2.0f64).exp();
+                    sum += self.texel(wrap, is, it) * weight;
+                    weight_sum += weight;
+                }
+            }
+        }
+        if weight_sum > 0.0 {
+            sum / weight_sum
+        } else {
+            self.lookup(wrap, Filter::Bilinear, u, v)
+        }
+    }
+}
+
+pub struct ImageTexture {
+    pub levels: Vec<MipLevel>,
+    pub wrap: (WrapMode, WrapMode),
+    pub filter: Filter,
+    pub mip_filter: MipFilter,
+}
 impl ImageTexture {
-    pub fn new() -> Self {
-        match open("raytracer/src/earthmap.jpg") {
-            Err(why) => panic!("{:?}", why),
-            Ok(img) => Self {
-                img: img.to_rgb(),
-                width: 1024,
-                height: 512,
+    // Loads PNG, JPEG, HDR and 16-bit images. 8- and 16-bit color maps are
+    // decoded from sRGB when `srgb` is set; HDR images are always linear.
+    pub fn from_path<P: AsRef<Path>>(
+        path: P,
+        srgb: bool,
+        wrap: (WrapMode, WrapMode),
+    ) -> ImageResult<Self> {
+        let path = path.as_ref();
+        let is_hdr = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map_or(false, |ext| ext.eq_ignore_ascii_case("hdr"));
+        if !is_hdr {
+            return Ok(Self::from_image(&open(path)?, srgb, wrap));
+        }
+        let decoder = HdrDecoder::new(BufReader::new(File::open(path)?))?;
+        let meta = decoder.metadata();
+        let data = decoder
+            .read_image_hdr()?
+            .iter()
+            .map(|pixel| Vec3::new(pixel[0] as f64, pixel[1] as f64, pixel[2] as f64))
+            .collect();
+        Ok(Self::from_level(
+            MipLevel {
+                data,
+                width: meta.width as usize,
+                height: meta.height as usize,
+            },
+            wrap,
+        ))
+    }
+    // A decoded 8- or 16-bit image, such as one embedded in a scene file.
+    pub fn from_image(img: &DynamicImage, srgb: bool, wrap: (WrapMode, WrapMode)) -> Self {
+        let decode = |value: f64| if srgb { srgb_to_linear(value) } else { value };
+        let data: Vec<Vec3> = match img.color() {
+            ColorType::L16 | ColorType::La16 | ColorType::Rgb16 | ColorType::Rgba16 => img
+                .to_rgb16()
+                .pixels()
+                .map(|pixel| {
+                    let scale = 1.0 / 65535.0;
+                    Vec3::new(
+                        decode(pixel[0] as f64 * scale),
+                        decode(pixel[1] as f64 * scale),
+                        decode(pixel[2] as f64 * scale),
+                    )
+                })
+                .collect(),
+            _ => img
+                .to_rgb8()
+                .pixels()
+                .map(|pixel| {
+                    let scale = 1.0 / 255.0;
+                    Vec3::new(
+                        decode(pixel[0] as f64 * scale),
+                        decode(pixel[1] as f64 * scale),
+                        decode(pixel[2] as f64 * scale),
+                    )
+                })
+                .collect(),
+        };
+        let (width, height) = img.dimensions();
+        Self::from_level(
+            MipLevel {
+                data,
+                width: width as usize,
+                height: height as usize,
             },
+            wrap,
+        )
+    }
+    // Builds the mip pyramid above `base`, looked up with `wrap` along u
+    // and v.
+    pub fn from_level(base: MipLevel, wrap: (WrapMode, WrapMode)) -> Self {
+        let mut levels = vec![base];
+        loop {
+            let last = &levels[levels.len() - 1];
+            if last.width == 1 && last.height == 1 {
+                break;
+            }
+            let next = last.downsample();
+            levels.push(next);
+        }
+        Self {
+            levels,
+            wrap,
+            filter: Filter::Bilinear,
+            mip_filter: MipFilter::Trilinear,
         }
     }
-}
-impl Default for ImageTexture {
-    fn default() -> Self {
-        Self::new()
+    // Continuous pyramid level whose texels are `width` wide in UV units.
+    fn level_of(&self, width: f64) -> f64 {
+        let resolution = self.levels[0].width.max(self.levels[0].height) as f64;
+        (width * resolution)
+            .max(1e-8)
+            .log2()
+            .max(0.0)
+            .min((self.levels.len() - 1) as f64)
+    }
+    fn trilinear(&self, u: f64, v: f64, width: f64) -> Vec3 {
+        let level = self.level_of(width);
+        let lower = level.floor() as usize;
+        let color = self.levels[lower].lookup(self.wrap, self.filter, u, v);
+        if lower + 1 >= self.levels.len() || level == lower as f64 {
+            return color;
+        }
+        let upper = self.levels[lower + 1].lookup(self.wrap, self.filter, u, v);
+        Vec3::lerp(upper, color, level - lower as f64)
+    }
+    fn ewa(&self, u: f64, v: f64, footprint: &UvFootprint) -> Vec3 {
+        let mut major = (footprint.dudx, footprint.dvdx);
+        let mut minor = (footprint.dudy, footprint.dvdy);
+        let length = |d: (f64, f64)| (d.0 * d.0 + d.1 * d.1).sqrt();
+        if length(major) < length(minor) {
+            std::mem::swap(&mut major, &mut minor);
+        }
+        let (major_length, mut minor_length) = (length(major), length(minor));
+        if minor_length == 0.0 {
+            return self.trilinear(u, v, major_length);
+        }
+        // Bound the eccentricity so that the ellipse covers a bounded number
+        // of texels on the chosen level.
+        let max_anisotropy = 8.0;
+        if minor_length * max_anisotropy < major_length {
+            let scale = major_length / (minor_length * max_anisotropy);
+            minor = (minor.0 * scale, minor.1 * scale);
+            minor_length *= scale;
+        }
+        let level = self.level_of(minor_length);
+        let lower = level.floor() as usize;
+        let color = self.levels[lower].ewa(self.wrap, u, v, major, minor);
+        if lower + 1 >= self.levels.len() || level == lower as f64 {
+            return color;
+        }
+        let upper = self.levels[lower + 1].ewa(self.wrap, u, v, major, minor);
+        Vec3::lerp(upper, color, level - lower as f64)
     }
 }
 impl Texture for ImageTexture {
     fn value(&self, u: f64, v: f64, _p: Vec3) -> Vec3 {
-        //Vec3::ones() * 0.5 * (1.0 + self.noise.noise(p * self.scale))
+        self.levels[0].lookup(self.wrap, self.filter, u, v)
+    }
+    fn filtered_value(&self, u: f64, v: f64, _p: Vec3, footprint: &UvFootprint) -> Vec3 {
+        let width = footprint
+            .dudx
+            .abs()
+            .max(footprint.dvdx.abs())
+            .max(footprint.dudy.abs())
+            .max(footprint.dvdy.abs());
+        match self.mip_filter {
+            MipFilter::None => self.levels[0].lookup(self.wrap, self.filter, u, v),
+            MipFilter::Trilinear => self.trilinear(u, v, width),
+            MipFilter::Ewa => self.ewa(u, v, footprint),
+        }
+    }
+}
 
-        let fu = u.max(0.0).min(1.0);
-        let fv = 1.0 - v.max(0.0).min(1.0);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        let mut ii = (fu * self.width as f64).floor() as usize;
-        let mut jj = (fv * self.height as f64).floor() as usize;
-        ii = ii.min(self.width - 1);
-        jj = jj.min(self.height - 1);
-        let color_scale = 1.0 / 255.0;
+    #[test]
+    fn test_wrap_modes() {
+        let indices =
+            |wrap: WrapMode| -> Vec<usize> { (-4..8).map(|i| wrap.texel(i, 4)).collect() };
+        assert_eq!(
+            indices(WrapMode::Repeat),
+            vec![0, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2, 3]
+        );
+        assert_eq!(
+            indices(WrapMode::Clamp),
+            vec![0, 0, 0, 0, 0, 1, 2, 3, 3, 3, 3, 3]
+        );
+        assert_eq!(
+            indices(WrapMode::Mirror),
+            vec![3, 2, 1, 0, 0, 1, 2, 3, 3, 2, 1, 0]
+        );
+    }
 
-        let pixel = self.img.get_pixel(ii as u32, jj as u32);
-        Vec3::new(
-            color_scale * pixel[0] as f64,
-            color_scale * pixel[1] as f64,
-            color_scale * pixel[2] as f64,
-        )
+    #[test]
+    fn test_filters() {
+        // A horizontal ramp 0, 1, 2, 3 across four texels.
+        let mut texture = ImageTexture::from_level(
+            MipLevel {
+                data: (0..4).map(|i| Vec3::ones() * i as f64).collect(),
+                width: 4,
+                height: 1,
+            },
+            (WrapMode::Clamp, WrapMode::Clamp),
+        );
+        let at = |texture: &ImageTexture, u: f64| texture.value(u, 0.5, Vec3::zero()).x;
+        texture.filter = Filter::Nearest;
+        assert_eq!(at(&texture, 0.3), 1.0);
+        // Texel centers sit at (i + 0.5) / 4.
+        texture.filter = Filter::Bilinear;
+        assert!((at(&texture, 0.375) - 1.0).abs() < 1e-12);
+        assert!((at(&texture, 0.4375) - 1.25).abs() < 1e-12);
+        assert!((at(&texture, 0.0) - 0.0).abs() < 1e-12);
+        // Catmull-Rom passes through the texels and reproduces a ramp away
+        // from the clamped ends.
+        texture.filter = Filter::Bicubic;
+        assert!((at(&texture, 0.375) - 1.0).abs() < 1e-12);
+        assert!((at(&texture, 0.4375) - 1.25).abs() < 1e-12);
+        assert!((at(&texture, 0.5) - 1.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_srgb_decode() {
+        assert_eq!(srgb_to_linear(0.0), 0.0);
+        assert!((srgb_to_linear(1.0) - 1.0).abs() < 1e-12);
+        assert!((srgb_to_linear(0.04) - 0.04 / 12.92).abs() < 1e-12);
+        assert!((srgb_to_linear(0.5) - 0.214_041).abs() < 1e-6);
+
+        let img =
+            DynamicImage::ImageRgb8(image::RgbImage::from_raw(1, 1, vec![255, 128, 0]).unwrap());
+        let linear = ImageTexture::from_image(&img, false, (WrapMode::Repeat, WrapMode::Repeat))
+            .levels[0]
+            .data[0]
+            .clone();
+        assert!((linear - Vec3::new(1.0, 128.0 / 255.0, 0.0)).length() < 1e-12);
+        let decoded = ImageTexture::from_image(&img, true, (WrapMode::Repeat, WrapMode::Repeat))
+            .levels[0]
+            .data[0]
+            .clone();
+        assert!((decoded - Vec3::new(1.0, srgb_to_linear(128.0 / 255.0), 0.0)).length() < 1e-12);
+        // 16-bit images keep their precision.
+        let img = DynamicImage::ImageRgb16(
+            image::ImageBuffer::from_raw(1, 1, vec![65535u16, 32768, 1]).unwrap(),
+        );
+        let decoded = ImageTexture::from_image(&img, true, (WrapMode::Repeat, WrapMode::Repeat))
+            .levels[0]
+            .data[0]
+            .clone();
+        assert!((decoded.y - srgb_to_linear(32768.0 / 65535.0)).abs() < 1e-12);
+        assert!((decoded.z - 1.0 / 65535.0 / 12.92).abs() < 1e-15);
+    }
+
+    #[test]
+    fn test_load_hdr() {
+        use image::codecs::hdr::HdrEncoder;
+        use image::Rgb;
+        // Values above one survive, and rows are stored from the top.
+        let pixels = [
+            Rgb([4.0f32, 0.5, 0.25]),
+            Rgb([0.125, 2.0, 8.0]),
+            Rgb([1.0, 1.0, 1.0]),
+            Rgb([16.0, 16.0, 16.0]),
+        ];
+        let path = std::env::temp_dir().join("test_load_hdr.hdr");
+        HdrEncoder::new(File::create(&path).unwrap())
+            .encode(&pixels, 2, 2)
+            .unwrap();
+        let mut texture =
+            ImageTexture::from_path(&path, true, (WrapMode::Repeat, WrapMode::Repeat)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let level = &texture.levels[0];
+        assert_eq!((level.width, level.height), (2, 2));
+        for (texel, pixel) in level.data.iter().zip(pixels.iter()) {
+            for channel in 0..3 {
+                let expected = pixel[channel] as f64;
+                assert!((texel[channel as i32] - expected).abs() < 0.02 * expected);
+            }
+        }
+        texture.filter = Filter::Nearest;
+        let top_left = texture.value(0.25, 0.75, Vec3::zero());
+        assert!((top_left.x - 4.0).abs() < 0.08);
+    }
+
+    #[test]
+    fn test_downsample_odd() {
+        // Five texels shrink to two, each covering two and a half.
+        let level = MipLevel {
+            data: [0.0, 0.0, 0.0, 0.0, 10.0, 5.0, 5.0, 5.0, 5.0, 5.0]
+                .iter()
+                .map(|&x| Vec3::ones() * x)
+                .collect(),
+            width: 5,
+            height: 2,
+        };
+        let half = level.downsample();
+        assert_eq!((half.width, half.height), (2, 1));
+        assert!((half.data[0].x - 2.5).abs() < 1e-12);
+        assert!((half.data[1].x - 4.5).abs() < 1e-12);
+        // Three rows shrink to one and keep the mean.
+        let level = MipLevel {
+            data: (0..3).map(|i| Vec3::ones() * i as f64).collect(),
+            width: 1,
+            height: 3,
+        };
+        assert!((level.downsample().data[0].x - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_mip_pyramid() {
+        let data = (0..32)
+            .map(|i| Vec3::ones() * ((i + i / 8) % 2) as f64)
+            .collect();
+        let texture = ImageTexture::from_level(
+            MipLevel {
+                data,
+                width: 8,
+                height: 4,
+            },
+            (WrapMode::Repeat, WrapMode::Repeat),
+        );
+        assert_eq!(texture.levels.len(), 4);
+        assert!((texture.levels[3].data[0].x - 0.5).abs() < 1e-9);
+        let wide = UvFootprint {
+            dudx: 1.0,
+            dvdx: 0.0,
+            dudy: 0.0,
+            dvdy: 1.0,
+        };
+        let filtered = texture.filtered_value(0.3, 0.6, Vec3::zero(), &wide);
+        assert!((filtered.x - 0.5).abs() < 1e-9);
+
+        // A quarter of the way from a black texel to the grey level above.
+        let mut texture = ImageTexture::from_level(
+            MipLevel {
+                data: vec![Vec3::zero(), Vec3::ones()],
+                width: 2,
+                height: 1,
+            },
+            (WrapMode::Clamp, WrapMode::Clamp),
+        );
+        texture.filter = Filter::Nearest;
+        let width = 2.0f64.powf(0.25) / 2.0;
+        let footprint = UvFootprint {
+            dudx: width,
+            dvdx: 0.0,
+            dudy: 0.0,
+            dvdy: 0.0,
+        };
+        let blended = texture.filtered_value(0.25, 0.5, Vec3::zero(), &footprint);
+        assert!((blended.x - 0.125).abs() < 1e-9);
     }
 }
@@ -3,6 +3,11 @@ use std::f64::consts::PI;
 
 use std::ops::{Add, AddAssign, Div, Index, Mul, MulAssign, Neg, Sub, SubAssign};
 
+// Scalar `Vec3::lerp`; `t` weighs `one`.
+pub fn lerp(one: f64, other: f64, t: f64) -> f64 {
+    one * t + other * (1.0 - t)
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Vec3 {
     pub x: f64,
@@ -277,14 +282,21 @@ impl Vec3 {
         }
     }
     pub fn random_cosine_direction() -> Self {
-        let r1 = random_double();
-        let r2 = random_double();
+        Self::cosine_direction(random_double(), random_double())
+    }
+    pub fn cosine_direction(r1: f64, r2: f64) -> Self {
         let z = (1.0 - r2).sqrt();
         let phi = 2.0 * PI * r1;
         let x = phi.cos() * r2.sqrt();
         let y = phi.sin() * r2.sqrt();
         Self::new(x, y, z)
     }
+    pub fn sphere_direction(r1: f64, r2: f64) -> Self {
+        let z = 1.0 - 2.0 * r2;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * r1;
+        Self::new(r * phi.cos(), r * phi.sin(), z)
+    }
     pub fn reflect(&self, normal: Self) -> Self {
         self.clone() - normal.clone() * (self.clone() * normal) * 2.0
     }
@@ -294,6 +306,13 @@ impl Vec3 {
         let r_out_parallel = -normal * (1.0 - r_out_perp.squared_length()).sqrt();
         r_out_perp + r_out_parallel
     }
+    pub fn abs(&self) -> Self {
+        Self {
+            x: self.x.abs(),
+            y: self.y.abs(),
+            z: self.z.abs(),
+        }
+    }
     pub fn min(&self, other: Self) -> Self {
         Self {
             x: self.x.min(other.x),
@@ -0,0 +1,345 @@
+use crate::hit::*;
+use crate::mapping::Transform3;
+use crate::medium::*;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Result, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+const MAGIC: &[u8; 4] = b"VOXG";
+
+// Cells per axis of the majorant grid, at most.
+const MAJORANT_RESOLUTION: usize = 16;
+
+fn read_u32(reader: &mut impl Read) -> Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+// Coarse grid of density bounds over the unit cube, so that tracking takes
+// short steps only where the medium is dense.
+pub struct MajorantGrid {
+    pub resolution: [usize; 3],
+    pub max: Vec<f64>,
+}
+
+// Dense grid of densities at the voxel centres of the unit cube, placed in
+// the world by `grid_to_world`. Lookups interpolate trilinearly and are zero
+// outside the grid.
+pub struct VoxelGrid {
+    pub size: [usize; 3],
+    pub data: Vec<f32>,
+    pub scale: f64,
+    world_to_grid: Transform3,
+    bbox: AABB,
+    majorant: MajorantGrid,
+}
+
+impl VoxelGrid {
+    pub fn new(size: [usize; 3], data: Vec<f32>, scale: f64, grid_to_world: Transform3) -> Self {
+        assert_eq!(data.len(), size[0] * size[1] * size[2]);
+        let mut min = Vec3::ones() * f64::INFINITY;
+        let mut max = -Vec3::ones() * f64::INFINITY;
+        for i in 0..8 {
+            let corner = Vec3::new((i & 1) as f64, ((i >> 1) & 1) as f64, ((i >> 2) & 1) as f64);
+            let p = grid_to_world.apply(corner);
+            min = min.min(p.clone());
+            max = max.max(p);
+        }
+        let mut grid = Self {
+            size,
+            data,
+            scale,
+            world_to_grid: grid_to_world.inverse(),
+            bbox: AABB { min, max },
+            majorant: MajorantGrid {
+                resolution: [0; 3],
+                max: vec![],
+            },
+        };
+        grid.majorant = grid.build_majorant();
+        grid
+    }
+    // Raw grid file: the bytes "VOXG", the little-endian u32 sizes along x, y
+    // and z, then one little-endian f32 density per voxel, x varying fastest.
+    pub fn from_path<P: AsRef<Path>>(
+        path: P,
+        scale: f64,
+        grid_to_world: Transform3,
+    ) -> Result<Self> {
+        let file = File::open(path)?;
+        let length = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "not a voxel grid file"));
+        }
+        let size = [
+            read_u32(&mut reader)? as usize,
+            read_u32(&mut reader)? as usize,
+            read_u32(&mut reader)? as usize,
+        ];
+        let data = Self::read_data(&mut reader, size, length.saturating_sub(16))?;
+        Ok(Self::new(size, data, scale, grid_to_world))
+    }
+    // Headerless dump of little-endian f32 densities, x varying fastest, as
+    // written by most simulators.
+    pub fn from_raw<P: AsRef<Path>>(
+        path: P,
+        size: [usize; 3],
+        scale: f64,
+        grid_to_world: Transform3,
+    ) -> Result<Self> {
+        let file = File::open(path)?;
+        let length = file.metadata()?.len();
+        let data = Self::read_data(&mut BufReader::new(file), size, length)?;
+        Ok(Self::new(size, data, scale, grid_to_world))
+    }
+    // Reads the densities of a `size` grid from the `available` bytes left in
+    // the file, checking the size before allocating for it.
+    fn read_data(reader: &mut impl Read, size: [usize; 3], available: u64) -> Result<Vec<f32>> {
+        let length = size[0]
+            .checked_mul(size[1])
+            .and_then(|count| count.checked_mul(size[2]))
+            .and_then(|count| count.checked_mul(4))
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "voxel grid too large"))?;
+        if length == 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "empty voxel grid"));
+        }
+        if length as u64 > available {
+            return Err(Error::new(ErrorKind::InvalidData, "truncated voxel grid"));
+        }
+        let mut bytes = vec![0u8; length];
+        reader.read_exact(&mut bytes)?;
+        Ok(bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect())
+    }
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        for &n in &self.size {
+            writer.write_all(&(n as u32).to_le_bytes())?;
+        }
+        for d in &self.data {
+            writer.write_all(&d.to_le_bytes())?;
+        }
+        writer.flush()
+    }
+    // World-space bounds, for the medium's boundary or a BVH.
+    pub fn bounding_box(&self) -> AABB {
+        self.bbox.clone()
+    }
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f64 {
+        self.data[(z * self.size[1] + y) * self.size[0] + x] as f64
+    }
+    // Trilinear lookup at a point of the unit cube.
+    fn lookup(&self, q: &Vec3) -> f64 {
+        let mut index = [(0, 0); 3];
+        let mut frac = [0.0; 3];
+        for axis in 0..3 {
+            let coord = q[axis as i32];
+            if !(0.0..=1.0).contains(&coord) {
+                return 0.0;
+            }
+            let count = self.size[axis];
+            let grid = coord * count as f64 - 0.5;
+            let cell = grid.floor();
+            frac[axis] = grid - cell;
+            let clamp = |cell: f64| cell.max(0.0).min((count - 1) as f64) as usize;
+            index[axis] = (clamp(cell), clamp(cell + 1.0));
+        }
+        let ((x0, x1), (y0, y1), (z0, z1)) = (index[0], index[1], index[2]);
+        let plane = |z: usize| {
+            lerp(
+                lerp(self.voxel(x1, y1, z), self.voxel(x0, y1, z), frac[0]),
+                lerp(self.voxel(x1, y0, z), self.voxel(x0, y0, z), frac[0]),
+                frac[1],
+            )
+        };
+        lerp(plane(z1), plane(z0), frac[2])
+    }
+    // Each majorant cell bounds every voxel whose interpolation reaches it.
+    fn build_majorant(&self) -> MajorantGrid {
+        let resolution = [
+            self.size[0].min(MAJORANT_RESOLUTION),
+            self.size[1].min(MAJORANT_RESOLUTION),
+            self.size[2].min(MAJORANT_RESOLUTION),
+        ];
+        let range = |axis: usize, cell: usize| {
+            let n = self.size[axis] as f64;
+            let m = resolution[axis] as f64;
+            let lo = (cell as f64 * n / m - 0.5).floor().max(0.0) as usize;
+            let hi = ((cell + 1) as f64 * n / m - 0.5).ceil().min(n - 1.0) as usize;
+            lo..=hi
+        };
+        let mut max = Vec::with_capacity(resolution[0] * resolution[1] * resolution[2]);
+        for k in 0..resolution[2] {
+            for j in 0..resolution[1] {
+                for i in 0..resolution[0] {
+                    let mut bound = 0.0f64;
+                    for z in range(2, k) {
+                        for y in range(1, j) {
+                            for x in range(0, i) {
+                                bound = bound.max(self.voxel(x, y, z));
+                            }
+                        }
+                    }
+                    max.push(bound);
+                }
+            }
+        }
+        MajorantGrid { resolution, max }
+    }
+}
+
+impl DensityField for VoxelGrid {
+    fn density(&self, p: &Vec3) -> f64 {
+        self.lookup(&self.world_to_grid.apply(p.clone())).max(0.0) * self.scale
+    }
+    // Walks the majorant grid with a 3D DDA. The grid transform is affine, so
+    // ray parameters are the same in grid space.
+    fn majorants(&self, ra: &Ray, t0: f64, t1: f64) -> Vec<(f64, f64, f64)> {
+        let origin = self.world_to_grid.apply(ra.origin.clone());
+        let direction =
+            self.world_to_grid.apply(ra.direction.clone()) - self.world_to_grid.offset.clone();
+        let (mut t_enter, mut t_exit) = (t0, t1);
+        for axis in 0..3 {
+            let (o, d) = (origin[axis], direction[axis]);
+            if d == 0.0 {
+                if !(0.0..=1.0).contains(&o) {
+                    return vec![];
+                }
+                continue;
+            }
+            let (a, b) = (-o / d, (1.0 - o) / d);
+            t_enter = t_enter.max(a.min(b));
+            t_exit = t_exit.min(a.max(b));
+        }
+        if t_enter >= t_exit {
+            return vec![];
+        }
+        let res = &self.majorant.resolution;
+        let start = origin.clone() + direction.clone() * t_enter;
+        let mut cell = [0i64; 3];
+        let mut step = [0i64; 3];
+        let mut next = [f64::INFINITY; 3];
+        let mut delta = [f64::INFINITY; 3];
+        for axis in 0..3 {
+            let m = res[axis] as f64;
+            let d = direction[axis as i32];
+            cell[axis] = ((start[axis as i32] * m) as i64)
+                .max(0)
+                .min(res[axis] as i64 - 1);
+            if d > 0.0 {
+                step[axis] = 1;
+                delta[axis] = 1.0 / (m * d);
+                next[axis] = t_enter + ((cell[axis] + 1) as f64 / m - start[axis as i32]) / d;
+            } else if d < 0.0 {
+                step[axis] = -1;
+                delta[axis] = -1.0 / (m * d);
+                next[axis] = t_enter + (cell[axis] as f64 / m - start[axis as i32]) / d;
+            }
+        }
+        let mut pieces = vec![];
+        let mut t = t_enter;
+        loop {
+            let axis = if next[0] < next[1] {
+                if next[0] < next[2] {
+                    0
+                } else {
+                    2
+                }
+            } else if next[1] < next[2] {
+                1
+            } else {
+                2
+            };
+            let end = next[axis].min(t_exit);
+            let index = (cell[2] as usize * res[1] + cell[1] as usize) * res[0] + cell[0] as usize;
+            pieces.push((t, end, self.majorant.max[index] * self.scale));
+            if end >= t_exit {
+                break;
+            }
+            t = end;
+            cell[axis] += step[axis];
+            if cell[axis] < 0 || cell[axis] >= res[axis] as i64 {
+                break;
+            }
+            next[axis] += delta[axis];
+        }
+        pieces
+    }
+}
+
+impl HeterogeneousMedium {
+    // Medium filling the bounding box of `grid`.
+    pub fn from_grid(
+        grid: Arc<VoxelGrid>,
+        sigma_a: Vec3,
+        sigma_s: Vec3,
+        phase_function: Arc<dyn Material>,
+    ) -> Self {
+        let bbox = grid.bounding_box();
+        Self {
+            boundary: Arc::new(Cube::new(bbox.min, bbox.max, phase_function.clone())),
+            density: grid,
+            sigma_a,
+            sigma_s,
+            phase_function,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_voxel_grid() {
+        let size = [5, 4, 3];
+        let data = (0..60).map(|i| (i % 7) as f32).collect();
+        let transform = Transform3::scale(Vec3::new(2.0, 1.0, 3.0))
+            .then(&Transform3::translate(Vec3::new(-1.0, 0.0, 1.0)));
+        let grid = VoxelGrid::new(size, data, 0.5, transform.clone());
+        let bbox = grid.bounding_box();
+        assert!((bbox.max - Vec3::new(1.0, 1.0, 4.0)).length() < 1e-9);
+
+        let path = std::env::temp_dir().join("test_voxel_grid.vox");
+        grid.write(&path).unwrap();
+        let loaded = VoxelGrid::from_path(&path, 0.5, transform).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.data, grid.data);
+
+        let ra = Ray {
+            origin: Vec3::new(-2.0, 0.3, 2.2),
+            direction: Vec3::new(1.0, 0.05, 0.1),
+        };
+        let pieces = loaded.majorants(&ra, 0.0, f64::INFINITY);
+        assert!((pieces[0].0 - 1.0).abs() < 1e-9);
+        for (t0, t1, bound) in pieces {
+            for i in 0..=10 {
+                let t = t0 + (t1 - t0) * i as f64 / 10.0;
+                assert!(loaded.density(&ra.at(t)) <= bound + 1e-9);
+            }
+        }
+    }
+    #[test]
+    fn test_bad_voxel_header() {
+        let path = std::env::temp_dir().join("test_bad_voxel_header.vox");
+        for &size in &[[u32::MAX; 3], [1000, 1000, 1000]] {
+            let mut bytes = MAGIC.to_vec();
+            for n in &size {
+                bytes.extend_from_slice(&n.to_le_bytes());
+            }
+            bytes.extend_from_slice(&[0u8; 64]);
+            std::fs::write(&path, &bytes).unwrap();
+            let result = VoxelGrid::from_path(&path, 1.0, Transform3::identity());
+            assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidData);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
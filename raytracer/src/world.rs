@@ -2,6 +2,8 @@ use std::sync::Arc;
 use std::vec::Vec;
 
 pub use crate::hit::*;
+pub use crate::medium::*;
+pub use crate::pdf::*;
 pub use crate::random::*;
 use std::cmp::Ordering;
 
@@ -81,11 +83,80 @@ impl Hitable for BVHNode {
 pub struct World {
     //pub hitlist: Vec<Arc<dyn Hitable>>,
     pub root: Arc<dyn Hitable>,
+    pub lights: Vec<Arc<dyn Hitable>>,
+    pub media: Vec<Arc<HeterogeneousMedium>>,
 }
 
 impl World {
     pub fn hit(&self, ra: &Ray, t_min: f64, t_max: f64) -> Option<HitResult> {
-        self.root.hit(&ra, t_min, t_max)
+        // Past a cut-out hit the ray continues from a point spawned clear of
+        // the surface; `t` is still measured along `ra`.
+        let along = |p: &Vec3| {
+            (p.clone() - ra.origin.clone()) * ra.direction.clone() / ra.direction.squared_length()
+        };
+        let mut ray = ra.clone();
+        let (mut t_min, mut t_end) = (t_min, t_max);
+        loop {
+            let mut hit_result = self.root.hit(&ray, t_min, t_end)?;
+            if !hit_result.mat_ptr.is_cutout(&hit_result) {
+                if ray != *ra {
+                    hit_result.t = along(&hit_result.p);
+                }
+                return Some(hit_result);
+            }
+            ray = hit_result.spawn_ray(ra.direction.clone());
+            t_min = 0.0;
+            t_end = t_max - along(&ray.origin);
+        }
+    }
+    // Density of `sample_light` towards `direction`, averaged over all lights.
+    pub fn light_pdf(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        if self.lights.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = self
+            .lights
+            .iter()
+            .map(|light| {
+                HitablePdf {
+                    origin: origin.clone(),
+                    ptr: light.clone(),
+                }
+                .value(direction.clone())
+            })
+            .sum();
+        sum / self.lights.len() as f64
+    }
+    // Picks a light uniformly and returns the vector from `origin` to a point on it.
+    pub fn sample_light(&self, origin: &Vec3) -> Option<Vec3> {
+        if self.lights.is_empty() {
+            return None;
+        }
+        let index = random_int_range(0, self.lights.len() as i32) as usize;
+        let light = HitablePdf {
+            origin: origin.clone(),
+            ptr: self.lights[index.min(self.lights.len() - 1)].clone(),
+        };
+        Some(light.generate())
+    }
+    // Tracks the media along `ra` in front of the surface at `t_max`, all at
+    // once so that overlapping media share one majorant. Returns the
+    // scattering point, if any, and the throughput weight of the segment.
+    pub fn sample_media(&self, ra: &Ray, t_max: f64) -> (Option<HitResult>, Vec3) {
+        let media: Vec<&HeterogeneousMedium> = self.media.iter().map(|medium| &**medium).collect();
+        let (scatter, weight) = track_media(&media, ra, t_max);
+        let hit_result = scatter.map(|(t, index)| {
+            let phase_function = self.media[index].phase_function.clone();
+            HitResult::new(ra, t, -ra.direction.unit(), 0.0, 0.0, phase_function)
+        });
+        (hit_result, weight)
+    }
+    pub fn transmittance(&self, ra: &Ray, t_max: f64) -> Vec3 {
+        self.media
+            .iter()
+            .fold(Vec3::ones(), |transmittance, medium| {
+                Vec3::elemul(transmittance, medium.transmittance(ra, t_max))
+            })
     }
     /*pub fn new(mut hitlist: Vec<Arc<dyn Hitable>>) -> Self {
         let length = hitlist.len();
@@ -93,3 +164,110 @@ impl World {
         Self { hitlist, root }
     }*/
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hit_through_cutouts() {
+        let white = white_lambertian();
+        let cutout: Arc<dyn Material> = Arc::new(AlphaMask {
+            material: white.clone(),
+            opacity: Arc::new(ConstantTexture {
+                color: Vec3::zero(),
+            }),
+            threshold: 0.5,
+        });
+        let square = |k: f64, mat_ptr: Arc<dyn Material>| -> Arc<dyn Hitable> {
+            Arc::new(XyRect {
+                x0: -1.0,
+                x1: 1.0,
+                y0: -1.0,
+                y1: 1.0,
+                k,
+                mat_ptr,
+            })
+        };
+        let mut list = vec![
+            square(0.0, cutout.clone()),
+            square(-1.0, cutout),
+            square(-2.0, white),
+        ];
+        let world = World {
+            root: Arc::new(BVHNode::new(&mut list, 0, 3)),
+            lights: vec![],
+            media: vec![],
+        };
+        let ray = Ray {
+            origin: Vec3::new(0.3, 0.2, 4.0),
+            direction: Vec3::new(0.0, 0.0, -2.0),
+        };
+        // Both cut-out squares are stepped past, and `t` stays along `ray`.
+        let hit = world.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        assert!((hit.t - 3.0).abs() < 1e-9);
+        assert!((hit.p - Vec3::new(0.3, 0.2, -2.0)).length() < 1e-9);
+        let hit = world.hit(&ray, 0.0, 3.1).unwrap();
+        assert!((hit.t - 3.0).abs() < 1e-9);
+        assert!(world.hit(&ray, 0.0, 2.9).is_none());
+    }
+
+    #[test]
+    fn test_overlapping_media() {
+        let medium = |sigma_a: f64, sigma_s: f64| {
+            Arc::new(HeterogeneousMedium {
+                boundary: Arc::new(Sphere {
+                    center: Vec3::zero(),
+                    radius: 1.0,
+                    mat_ptr: white_lambertian(),
+                }),
+                density: Arc::new(TextureDensity {
+                    texture: Arc::new(ConstantTexture {
+                        color: Vec3::ones(),
+                    }),
+                    scale: 1.0,
+                    max: 1.0,
+                }),
+                sigma_a: Vec3::ones() * sigma_a,
+                sigma_s: Vec3::ones() * sigma_s,
+                phase_function: Arc::new(Isotropic {
+                    albedo: white_texture(),
+                }),
+            })
+        };
+        let world = |media: Vec<Arc<HeterogeneousMedium>>| {
+            let mut list: Vec<Arc<dyn Hitable>> = vec![Arc::new(Sphere {
+                center: Vec3::new(0.0, 0.0, 10.0),
+                radius: 1.0,
+                mat_ptr: white_lambertian(),
+            })];
+            World {
+                root: Arc::new(BVHNode::new(&mut list, 0, 1)),
+                lights: vec![],
+                media,
+            }
+        };
+        // An absorber and a scatterer sharing a sphere act as one medium
+        // with both coefficients.
+        let ray = Ray {
+            origin: Vec3::new(0.0, 0.0, -3.0),
+            direction: Vec3::new(0.0, 0.0, 1.0),
+        };
+        let estimate = |world: World| {
+            let samples = 20000;
+            let (mut scattered, mut depth) = (0.0, 0.0);
+            for _ in 0..samples {
+                if let (Some(hit), weight) = world.sample_media(&ray, f64::INFINITY) {
+                    scattered += weight.x;
+                    depth += weight.x * hit.t;
+                }
+            }
+            (scattered / samples as f64, depth / samples as f64)
+        };
+        let (scattered, depth) = estimate(world(vec![medium(0.5, 0.0), medium(0.0, 0.5)]));
+        let (expected_scattered, expected_depth) = estimate(world(vec![medium(0.5, 0.5)]));
+        assert!((scattered - expected_scattered).abs() < 0.03);
+        assert!((depth - expected_depth).abs() < 0.1);
+        assert!((expected_scattered - 0.5 * (1.0 - (-2.0f64).exp())).abs() < 0.03);
+    }
+}